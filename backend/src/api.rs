@@ -1,34 +1,141 @@
-use crate::Backend;
+use crate::{Backend, DOWNLOADS_FLUSH_INTERVAL};
 use anyhow::Result;
-use axum::{serve, Router};
+use axum::{
+    http::{header, HeaderMap},
+    middleware, serve, Router,
+};
+use buildsrs_database::{ReadHandle, WriteHandle};
 use std::net::SocketAddr;
-use tokio::net::TcpListener;
+use tokio::{
+    net::TcpListener,
+    time::{interval, timeout},
+};
 use tower_http::trace::TraceLayer;
+use tracing::*;
 
+mod admin;
+mod badge;
 mod crates;
+mod error;
 #[cfg(feature = "frontend")]
 mod frontend;
 mod jobs;
+mod metrics;
+mod openapi;
 
-fn routes() -> Router<Backend> {
-    let api = Router::new().merge(crates::routes()).merge(jobs::routes());
-    let router = Router::new().nest("/api/v1", api);
+use error::ApiError;
+pub use jobs::WebSocketError;
+use jobs::JOBS_EXPIRE_INTERVAL;
+use openapi::{ApiRouter, PREFIX};
+
+/// Routes of the REST API, including its OpenAPI document.
+pub(crate) fn api() -> ApiRouter {
+    ApiRouter::new("")
+        .merge(admin::routes())
+        .merge(crates::routes())
+        .merge(jobs::routes())
+        .with_document()
+}
+
+fn routes(backend: &Backend) -> Router<Backend> {
+    let router = Router::new()
+        .nest(PREFIX, api().into_router())
+        .merge(badge::routes())
+        .merge(metrics::routes());
     #[cfg(feature = "frontend")]
     let router = router.nest("/", frontend::routes());
-    router.layer(TraceLayer::new_for_http())
+    router
+        .layer(middleware::from_fn_with_state(
+            backend.clone(),
+            metrics::track_requests,
+        ))
+        .layer(TraceLayer::new_for_http())
+}
+
+/// Extract the bearer token from the authorization header.
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
 }
 
 impl Backend {
     /// Get router for REST API.
     pub fn router(&self) -> Router {
-        routes().with_state(self.clone())
+        routes(self).with_state(self.clone())
+    }
+
+    /// Get a database read handle, giving up if none becomes available in time.
+    async fn read(&self) -> Result<Box<dyn ReadHandle>, ApiError> {
+        timeout(self.database_timeout(), self.database().read())
+            .await
+            .map_err(|_| ApiError::Unavailable)?
+            .map_err(ApiError::Connection)
+    }
+
+    /// Get a database write handle, giving up if none becomes available in time.
+    async fn write(&self) -> Result<Box<dyn WriteHandle>, ApiError> {
+        timeout(self.database_timeout(), self.database().write())
+            .await
+            .map_err(|_| ApiError::Unavailable)?
+            .map_err(ApiError::Connection)
+    }
+
+    /// Write buffered download counts to the database.
+    ///
+    /// If they cannot be written, the counts are kept and written by the next flush.
+    pub async fn flush_downloads(&self) -> Result<()> {
+        let downloads = self.downloads().take();
+        if downloads.is_empty() {
+            return Ok(());
+        }
+        let result = async {
+            let writer = self.write().await?;
+            writer.artifact_downloads_add(&downloads).await?;
+            writer.commit().await?;
+            Ok::<_, ApiError>(())
+        }
+        .await;
+        if result.is_err() {
+            self.downloads().restore(downloads);
+        }
+        Ok(result?)
     }
 
     /// Launch REST API, listening on the given address.
+    ///
+    /// Download counts are flushed to the database and jobs whose lease has expired are returned
+    /// to the queue periodically while listening.
     pub async fn listen(&self, addr: SocketAddr) -> Result<()> {
         let router = self.router();
         let listener = TcpListener::bind(&addr).await?;
-        serve(listener, router).await?;
+        let backend = self.clone();
+        let flush = tokio::spawn(async move {
+            let mut interval = interval(DOWNLOADS_FLUSH_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(error) = backend.flush_downloads().await {
+                    warn!("Cannot write download counts: {error}");
+                }
+            }
+        });
+        let backend = self.clone();
+        let expire = tokio::spawn(async move {
+            let mut interval = interval(JOBS_EXPIRE_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(error) = backend.expire_jobs().await {
+                    warn!("Cannot expire jobs: {error}");
+                }
+            }
+        });
+        let result = serve(listener, router).await;
+        flush.abort();
+        expire.abort();
+        self.flush_downloads().await?;
+        result?;
         Ok(())
     }
 }
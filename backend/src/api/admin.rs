@@ -0,0 +1,336 @@
+//! Admin API
+//!
+//! Manages builders, triples and crates, like the `buildsrs-database` command-line tool does. All
+//! routes require an admin token, which is passed as a bearer token. Admin tokens are created
+//! using `buildsrs-database token add`.
+
+use super::{
+    bearer_token,
+    openapi::{ApiRouter, Operation},
+    ApiError,
+};
+use crate::Backend;
+use axum::{
+    async_trait,
+    extract::{
+        rejection::{JsonRejection, PathRejection},
+        FromRequestParts, Path, State,
+    },
+    http::{request::Parts, StatusCode},
+    Json,
+};
+use buildsrs_common::api::*;
+use buildsrs_database::ReadHandle;
+use buildsrs_protocol::ssh_key::{HashAlg, PublicKey};
+use tracing::*;
+use uuid::Uuid;
+
+/// Admin which a request was authenticated as, identified by the name of its token.
+#[derive(Clone, Debug)]
+pub struct Admin(String);
+
+#[async_trait]
+impl FromRequestParts<Backend> for Admin {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, backend: &Backend) -> Result<Self, ApiError> {
+        let token = bearer_token(&parts.headers)
+            .ok_or_else(|| ApiError::Unauthorized("missing admin token".into()))?;
+        let database = backend.read().await?;
+        let name = database
+            .admin_token_check(token)
+            .await?
+            .ok_or_else(|| ApiError::Unauthorized("invalid admin token".into()))?;
+        Ok(Self(name))
+    }
+}
+
+/// Look up a builder, including its triples.
+async fn builder_response<H: ReadHandle + ?Sized>(
+    database: &H,
+    uuid: Uuid,
+) -> Result<BuilderResponse, ApiError> {
+    let builder = database.builder_get(uuid).await?;
+    let triples = database.builder_triples(uuid).await?;
+    Ok(BuilderResponse {
+        uuid,
+        public_key: builder.public_key.to_openssh().unwrap_or_default(),
+        fingerprint: builder.public_key.fingerprint(HashAlg::Sha256).to_string(),
+        comment: builder.comment,
+        enabled: builder.enabled,
+        triples,
+    })
+}
+
+/// Look up a triple.
+async fn triple_response<H: ReadHandle + ?Sized>(
+    database: &H,
+    name: &str,
+) -> Result<TripleResponse, ApiError> {
+    let info = database.triple_info(name).await?;
+    Ok(TripleResponse {
+        name: info.name,
+        enabled: info.enabled,
+    })
+}
+
+async fn builder_list(
+    _: Admin,
+    State(backend): State<Backend>,
+) -> Result<Json<BuildersResponse>, ApiError> {
+    let database = backend.read().await?;
+    let mut builders = vec![];
+    for uuid in database.builder_list().await? {
+        builders.push(builder_response(&*database, uuid).await?);
+    }
+    Ok(Json(BuildersResponse { builders }))
+}
+
+async fn builder_add(
+    Admin(admin): Admin,
+    State(backend): State<Backend>,
+    request: Result<Json<BuilderAddRequest>, JsonRejection>,
+) -> Result<(StatusCode, Json<BuilderResponse>), ApiError> {
+    let Json(request) = request?;
+    let key = PublicKey::from_openssh(&request.public_key)
+        .map_err(|error| ApiError::BadRequest(format!("invalid public key: {error}")))?;
+    let database = backend.write().await?;
+    let fingerprint = key.fingerprint(HashAlg::Sha512).to_string();
+    match database.builder_lookup(&fingerprint).await {
+        Ok(_) => return Err(ApiError::Conflict("builder already exists".into())),
+        Err(error) if error.is_not_found() => {}
+        Err(error) => return Err(error.into()),
+    }
+    let uuid = Uuid::new_v4();
+    database.builder_add(uuid, &key, &request.comment).await?;
+    let builder = builder_response(&*database, uuid).await?;
+    database.commit().await?;
+    info!("Admin {admin} added builder {uuid}");
+    Ok((StatusCode::CREATED, Json(builder)))
+}
+
+async fn builder_get(
+    _: Admin,
+    State(backend): State<Backend>,
+    path: Result<Path<Uuid>, PathRejection>,
+) -> Result<Json<BuilderResponse>, ApiError> {
+    let Path(uuid) = path?;
+    let database = backend.read().await?;
+    Ok(Json(builder_response(&*database, uuid).await?))
+}
+
+async fn builder_edit(
+    Admin(admin): Admin,
+    State(backend): State<Backend>,
+    path: Result<Path<Uuid>, PathRejection>,
+    request: Result<Json<BuilderEditRequest>, JsonRejection>,
+) -> Result<Json<BuilderResponse>, ApiError> {
+    let Path(uuid) = path?;
+    let Json(request) = request?;
+    let database = backend.write().await?;
+    database.builder_get(uuid).await?;
+    if let Some(enabled) = request.enabled {
+        database.builder_set_enabled(uuid, enabled).await?;
+    }
+    if let Some(comment) = &request.comment {
+        database.builder_set_comment(uuid, comment).await?;
+    }
+    let builder = builder_response(&*database, uuid).await?;
+    database.commit().await?;
+    info!("Admin {admin} edited builder {uuid}");
+    Ok(Json(builder))
+}
+
+async fn builder_triple_add(
+    Admin(admin): Admin,
+    State(backend): State<Backend>,
+    path: Result<Path<(Uuid, String)>, PathRejection>,
+) -> Result<StatusCode, ApiError> {
+    let Path((uuid, triple)) = path?;
+    let database = backend.write().await?;
+    database.builder_get(uuid).await?;
+    database.triple_info(&triple).await?;
+    database.builder_triple_add(uuid, &triple).await?;
+    database.commit().await?;
+    info!("Admin {admin} allowed builder {uuid} to build {triple}");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn builder_triple_remove(
+    Admin(admin): Admin,
+    State(backend): State<Backend>,
+    path: Result<Path<(Uuid, String)>, PathRejection>,
+) -> Result<StatusCode, ApiError> {
+    let Path((uuid, triple)) = path?;
+    let database = backend.write().await?;
+    database.builder_get(uuid).await?;
+    database.triple_info(&triple).await?;
+    database.builder_triple_remove(uuid, &triple).await?;
+    database.commit().await?;
+    info!("Admin {admin} disallowed builder {uuid} to build {triple}");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn triple_list(
+    _: Admin,
+    State(backend): State<Backend>,
+) -> Result<Json<TriplesResponse>, ApiError> {
+    let database = backend.read().await?;
+    let mut triples = vec![];
+    for name in database.triple_list().await? {
+        triples.push(triple_response(&*database, &name).await?);
+    }
+    Ok(Json(TriplesResponse { triples }))
+}
+
+async fn triple_add(
+    Admin(admin): Admin,
+    State(backend): State<Backend>,
+    request: Result<Json<TripleAddRequest>, JsonRejection>,
+) -> Result<(StatusCode, Json<TripleResponse>), ApiError> {
+    let Json(request) = request?;
+    let database = backend.write().await?;
+    database.triple_add(&request.name).await?;
+    let triple = triple_response(&*database, &request.name).await?;
+    database.commit().await?;
+    info!("Admin {admin} added triple {}", request.name);
+    Ok((StatusCode::CREATED, Json(triple)))
+}
+
+async fn triple_edit(
+    Admin(admin): Admin,
+    State(backend): State<Backend>,
+    Path(name): Path<String>,
+    request: Result<Json<EnabledRequest>, JsonRejection>,
+) -> Result<Json<TripleResponse>, ApiError> {
+    let Json(request) = request?;
+    let database = backend.write().await?;
+    database.triple_info(&name).await?;
+    database.triple_enabled(&name, request.enabled).await?;
+    let triple = triple_response(&*database, &name).await?;
+    database.commit().await?;
+    info!(
+        "Admin {admin} set triple {name} enabled {}",
+        request.enabled
+    );
+    Ok(Json(triple))
+}
+
+async fn crate_edit(
+    Admin(admin): Admin,
+    State(backend): State<Backend>,
+    Path(name): Path<String>,
+    request: Result<Json<EnabledRequest>, JsonRejection>,
+) -> Result<StatusCode, ApiError> {
+    let Json(request) = request?;
+    let database = backend.write().await?;
+    database.crate_info(&name).await?;
+    database.crate_set_enabled(&name, request.enabled).await?;
+    database.commit().await?;
+    info!("Admin {admin} set crate {name} enabled {}", request.enabled);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Describe an operation of the admin API, which requires an admin token.
+fn admin(operation: Operation) -> Operation {
+    operation.security("adminToken")
+}
+
+pub fn routes() -> ApiRouter {
+    ApiRouter::new("admin")
+        .route(
+            admin(Operation::get(
+                "/admin/builders",
+                "builder_list",
+                "List builders",
+            ))
+            .json::<BuildersResponse>(StatusCode::OK, "Builders"),
+            builder_list,
+        )
+        .route(
+            admin(Operation::post(
+                "/admin/builders",
+                "builder_add",
+                "Register a builder",
+            ))
+            .json_body::<BuilderAddRequest>()
+            .json::<BuilderResponse>(StatusCode::CREATED, "Registered builder"),
+            builder_add,
+        )
+        .route(
+            admin(Operation::get(
+                "/admin/builders/:uuid",
+                "builder_get",
+                "Get a builder",
+            ))
+            .json::<BuilderResponse>(StatusCode::OK, "Builder"),
+            builder_get,
+        )
+        .route(
+            admin(Operation::patch(
+                "/admin/builders/:uuid",
+                "builder_edit",
+                "Set the comment or enabled state of a builder",
+            ))
+            .json_body::<BuilderEditRequest>()
+            .json::<BuilderResponse>(StatusCode::OK, "Edited builder"),
+            builder_edit,
+        )
+        .route(
+            admin(Operation::put(
+                "/admin/builders/:uuid/triples/:triple",
+                "builder_triple_add",
+                "Allow a builder to build a triple",
+            ))
+            .response(StatusCode::NO_CONTENT, "Triple allowed"),
+            builder_triple_add,
+        )
+        .route(
+            admin(Operation::delete(
+                "/admin/builders/:uuid/triples/:triple",
+                "builder_triple_remove",
+                "Disallow a builder to build a triple",
+            ))
+            .response(StatusCode::NO_CONTENT, "Triple disallowed"),
+            builder_triple_remove,
+        )
+        .route(
+            admin(Operation::get(
+                "/admin/triples",
+                "triple_list",
+                "List triples",
+            ))
+            .json::<TriplesResponse>(StatusCode::OK, "Triples"),
+            triple_list,
+        )
+        .route(
+            admin(Operation::post(
+                "/admin/triples",
+                "triple_add",
+                "Add a triple",
+            ))
+            .json_body::<TripleAddRequest>()
+            .json::<TripleResponse>(StatusCode::CREATED, "Added triple"),
+            triple_add,
+        )
+        .route(
+            admin(Operation::patch(
+                "/admin/triples/:triple",
+                "triple_edit",
+                "Enable or disable a triple",
+            ))
+            .json_body::<EnabledRequest>()
+            .json::<TripleResponse>(StatusCode::OK, "Edited triple"),
+            triple_edit,
+        )
+        .route(
+            admin(Operation::patch(
+                "/admin/crates/:crate",
+                "crate_edit",
+                "Enable or disable a crate",
+            ))
+            .json_body::<EnabledRequest>()
+            .response(StatusCode::NO_CONTENT, "Crate edited"),
+            crate_edit,
+        )
+}
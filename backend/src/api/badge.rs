@@ -0,0 +1,75 @@
+//! Build status badges
+//!
+//! Serves badges at `/badge/:crate.svg`, `/badge/:crate/:version.svg` and
+//! `/badge/:crate/:version/:triple.svg`. The version accepts the same selectors as artifact
+//! downloads, and defaults to the latest version. Without a triple, the status covers the tasks of
+//! all triples.
+
+use super::ApiError;
+use crate::{
+    badge::{self, BuildStatus},
+    caching::{self, CACHE_BADGE},
+    resolve::VersionSelector,
+    Backend,
+};
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use sha2::{Digest, Sha256};
+
+/// Content type of badges.
+const CONTENT_TYPE: &str = "image/svg+xml";
+
+/// Serve the badge of the latest build status of a crate.
+async fn crate_badge(
+    State(backend): State<Backend>,
+    Path(path): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let not_found = || ApiError::NotFound("badge".into());
+    let path = path.strip_suffix(".svg").ok_or_else(not_found)?;
+    let (krate, version, triple) = match path.split('/').collect::<Vec<_>>()[..] {
+        [krate] => (krate, "latest", None),
+        [krate, version] => (krate, version, None),
+        [krate, version, triple] => (krate, version, Some(triple)),
+        _ => return Err(not_found()),
+    };
+    let selector: VersionSelector = version
+        .parse()
+        .map_err(|error| ApiError::BadRequest(format!("invalid version {version:?}: {error}")))?;
+
+    let database = backend.read().await?;
+    database.crate_info(krate).await?;
+    let versions = database.crate_version_list(krate).await?;
+    let tasks = match selector.resolve(&versions) {
+        Some(info) => database.crate_version_tasks(krate, &info.version).await?,
+        None => vec![],
+    };
+    let tasks: Vec<_> = tasks
+        .into_iter()
+        .filter(|task| triple.map_or(true, |triple| task.triple == triple))
+        .collect();
+
+    let svg = badge::render(BuildStatus::of(&tasks));
+    let etag = caching::etag(&hex::encode(Sha256::digest(&svg)));
+    if caching::none_match(&headers, &etag) {
+        return Ok(caching::not_modified(&etag, CACHE_BADGE));
+    }
+    Ok((
+        [
+            (header::CONTENT_TYPE, CONTENT_TYPE.to_owned()),
+            (header::CACHE_CONTROL, CACHE_BADGE.to_owned()),
+            (header::ETAG, etag),
+        ],
+        svg,
+    )
+        .into_response())
+}
+
+pub fn routes() -> Router<Backend> {
+    Router::new().route("/badge/*path", get(crate_badge))
+}
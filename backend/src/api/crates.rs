@@ -1,27 +1,111 @@
-use crate::Backend;
+use super::{
+    openapi::{ApiRouter, Operation},
+    ApiError,
+};
+use crate::{
+    caching::{self, CACHE_ALIAS, CACHE_VERSION},
+    resolve::VersionSelector,
+    Backend,
+};
 use axum::{
-    extract::{Path, Query, State},
-    routing::get,
-    Json, Router,
+    body::Body,
+    extract::{rejection::QueryRejection, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use buildsrs_common::{
+    api::*,
+    entities::{CrateSearchCursor, Task},
 };
-use buildsrs_common::api::*;
+use buildsrs_storage::{ArtifactData, ArtifactId, ArtifactKind, StorageError};
+
+/// Number of crates returned by a search if no limit is given.
+const SEARCH_LIMIT_DEFAULT: usize = 20;
+
+/// Maximum number of crates returned by a search.
+const SEARCH_LIMIT_MAX: usize = 100;
+
+/// Encode a search cursor, which clients treat as opaque.
+fn cursor_encode(cursor: &CrateSearchCursor) -> String {
+    hex::encode(format!("{}:{}", cursor.rank, cursor.name))
+}
+
+/// Decode a search cursor, returning `None` if it is invalid.
+fn cursor_decode(cursor: &str) -> Option<CrateSearchCursor> {
+    let decoded = String::from_utf8(hex::decode(cursor).ok()?).ok()?;
+    let (rank, name) = decoded.split_once(':')?;
+    Some(CrateSearchCursor {
+        rank: rank.parse().ok()?,
+        name: name.into(),
+    })
+}
 
 async fn crate_list(
     State(backend): State<Backend>,
-    Query(query): Query<CratesQuery>,
-) -> Result<Json<CratesResponse>, ()> {
-    let database = backend.database().read().await.unwrap();
-    let crates = database.crate_list(&query.name).await.unwrap();
-    Ok(Json(CratesResponse { crates }))
+    query: Result<Query<CratesQuery>, QueryRejection>,
+) -> Result<Json<CratesResponse>, ApiError> {
+    let Query(query) = query?;
+    let limit = query.limit.unwrap_or(SEARCH_LIMIT_DEFAULT);
+    if !(1..=SEARCH_LIMIT_MAX).contains(&limit) {
+        return Err(ApiError::BadRequest(format!(
+            "limit must be between 1 and {SEARCH_LIMIT_MAX}"
+        )));
+    }
+
+    let after = match &query.cursor {
+        Some(cursor) => Some(
+            cursor_decode(cursor)
+                .ok_or_else(|| ApiError::BadRequest(format!("invalid cursor {cursor:?}")))?,
+        ),
+        None => None,
+    };
+
+    // one more crate than requested is fetched to tell whether there is a next page
+    let database = backend.read().await?;
+    let total = database.crate_search_count(&query.name).await?;
+    let mut entries = database
+        .crate_search(
+            &query.name,
+            i64::try_from(limit + 1).unwrap_or(i64::MAX),
+            after.as_ref(),
+        )
+        .await?;
+
+    let next_cursor = if entries.len() > limit {
+        entries.truncate(limit);
+        entries.last().map(|entry| {
+            cursor_encode(&CrateSearchCursor {
+                rank: entry.rank,
+                name: entry.name.clone(),
+            })
+        })
+    } else {
+        None
+    };
+    let total = u64::try_from(total).unwrap_or_default();
+    let crates = entries
+        .into_iter()
+        .map(|entry| CrateSummary {
+            name: entry.name,
+            latest_version: entry.latest_version,
+            has_artifacts: entry.has_artifacts,
+        })
+        .collect();
+    Ok(Json(CratesResponse {
+        crates,
+        total,
+        next_cursor,
+    }))
 }
 
 async fn crate_info(
     State(backend): State<Backend>,
     Path(name): Path<String>,
-) -> Result<Json<CrateResponse>, ()> {
-    let database = backend.database().read().await.unwrap();
-    let info = database.crate_info(&name).await.unwrap();
-    let versions = database.crate_versions(&name).await.unwrap();
+) -> Result<Json<CrateResponse>, ApiError> {
+    let database = backend.read().await?;
+    let info = database.crate_info(&name).await?;
+    let versions = database.crate_versions(&name).await?;
     Ok(Json(CrateResponse {
         name: info.name,
         versions: versions.into_iter().collect(),
@@ -31,32 +115,220 @@ async fn crate_info(
 async fn crate_version(
     State(backend): State<Backend>,
     Path((name, version)): Path<(String, String)>,
-) -> Result<Json<CrateVersionResponse>, ()> {
-    let database = backend.database().read().await.unwrap();
-    let info = database.crate_version_info(&name, &version).await.unwrap();
+) -> Result<Json<CrateVersionResponse>, ApiError> {
+    let database = backend.read().await?;
+    let info = database.crate_version_info(&name, &version).await?;
+    let artifacts = database
+        .crate_version_artifacts(&name, &version)
+        .await?
+        .into_iter()
+        .map(|artifact| ArtifactResponse {
+            url: format!(
+                "/api/v1/crates/{}/{}/{}/{}",
+                info.name, info.version, artifact.triple, artifact.kind
+            ),
+            name: info.name.clone(),
+            version: info.version.clone(),
+            triple: artifact.triple,
+            kind: artifact.kind,
+            size: artifact.size,
+            hash: artifact.hash,
+            builder: artifact.builder,
+            built: artifact.built,
+        })
+        .collect();
     Ok(Json(CrateVersionResponse {
         name: info.name,
         version: info.version,
         checksum: info.checksum,
         yanked: info.yanked,
-        artifacts: Default::default(),
+        artifacts,
+    }))
+}
+
+async fn crate_downloads(
+    State(backend): State<Backend>,
+    Path(name): Path<String>,
+) -> Result<Json<CrateDownloadsResponse>, ApiError> {
+    let database = backend.read().await?;
+    let info = database.crate_info(&name).await?;
+    let mut series: Vec<DownloadsSeries> = vec![];
+    // counts are ordered by version and triple, so each series is contiguous
+    for count in database.crate_downloads(&name).await? {
+        let day = DownloadsDay {
+            date: count.date,
+            downloads: count.downloads,
+        };
+        match series.last_mut() {
+            Some(last) if last.version == count.version && last.triple == count.triple => {
+                last.total += count.downloads;
+                last.days.push(day);
+            }
+            _ => series.push(DownloadsSeries {
+                version: count.version,
+                triple: count.triple,
+                total: count.downloads,
+                days: vec![day],
+            }),
+        }
+    }
+    Ok(Json(CrateDownloadsResponse {
+        name: info.name,
+        series,
     }))
 }
 
 async fn crate_artifact(
-    State(_backend): State<Backend>,
-    Path((_name, _version, _target, _artifact)): Path<(String, String, String, String)>,
-) -> Result<Json<()>, ()> {
-    todo!()
+    State(backend): State<Backend>,
+    Path((krate, version, triple, artifact)): Path<(String, String, String, String)>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let kind: ArtifactKind = artifact
+        .parse()
+        .map_err(|_| ApiError::BadRequest(format!("invalid artifact kind {artifact:?}")))?;
+    let selector: VersionSelector = version
+        .parse()
+        .map_err(|error| ApiError::BadRequest(format!("invalid version {version:?}: {error}")))?;
+    let cache_control = match &selector {
+        VersionSelector::Exact(_) => CACHE_VERSION,
+        VersionSelector::Requirement(_) => CACHE_ALIAS,
+    };
+
+    // serve the newest matching version which has been built for this triple, the hash is
+    // recorded when the artifact is stored
+    let database = backend.read().await?;
+    let versions = database
+        .crate_artifact_versions(&krate, &triple, &artifact)
+        .await?;
+    drop(database);
+    let resolved = selector
+        .resolve(&versions)
+        .ok_or_else(|| ApiError::NotFound("artifact".into()))?;
+    let id = ArtifactId {
+        task: Task {
+            krate,
+            version: resolved.version.clone(),
+            triple,
+            kind,
+        },
+    };
+    let etag = caching::etag(&resolved.hash);
+    if caching::none_match(&headers, &etag) {
+        return Ok(caching::not_modified(&etag, cache_control));
+    }
+
+    let result = match caching::range(&headers, &etag) {
+        Some(range) => backend.storage().artifact_get_range(&id, range).await,
+        None => backend.storage().artifact_get(&id).await,
+    };
+    match result {
+        Ok(data) => {
+            let response = artifact_response(&id, data, etag, cache_control);
+            // resumed and revalidated downloads are not counted again
+            if matches!(response.status(), StatusCode::OK | StatusCode::FOUND) {
+                backend.downloads().record(&id);
+            }
+            Ok(response)
+        }
+        Err(StorageError::NotFound(_)) => Err(ApiError::NotFound("artifact".into())),
+        Err(StorageError::RangeNotSatisfiable(size)) => Ok((
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            [(header::CONTENT_RANGE, format!("bytes */{size}"))],
+        )
+            .into_response()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Respond with artifact data, tagged with the entity tag derived from its hash.
+fn artifact_response(
+    artifact: &ArtifactId,
+    data: ArtifactData,
+    etag: String,
+    cache_control: &'static str,
+) -> Response {
+    let (bytes, part) = match data {
+        ArtifactData::Data { bytes } => (bytes, None),
+        ArtifactData::Partial { bytes, start, size } => (bytes, Some((start, size))),
+        ArtifactData::Redirect { url, .. } => {
+            return (StatusCode::FOUND, [(header::LOCATION, url.to_string())]).into_response()
+        }
+    };
+    let status = match part {
+        Some(_) => StatusCode::PARTIAL_CONTENT,
+        None => StatusCode::OK,
+    };
+    let content_range = part.map(|(start, size)| {
+        let end = start + bytes.len() as u64 - 1;
+        [(header::CONTENT_RANGE, format!("bytes {start}-{end}/{size}"))]
+    });
+    (
+        status,
+        [
+            (
+                header::CONTENT_TYPE,
+                artifact.task.kind.content_type().to_string(),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", artifact.file_name()),
+            ),
+            (header::ACCEPT_RANGES, "bytes".into()),
+            (header::CACHE_CONTROL, cache_control.into()),
+            (header::ETAG, etag),
+        ],
+        content_range,
+        Body::from(bytes),
+    )
+        .into_response()
 }
 
-pub fn routes() -> Router<Backend> {
-    Router::new()
-        .route("/crates", get(crate_list))
-        .route("/crates/:crate", get(crate_info))
-        .route("/crates/:crate/:version", get(crate_version))
+pub fn routes() -> ApiRouter {
+    ApiRouter::new("crates")
+        .route(
+            Operation::get("/crates", "crate_list", "Search crates")
+                .query::<CratesQuery>()
+                .json::<CratesResponse>(StatusCode::OK, "Matching crates"),
+            crate_list,
+        )
+        .route(
+            Operation::get(
+                "/crates/:crate",
+                "crate_info",
+                "Get a crate and its versions",
+            )
+            .json::<CrateResponse>(StatusCode::OK, "Crate"),
+            crate_info,
+        )
+        .route(
+            Operation::get(
+                "/crates/:crate/downloads",
+                "crate_downloads",
+                "Get the daily downloads of a crate by version and triple",
+            )
+            .json::<CrateDownloadsResponse>(StatusCode::OK, "Crate downloads"),
+            crate_downloads,
+        )
+        .route(
+            Operation::get(
+                "/crates/:crate/:version",
+                "crate_version",
+                "Get a crate version and its artifacts",
+            )
+            .json::<CrateVersionResponse>(StatusCode::OK, "Crate version"),
+            crate_version,
+        )
         .route(
-            "/crates/:crate/:version/:target/:artifact",
-            get(crate_artifact),
+            Operation::get(
+                "/crates/:crate/:version/:target/:artifact",
+                "crate_artifact",
+                "Download an artifact",
+            )
+            .binary(StatusCode::OK, "Artifact")
+            .binary(StatusCode::PARTIAL_CONTENT, "Part of the artifact")
+            .response(StatusCode::FOUND, "Redirect to the artifact")
+            .response(StatusCode::NOT_MODIFIED, "Artifact not modified")
+            .response(StatusCode::RANGE_NOT_SATISFIABLE, "Range not satisfiable"),
+            crate_artifact,
         )
 }
@@ -0,0 +1,143 @@
+use crate::{ingest::IngestError, tokens::TokenError, uploads::UploadError};
+use axum::{
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use buildsrs_common::api::{ErrorKind, ErrorResponse};
+use buildsrs_database::{BoxError, Error as DatabaseError, SqlState};
+use buildsrs_storage::StorageError;
+use tracing::*;
+
+/// Error returned by a REST API handler.
+///
+/// Every error is rendered as an [`ErrorResponse`] with a matching status code. Details of
+/// internal errors are logged rather than returned.
+#[derive(thiserror::Error, Debug)]
+pub enum ApiError {
+    /// Requested resource does not exist
+    #[error("{0} not found")]
+    NotFound(String),
+    /// Request is invalid
+    #[error("{0}")]
+    BadRequest(String),
+    /// Request is not authorized
+    #[error("{0}")]
+    Unauthorized(String),
+    /// Request conflicts with an existing resource
+    #[error("{0}")]
+    Conflict(String),
+    /// No database connection became available in time
+    #[error("database unavailable")]
+    Unavailable,
+    /// Error connecting to database
+    #[error(transparent)]
+    Connection(BoxError),
+    /// Error in database query
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+    /// Error in metadata
+    #[error(transparent)]
+    Metadata(#[from] BoxError),
+    /// Error in storage
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        Self::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<UploadError> for ApiError {
+    fn from(error: UploadError) -> Self {
+        Self::BadRequest(error.to_string())
+    }
+}
+
+impl From<IngestError> for ApiError {
+    fn from(error: IngestError) -> Self {
+        Self::BadRequest(error.to_string())
+    }
+}
+
+impl From<TokenError> for ApiError {
+    fn from(error: TokenError) -> Self {
+        Self::Unauthorized(error.to_string())
+    }
+}
+
+impl ApiError {
+    /// Kind of this error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::NotFound(_) | Self::Storage(StorageError::NotFound(_)) => ErrorKind::NotFound,
+            Self::Database(error) => database_error_kind(error),
+            Self::Metadata(error) => match error.downcast_ref::<DatabaseError>() {
+                Some(error) => database_error_kind(error),
+                None => ErrorKind::Internal,
+            },
+            Self::BadRequest(_) => ErrorKind::BadRequest,
+            Self::Unauthorized(_) => ErrorKind::Unauthorized,
+            Self::Conflict(_) => ErrorKind::Conflict,
+            Self::Unavailable => ErrorKind::Unavailable,
+            Self::Connection(_) | Self::Storage(_) => ErrorKind::Internal,
+        }
+    }
+}
+
+/// Kind of a database error, as seen by clients.
+///
+/// Requests which violate a constraint of the database are the fault of the client.
+fn database_error_kind(error: &DatabaseError) -> ErrorKind {
+    if error.is_not_found() {
+        return ErrorKind::NotFound;
+    }
+    match error.code() {
+        Some(&SqlState::UNIQUE_VIOLATION) => ErrorKind::Conflict,
+        Some(&SqlState::FOREIGN_KEY_VIOLATION) => ErrorKind::BadRequest,
+        _ => ErrorKind::Internal,
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let kind = self.kind();
+        let (status, message) = match (kind, &self) {
+            (ErrorKind::NotFound, Self::NotFound(_)) => (StatusCode::NOT_FOUND, self.to_string()),
+            (ErrorKind::NotFound, _) => (StatusCode::NOT_FOUND, "not found".into()),
+            (ErrorKind::BadRequest, Self::Database(_) | Self::Metadata(_)) => (
+                StatusCode::BAD_REQUEST,
+                "references a resource which does not exist".into(),
+            ),
+            (ErrorKind::BadRequest, _) => (StatusCode::BAD_REQUEST, self.to_string()),
+            (ErrorKind::Conflict, Self::Conflict(_)) => (StatusCode::CONFLICT, self.to_string()),
+            (ErrorKind::Conflict, _) => (StatusCode::CONFLICT, "already exists".into()),
+            (ErrorKind::Unauthorized, _) => (StatusCode::UNAUTHORIZED, self.to_string()),
+            (ErrorKind::Unavailable, _) => (StatusCode::SERVICE_UNAVAILABLE, self.to_string()),
+            (ErrorKind::Internal, _) => {
+                error!("Error handling request: {self}");
+                (StatusCode::INTERNAL_SERVER_ERROR, "internal error".into())
+            }
+        };
+        let body = ErrorResponse {
+            error: kind,
+            message,
+        };
+        (status, Json(body)).into_response()
+    }
+}
@@ -1,7 +1,10 @@
 use crate::Backend;
 use axum::{
     extract::{Path, State},
-    http::header::{self, HeaderMap},
+    http::{
+        header::{self, HeaderMap, HeaderValue},
+        StatusCode,
+    },
     routing::get,
     Router,
 };
@@ -10,28 +13,89 @@ use std::path::PathBuf;
 
 const DEFAULT_FILE: &str = "index.html";
 
+/// Paths which are never served by the frontend.
+const API_PREFIX: &str = "api/";
+
+/// Precompressed variants of files, by content encoding and extension, in order of preference.
+const ENCODINGS: [(&str, &str); 2] = [("br", "br"), ("gzip", "gz")];
+
+/// Determine if the client accepts a content encoding.
+fn accepts(headers: &HeaderMap, encoding: &str) -> bool {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|entry| {
+            let mut parts = entry.split(';').map(str::trim);
+            let name = parts.next().unwrap_or_default();
+            let disabled = parts.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .is_some_and(|q| q == 0.0)
+            });
+            (name == encoding || name == "*") && !disabled
+        })
+}
+
+/// Serve a file of the frontend.
+///
+/// Unknown paths without an extension are served the default file, so that the frontend can route
+/// them on the client side, while missing files are not found. If the client accepts it, a
+/// precompressed variant of the file is served instead.
 async fn frontend_file(
     State(state): State<Backend>,
-    Path(mut path): Path<Vec<String>>,
-) -> Result<(HeaderMap, Bytes), ()> {
-    if path.is_empty() {
-        path.push(DEFAULT_FILE.into());
+    path: Option<Path<String>>,
+    request: HeaderMap,
+) -> Result<(HeaderMap, Bytes), StatusCode> {
+    let path = path.map(|Path(path)| path).unwrap_or_default();
+    if path.starts_with(API_PREFIX) {
+        return Err(StatusCode::NOT_FOUND);
     }
 
-    tracing::debug!("{path:?}");
-
-    let path = PathBuf::from(path.join("/"));
-    let Some(bytes) = state.frontend().get(&path) else {
-        return Err(());
+    let frontend = state.frontend();
+    let read = |path: PathBuf| async move {
+        frontend.read(&path).await.map_err(|error| {
+            tracing::error!("Reading frontend file {path:?}: {error}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+    };
+    let (path, bytes) = match PathBuf::from(&path) {
+        path if path.as_os_str().is_empty() => (PathBuf::from(DEFAULT_FILE), None),
+        path => {
+            let bytes = read(path.clone()).await?;
+            match bytes {
+                None if path.extension().is_none() => (PathBuf::from(DEFAULT_FILE), None),
+                bytes => (path, bytes),
+            }
+        }
     };
+    tracing::debug!("{path:?}");
 
     let mut headers = HeaderMap::default();
-
-    if let Some(mime) = mime_guess::from_path(path).first() {
+    if let Some(mime) = mime_guess::from_path(&path).first() {
         headers.insert(header::CONTENT_TYPE, mime.to_string().try_into().unwrap());
     }
 
-    Ok((headers, bytes.clone()))
+    for (encoding, extension) in ENCODINGS {
+        let mut compressed = path.clone().into_os_string();
+        compressed.push(".");
+        compressed.push(extension);
+        if let Some(bytes) = read(PathBuf::from(compressed)).await? {
+            headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+            if accepts(&request, encoding) {
+                headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding));
+                return Ok((headers, bytes));
+            }
+        }
+    }
+
+    let bytes = match bytes {
+        Some(bytes) => bytes,
+        None => read(path).await?.ok_or(StatusCode::NOT_FOUND)?,
+    };
+    Ok((headers, bytes))
 }
 
 pub fn routes() -> Router<Backend> {
@@ -39,3 +103,24 @@ pub fn routes() -> Router<Backend> {
         .route("/*path", get(frontend_file))
         .route("/", get(frontend_file))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(value: &'static str) -> HeaderMap {
+        [(header::ACCEPT_ENCODING, HeaderValue::from_static(value))]
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn can_check_accepted_encoding() {
+        assert!(accepts(&headers("gzip, deflate, br"), "br"));
+        assert!(accepts(&headers("gzip;q=0.5"), "gzip"));
+        assert!(accepts(&headers("*"), "br"));
+        assert!(!accepts(&headers("gzip"), "br"));
+        assert!(!accepts(&headers("br;q=0, gzip"), "br"));
+        assert!(!accepts(&HeaderMap::new(), "gzip"));
+    }
+}
@@ -1,44 +1,267 @@
-use crate::Backend;
+use super::{
+    bearer_token,
+    openapi::{ApiRouter, Operation, EVENT_STREAM},
+    ApiError,
+};
+use crate::{
+    ingest,
+    logs::{JobLogs, LogEvent},
+    metrics::Metrics,
+    tokens::JobTokens,
+    uploads::{self, UploadError, Uploads, MAX_ARTIFACT_SIZE},
+    Backend,
+};
 use axum::{
+    body::Bytes,
     extract::{
+        rejection::{PathRejection, QueryRejection},
         ws::{Message, WebSocket, WebSocketUpgrade},
-        State,
+        DefaultBodyLimit, Path, Query, State,
+    },
+    handler::Handler,
+    http::{HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Response,
     },
-    response::Response,
-    routing::get,
-    Router,
 };
-use buildsrs_database::{entity::Builder, AnyMetadata, Error as DatabaseError};
-use buildsrs_protocol::{ssh_key::Fingerprint, types::JobKind, *};
-use futures::StreamExt;
+use buildsrs_common::{
+    api::{ArtifactUploadQuery, JobLogEnd, JobLogLine, JobLogStage, JobLogsResponse},
+    entities::{CrateTarget, JobArtifactInfo, JobInfo, JobLogEntry, Task},
+};
+use buildsrs_database::{entity::Builder, AnyMetadata, BoxError, Error as DatabaseError};
+use buildsrs_protocol::{
+    types::{BuildEnv, JobKind, Variant},
+    *,
+};
+use buildsrs_storage::{AnyStorage, ArtifactId, ArtifactKind, StorageError};
+use futures::{future, stream, stream::BoxStream, SinkExt, StreamExt, TryStreamExt};
+use serde::Serialize;
+use std::{
+    collections::BTreeSet,
+    convert::Infallible,
+    time::{Duration, SystemTime},
+};
+use tokio::sync::broadcast::error::RecvError;
 use tracing::*;
+use uuid::Uuid;
 
+/// Error in the jobs connection of a builder.
 #[derive(thiserror::Error, Debug)]
 pub enum WebSocketError {
+    /// Connection closed before the builder sent its hello
     #[error("Missing Hello message")]
     MissingHello,
+    /// Response to the challenge is invalid
     #[error("Challenge incorrect")]
     ChallengeError,
+    /// Challenge expired before the builder responded
+    #[error("Challenge expired")]
+    ChallengeExpired,
+    /// Builder is disabled
+    #[error("Builder {0} is disabled")]
+    BuilderDisabled(Uuid),
+    /// Connection was closed
     #[error("Stream is closed")]
     StreamClosed,
+    /// Builder reported on a job it was not assigned
+    #[error("Job {0} is not assigned to this builder")]
+    JobNotAssigned(Uuid),
+    /// Builder used a feature that was not negotiated
+    #[error("Feature {0:?} was not negotiated")]
+    FeatureNotNegotiated(Feature),
+    /// Builder was rejected during negotiation
+    #[error("Builder rejected: {0}")]
+    Rejected(#[from] Rejection),
+    /// Error in transport
     #[error(transparent)]
-    Axum(#[from] axum::Error),
+    Transport(#[from] TransportError),
+    /// Error encoding or decoding message
     #[error(transparent)]
-    Json(#[from] serde_json::Error),
+    Codec(#[from] CodecError),
+    /// Message signature is invalid
     #[error(transparent)]
     Signature(#[from] SignatureError),
+    /// Error in database
     #[error(transparent)]
     Database(#[from] DatabaseError),
+    /// Error in metadata
+    #[error(transparent)]
+    Metadata(#[from] BoxError),
+    /// Error in artifact upload
+    #[error(transparent)]
+    Upload(#[from] UploadError),
+    /// Error in storage
+    #[error(transparent)]
+    Storage(#[from] StorageError),
 }
 
-async fn extract_fingerprint(socket: &mut WebSocket) -> Result<Fingerprint, WebSocketError> {
-    while let Some(message) = socket.next().await {
-        let message: SignedMessage<ClientMessage> = match message? {
-            Message::Text(message) => serde_json::from_str(&message)?,
-            _ => continue,
+/// Duration a job is leased to a builder, extended by every heartbeat.
+const JOB_LEASE: Duration = Duration::from_secs(4 * JOB_HEARTBEAT_INTERVAL.as_secs());
+
+/// Interval at which jobs whose lease has expired are returned to the queue.
+pub(crate) const JOBS_EXPIRE_INTERVAL: Duration = JOB_HEARTBEAT_INTERVAL;
+
+/// Attempts at ingesting the metadata of a completed job before giving up.
+const INGEST_ATTEMPTS: u32 = 3;
+
+/// Delay between attempts at ingesting metadata.
+const INGEST_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Return jobs whose lease has expired to the queue.
+///
+/// Partial uploads of expired jobs are discarded and subscribers to their logs are notified that
+/// they have ended.
+async fn expire_jobs(
+    database: &AnyMetadata,
+    uploads: &Uploads,
+    job_logs: &JobLogs,
+    metrics: &Metrics,
+) -> Result<(), BoxError> {
+    let writer = database.write().await?;
+    let mut expired = vec![];
+    for job in writer.jobs_expire().await? {
+        expired.push(writer.job_info(job).await?);
+    }
+    writer.commit().await?;
+    for job in &expired {
+        info!("Lease of job {} has expired", job.uuid);
+        uploads.discard(job.uuid);
+        metrics.job_ended(&job.triple, false);
+        job_logs.publish(job.uuid, LogEvent::End { success: false });
+    }
+    Ok(())
+}
+
+/// Record the targets of a crate version and create its follow-up tasks.
+///
+/// The job has already completed at this point, so database errors are logged and retried rather
+/// than returned.
+async fn ingest_targets(database: &AnyMetadata, info: &JobInfo, targets: &[CrateTarget]) {
+    for attempt in 1..=INGEST_ATTEMPTS {
+        let result = async {
+            let writer = database.write().await?;
+            ingest::ingest(&*writer, &info.name, &info.version, targets).await?;
+            writer.commit().await
+        }
+        .await;
+        match result {
+            Ok(()) => return,
+            Err(error) if attempt < INGEST_ATTEMPTS => {
+                warn!("Ingesting metadata of job {} failed: {error}", info.uuid);
+                tokio::time::sleep(INGEST_RETRY_DELAY).await;
+            }
+            Err(error) => error!(
+                "Ingesting metadata of job {} failed after {INGEST_ATTEMPTS} attempts: {error}",
+                info.uuid
+            ),
+        }
+    }
+}
+
+/// Identifier of the artifact of a kind built by a job.
+fn artifact_id(info: &JobInfo, kind: ArtifactKind) -> ArtifactId {
+    ArtifactId {
+        task: Task {
+            krate: info.name.clone(),
+            version: info.version.clone(),
+            triple: info.triple.clone(),
+            kind,
+        },
+    }
+}
+
+/// Kind of job which builds a task of the given kind for a triple.
+fn job_kind(kind: &str, triple: &str) -> JobKind {
+    let env = || BuildEnv {
+        variant: Variant::default(),
+        target: triple.into(),
+        features: Default::default(),
+        default_features: true,
+        environment: Default::default(),
+        dependencies: Default::default(),
+    };
+    match kind.parse() {
+        Ok(ArtifactKind::Tarball) => JobKind::Binary(env()),
+        Ok(ArtifactKind::Debian) => JobKind::Debian(env()),
+        Ok(ArtifactKind::Coverage) => JobKind::Coverage(env()),
+        _ => JobKind::Metadata,
+    }
+}
+
+/// Optional protocol features supported by this server.
+fn supported_features() -> BTreeSet<Feature> {
+    [
+        Feature::JobEvents,
+        Feature::Capabilities,
+        Feature::JobLease,
+        Feature::ArtifactUpload,
+        Feature::Cbor,
+    ]
+    .into()
+}
+
+/// Convert a `WebSocket` message into a frame, returning `None` for control messages.
+fn message_frame(message: Message) -> Option<Frame> {
+    match message {
+        Message::Text(text) => Some(Frame::Text(text)),
+        Message::Binary(data) => Some(Frame::Binary(data)),
+        _ => None,
+    }
+}
+
+/// Convert a frame into a `WebSocket` message.
+fn frame_message(frame: Frame) -> Message {
+    match frame {
+        Frame::Text(text) => Message::Text(text),
+        Frame::Binary(data) => Message::Binary(data),
+    }
+}
+
+/// Adapt a `WebSocket` into a transport of frames, skipping control messages.
+fn websocket_transport(websocket: WebSocket) -> BoxTransport {
+    let transport = websocket
+        .map_err(TransportError::new)
+        .try_filter_map(|message| future::ready(Ok(message_frame(message))))
+        .sink_map_err(TransportError::new)
+        .with(|frame| future::ready(Ok(frame_message(frame))));
+    Box::new(transport)
+}
+
+/// Wait for the [`ClientMessage::Hello`] message, returning it along with its contents.
+///
+/// The message cannot be verified yet, because the key used to sign it is only known once the
+/// builder has been looked up by the fingerprint. The hello is always encoded as JSON, since no
+/// codec has been negotiated yet.
+///
+/// Builders speaking version 1 of the protocol send their hello in a [`LegacyMessage`], they are
+/// rejected with [`Rejection::UnsupportedVersion`].
+async fn extract_hello(
+    transport: &mut BoxTransport,
+) -> Result<(Hello, SignedMessage<ClientMessage>), WebSocketError> {
+    while let Some(frame) = transport.next().await {
+        let frame = frame?;
+        let message: SignedMessage<ClientMessage> = match Codec::Json.decode_frame(&frame) {
+            Ok(message) => message,
+            Err(error) => {
+                if let Ok(LegacyMessage {
+                    message: ClientMessage::Hello(hello),
+                    ..
+                }) = Codec::Json.decode_frame(&frame)
+                {
+                    if let Err(rejection) = hello.negotiate(&supported_features()) {
+                        info!("Rejecting builder {}: {rejection}", hello.fingerprint);
+                        let frame =
+                            Codec::Json.encode_frame(&ServerMessage::Rejected(rejection))?;
+                        transport.send(frame).await?;
+                        return Err(rejection.into());
+                    }
+                }
+                return Err(error.into());
+            }
         };
-        match message.message {
-            ClientMessage::Hello(fingerprint) => return Ok(fingerprint),
+        match message.inspect()?.message {
+            ClientMessage::Hello(hello) => return Ok((hello, message)),
             _ => continue,
         }
     }
@@ -47,93 +270,480 @@ async fn extract_fingerprint(socket: &mut WebSocket) -> Result<Fingerprint, WebS
 }
 
 struct Connection {
-    websocket: WebSocket,
+    transport: BoxTransport,
     builder: Builder,
+    /// Verifies messages received from the builder.
+    verifier: MessageVerifier,
     database: AnyMetadata,
+    storage: AnyStorage,
+    /// Partial artifact uploads, shared between connections.
+    uploads: Uploads,
+    /// Issues tokens for jobs handed out to the builder.
+    job_tokens: JobTokens,
+    /// Subscribers to job logs, shared between connections.
+    job_logs: JobLogs,
+    /// Metrics of the backend.
+    metrics: Metrics,
+    /// Identity of this server, which challenges are bound to.
+    server: String,
+    /// Negotiated optional features.
+    features: BTreeSet<Feature>,
+    /// Capabilities advertised by the builder.
+    capabilities: Option<Capabilities>,
+    /// Codec used to encode messages.
+    codec: Codec,
 }
 
 impl Connection {
     async fn recv(&mut self) -> Result<ClientMessage, WebSocketError> {
-        while let Some(message) = self.websocket.next().await {
-            let message: SignedMessage<ClientMessage> = match message? {
-                Message::Text(message) => serde_json::from_str(&message)?,
-                _ => continue,
-            };
-            message.verify(&self.builder.public_key)?;
-            return Ok(message.message);
-        }
-        Err(WebSocketError::StreamClosed)
+        let frame = self
+            .transport
+            .next()
+            .await
+            .ok_or(WebSocketError::StreamClosed)??;
+        let message: SignedMessage<ClientMessage> = self.codec.decode_frame(&frame)?;
+        Ok(self.verifier.verify(&message)?)
     }
 
     async fn send(&mut self, message: ServerMessage) -> Result<(), WebSocketError> {
-        self.websocket
-            .send(Message::Text(serde_json::to_string(&message)?))
-            .await?;
+        let frame = self.codec.encode_frame(&message)?;
+        self.transport.send(frame).await?;
         Ok(())
     }
 
+    /// Negotiate protocol version and features with the builder.
+    ///
+    /// Builders speaking an unsupported version of the protocol are rejected. If
+    /// [`Feature::Cbor`] was negotiated, all messages following the reply are encoded as CBOR.
+    async fn negotiate(&mut self, hello: &Hello) -> Result<(), WebSocketError> {
+        let result = if self.builder.enabled {
+            hello.negotiate(&supported_features())
+        } else {
+            Err(Rejection::BuilderDisabled)
+        };
+        match result {
+            Ok(welcome) => {
+                debug!(
+                    "Builder {} negotiated protocol version {} with features {:?}",
+                    self.builder.uuid, welcome.version, welcome.features
+                );
+                self.features.clone_from(&welcome.features);
+                self.send(ServerMessage::Welcome(welcome)).await?;
+                if self.features.contains(&Feature::Cbor) {
+                    self.codec = Codec::Cbor;
+                }
+                Ok(())
+            }
+            Err(rejection) => {
+                self.send(ServerMessage::Rejected(rejection)).await?;
+                match rejection {
+                    Rejection::BuilderDisabled => {
+                        Err(WebSocketError::BuilderDisabled(self.builder.uuid))
+                    }
+                    rejection @ Rejection::UnsupportedVersion { .. } => Err(rejection.into()),
+                }
+            }
+        }
+    }
+
     async fn challenge(&mut self) -> Result<(), WebSocketError> {
-        // FIXME: randomize this!
-        let challenge = [0xab].to_vec();
-        self.send(ServerMessage::ChallengeRequest(challenge.clone().into()))
+        let challenge = Challenge::new(self.server.clone());
+        self.send(ServerMessage::ChallengeRequest(challenge.clone()))
             .await?;
         loop {
             let message = self.recv().await?;
             match message {
                 ClientMessage::ChallengeResponse(response) => {
-                    return if challenge == response {
-                        Ok(())
-                    } else {
-                        Err(WebSocketError::ChallengeError)
+                    if challenge.expired(SystemTime::now()) {
+                        return Err(WebSocketError::ChallengeExpired);
                     }
+                    return challenge
+                        .verify(&self.builder.public_key, &response)
+                        .map_err(|_| WebSocketError::ChallengeError);
                 }
                 _ => continue,
             }
         }
     }
 
-    #[allow(clippy::no_effect_underscore_binding)]
+    fn handle_capabilities(&mut self, capabilities: Capabilities) -> Result<(), WebSocketError> {
+        self.check_feature(Feature::Capabilities)?;
+        debug!(
+            "Builder {} advertised capabilities {capabilities:?}",
+            self.builder.uuid
+        );
+        self.capabilities = Some(capabilities);
+        Ok(())
+    }
+
+    /// Triples that a job may be handed out for.
+    ///
+    /// Builders that do not advertise capabilities can only request a specific target, otherwise
+    /// the target has to be one of the advertised triples.
+    fn job_triples<'a>(&'a self, request: &'a JobRequest) -> Vec<&'a str> {
+        match (&request.target, &self.capabilities) {
+            (Some(target), Some(capabilities)) if !capabilities.triples.contains(target) => vec![],
+            (Some(target), _) => vec![target.as_str()],
+            (None, Some(capabilities)) => capabilities.triples.iter().map(String::as_str).collect(),
+            (None, None) => vec![],
+        }
+    }
+
     async fn handle_job_request(
         &mut self,
-        _request: &JobRequest,
-    ) -> Result<ServerMessage, WebSocketError> {
-        let writer = self.database.write().await.unwrap();
-        let job = writer.job_request(self.builder.uuid).await.unwrap();
-        let job = writer.job_info(job).await.unwrap();
-        Ok(ServerMessage::JobResponse(Job {
-            kind: JobKind::Metadata,
+        request: &JobRequest,
+    ) -> Result<Option<ServerMessage>, WebSocketError> {
+        // return jobs of builders which have gone silent to the queue
+        expire_jobs(&self.database, &self.uploads, &self.job_logs, &self.metrics).await?;
+
+        let triples = self.job_triples(request);
+        let writer = self.database.write().await?;
+        let Some(job) = writer
+            .job_request(self.builder.uuid, &triples, JOB_LEASE)
+            .await?
+        else {
+            writer.commit().await?;
+            return Ok(None);
+        };
+        let job = writer.job_info(job).await?;
+        writer.commit().await?;
+        self.metrics.job_dispatched(&job.triple);
+        Ok(Some(ServerMessage::JobResponse(Job {
+            kind: job_kind(&job.kind, &job.triple),
             name: job.name,
             source: "https://example.com".parse().unwrap(),
+            token: self.job_tokens.issue(job.uuid, SystemTime::now()),
             uuid: job.uuid,
             version: job.version,
+        })))
+    }
+
+    /// Notify subscribers to the logs of jobs that they have ended.
+    fn end_jobs(&self, jobs: &[Uuid], success: bool) {
+        for &job in jobs {
+            self.job_logs.publish(job, LogEvent::End { success });
+        }
+    }
+
+    /// Make sure that the feature was negotiated with the builder.
+    fn check_feature(&self, feature: Feature) -> Result<(), WebSocketError> {
+        if self.features.contains(&feature) {
+            Ok(())
+        } else {
+            Err(WebSocketError::FeatureNotNegotiated(feature))
+        }
+    }
+
+    /// Make sure that the job exists and is assigned to the connected builder.
+    async fn check_job(&mut self, job: Uuid) -> Result<JobInfo, WebSocketError> {
+        let reader = self.database.read().await?;
+        let info = reader.job_info(job).await?;
+        if info.builder != self.builder.uuid {
+            return Err(WebSocketError::JobNotAssigned(job));
+        }
+        Ok(info)
+    }
+
+    /// Make sure that the job has neither ended nor expired, before accepting its outcome.
+    ///
+    /// Outcomes reported late or more than once are ignored, such that they cannot replace the
+    /// artifacts or the result of the job.
+    async fn check_job_active(&mut self, info: &JobInfo) -> Result<bool, WebSocketError> {
+        if info.ended.is_some() || !self.job_keepalive(info.uuid).await? {
+            warn!("Ignoring outcome of inactive job {}", info.uuid);
+            self.uploads.discard(info.uuid);
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Extend the lease of a job, returning `false` if it is no longer active.
+    ///
+    /// Jobs which are no longer active are cancelled on the builder.
+    async fn job_keepalive(&mut self, job: Uuid) -> Result<bool, WebSocketError> {
+        let writer = self.database.write().await?;
+        let active = writer.job_heartbeat(job, JOB_LEASE).await?;
+        writer.commit().await?;
+        if !active && self.features.contains(&Feature::JobLease) {
+            info!("Cancelling job {job} on builder {}", self.builder.uuid);
+            self.send(ServerMessage::CancelJob(JobCancel {
+                job,
+                reason: "job is no longer active".into(),
+            }))
+            .await?;
+        }
+        Ok(active)
+    }
+
+    async fn handle_job_heartbeat(
+        &mut self,
+        heartbeat: JobHeartbeat,
+    ) -> Result<(), WebSocketError> {
+        self.check_feature(Feature::JobLease)?;
+        self.check_job(heartbeat.job).await?;
+        self.job_keepalive(heartbeat.job).await?;
+        Ok(())
+    }
+
+    async fn handle_job_stage(&mut self, update: &JobStageUpdate) -> Result<(), WebSocketError> {
+        self.check_feature(Feature::JobEvents)?;
+        self.check_job(update.job).await?;
+        if !self.job_keepalive(update.job).await? {
+            return Ok(());
+        }
+        debug!("Job {} entered stage {:?}", update.job, update.stage);
+        let writer = self.database.write().await?;
+        writer.job_stage(update.job, update.stage.name()).await?;
+        writer.commit().await?;
+        Ok(())
+    }
+
+    async fn handle_job_log(&mut self, log: &JobLog) -> Result<(), WebSocketError> {
+        self.check_feature(Feature::JobEvents)?;
+        let info = self.check_job(log.job).await?;
+        if !self.job_keepalive(log.job).await? {
+            return Ok(());
+        }
+        let writer = self.database.write().await?;
+        let mut ids = Vec::with_capacity(log.lines.len());
+        for line in &log.lines {
+            ids.push(writer.job_log(log.job, line).await?);
+        }
+        writer.commit().await?;
+        for (id, line) in ids.into_iter().zip(&log.lines) {
+            self.job_logs.publish(
+                log.job,
+                LogEvent::Line {
+                    id,
+                    stage: info.stage.clone(),
+                    line: line.clone(),
+                },
+            );
+        }
+        Ok(())
+    }
+
+    async fn handle_artifact_chunk(&mut self, chunk: ArtifactChunk) -> Result<(), WebSocketError> {
+        self.check_feature(Feature::ArtifactUpload)?;
+        self.check_job(chunk.job).await?;
+        if !self.job_keepalive(chunk.job).await? {
+            return Ok(());
+        }
+        let offset = self
+            .uploads
+            .append(chunk.job, &chunk.artifact, chunk.offset, &chunk.data)?;
+        self.send(ServerMessage::ArtifactAck(ArtifactAck {
+            job: chunk.job,
+            artifact: chunk.artifact,
+            offset,
         }))
+        .await
+    }
+
+    /// Verify the uploaded artifacts against the ones declared in the completion, and store them.
+    ///
+    /// Artifacts are only stored once all of them have been verified. Artifacts which were
+    /// already stored over HTTP are not uploaded again. Metadata which cannot be parsed is skipped
+    /// without rejecting the other artifacts. Returns the artifacts to record, and the targets of
+    /// the crate if metadata was uploaded.
+    async fn store_artifacts(
+        &mut self,
+        info: &JobInfo,
+        complete: &JobComplete,
+    ) -> Result<(Vec<JobArtifactInfo>, Option<Vec<CrateTarget>>), WebSocketError> {
+        let recorded = self
+            .database
+            .read()
+            .await?
+            .job_artifacts(complete.job)
+            .await?;
+        let mut targets = None;
+        let mut verified = Vec::with_capacity(complete.artifacts.len());
+        let mut artifacts = Vec::with_capacity(complete.artifacts.len());
+        for artifact in &complete.artifacts {
+            let kind: ArtifactKind = artifact
+                .name
+                .parse()
+                .map_err(|_| UploadError::UnknownKind(artifact.name.clone()))?;
+            let signature = artifact
+                .signature
+                .clone()
+                .ok_or_else(|| UploadError::Unsigned(artifact.name.clone()))?;
+            artifact
+                .verify(complete.job, &self.builder.public_key, &signature)
+                .map_err(|_| UploadError::InvalidSignature(artifact.name.clone()))?;
+            let id = artifact_id(info, kind);
+            let stored = recorded.iter().any(|recorded| {
+                recorded.name == artifact.name && recorded.hash.eq_ignore_ascii_case(&artifact.hash)
+            });
+            let data = if stored {
+                None
+            } else {
+                Some(self.uploads.finish(complete.job, artifact)?)
+            };
+            if kind == ArtifactKind::Metadata {
+                let metadata = match &data {
+                    Some(data) => data.clone(),
+                    None => self
+                        .storage
+                        .artifact_get(&id)
+                        .await?
+                        .bytes()
+                        .cloned()
+                        .unwrap_or_default(),
+                };
+                match ingest::targets(&metadata, &info.name, &info.version) {
+                    Ok(found) => targets = Some(found),
+                    Err(error) => {
+                        warn!("Job {} metadata rejected: {error}", complete.job);
+                        continue;
+                    }
+                }
+            }
+            if let Some(data) = data {
+                verified.push((id, data));
+            }
+            artifacts.push(JobArtifactInfo {
+                name: artifact.name.clone(),
+                hash: artifact.hash.clone(),
+                size: artifact.size,
+                signature,
+            });
+        }
+
+        for (id, data) in verified {
+            self.storage.artifact_put(&id, &data).await?;
+        }
+
+        Ok((artifacts, targets))
+    }
+
+    async fn handle_job_complete(&mut self, complete: &JobComplete) -> Result<(), WebSocketError> {
+        self.check_feature(Feature::JobEvents)?;
+        let info = self.check_job(complete.job).await?;
+        if !self.check_job_active(&info).await? {
+            return Ok(());
+        }
+        info!(
+            "Job {} completed with {} artifacts",
+            complete.job,
+            complete.artifacts.len()
+        );
+        let (result, targets) = match self.store_artifacts(&info, complete).await {
+            Ok((artifacts, targets)) => (Ok(artifacts), targets),
+            Err(error) => {
+                warn!("Job {} artifacts rejected: {error}", complete.job);
+                (Err(format!("artifacts rejected: {error}")), None)
+            }
+        };
+        self.uploads.discard(complete.job);
+        let success = result.is_ok();
+        let writer = self.database.write().await?;
+        match &result {
+            Ok(artifacts) => {
+                writer.job_finish(complete.job, true).await?;
+                for artifact in artifacts {
+                    writer.job_artifact_add(complete.job, artifact).await?;
+                }
+            }
+            Err(reason) => writer.job_fail(complete.job, reason).await?,
+        }
+        writer.commit().await?;
+        self.end_jobs(&[complete.job], success);
+        self.metrics.job_ended(&info.triple, success);
+        if let Some(targets) = &targets {
+            ingest_targets(&self.database, &info, targets).await;
+        }
+        Ok(())
+    }
+
+    async fn handle_job_failed(&mut self, failed: &JobFailed) -> Result<(), WebSocketError> {
+        self.check_feature(Feature::JobEvents)?;
+        let info = self.check_job(failed.job).await?;
+        if !self.check_job_active(&info).await? {
+            return Ok(());
+        }
+        info!("Job {} failed: {}", failed.job, failed.reason);
+        self.uploads.discard(failed.job);
+        let writer = self.database.write().await?;
+        writer.job_fail(failed.job, &failed.reason).await?;
+        writer.commit().await?;
+        self.end_jobs(&[failed.job], false);
+        self.metrics.job_ended(&info.triple, false);
+        Ok(())
     }
 
     async fn handle(&mut self) -> Result<(), WebSocketError> {
         loop {
-            let response = match self.recv().await? {
+            match self.recv().await? {
                 ClientMessage::Hello(_) | ClientMessage::ChallengeResponse(_) => break,
-                ClientMessage::JobRequest(request) => self.handle_job_request(&request).await?,
-            };
-            self.send(response).await?;
+                ClientMessage::Capabilities(capabilities) => {
+                    self.handle_capabilities(capabilities)?;
+                }
+                ClientMessage::JobRequest(request) => {
+                    if let Some(response) = self.handle_job_request(&request).await? {
+                        self.send(response).await?;
+                    }
+                }
+                ClientMessage::JobHeartbeat(heartbeat) => {
+                    self.handle_job_heartbeat(heartbeat).await?;
+                }
+                ClientMessage::JobStage(update) => self.handle_job_stage(&update).await?,
+                ClientMessage::JobLog(log) => self.handle_job_log(&log).await?,
+                ClientMessage::ArtifactChunk(chunk) => self.handle_artifact_chunk(chunk).await?,
+                ClientMessage::JobComplete(complete) => self.handle_job_complete(&complete).await?,
+                ClientMessage::JobFailed(failed) => self.handle_job_failed(&failed).await?,
+            }
         }
         Ok(())
     }
 }
 
 impl Backend {
-    /// Handle jobs websocket connection.
-    pub async fn handle_jobs(&self, mut websocket: WebSocket) -> Result<(), WebSocketError> {
-        let fingerprint = extract_fingerprint(&mut websocket).await?;
-        let database = self.database().read().await.unwrap();
-        let uuid = database.builder_lookup(&fingerprint.to_string()).await?;
+    /// Return jobs whose lease has expired to the queue.
+    ///
+    /// Subscribers to the logs of expired jobs are notified that they have ended.
+    pub async fn expire_jobs(&self) -> Result<(), BoxError> {
+        expire_jobs(
+            self.database(),
+            self.uploads(),
+            self.job_logs(),
+            self.metrics(),
+        )
+        .await
+    }
+
+    /// Handle jobs connection of a builder.
+    ///
+    /// The authentication challenge is bound to the configured [server name](Self::server_name).
+    pub async fn handle_jobs(&self, mut transport: BoxTransport) -> Result<(), WebSocketError> {
+        let (hello, message) = extract_hello(&mut transport).await?;
+        let database = self.database().read().await?;
+        let uuid = database
+            .builder_lookup(&hello.fingerprint.to_string())
+            .await?;
         let builder = database.builder_get(uuid).await?;
+        drop(database);
+        let mut verifier = MessageVerifier::new(builder.public_key.clone());
+        verifier.verify(&message)?;
         let mut connection = Connection {
-            websocket,
+            transport,
             builder,
+            verifier,
             database: self.database().clone(),
+            storage: self.storage().clone(),
+            uploads: self.uploads().clone(),
+            job_tokens: self.job_tokens().clone(),
+            job_logs: self.job_logs().clone(),
+            metrics: self.metrics().clone(),
+            server: self.server_name().into(),
+            features: BTreeSet::new(),
+            capabilities: None,
+            codec: Codec::default(),
         };
+        connection.negotiate(&hello).await?;
         connection.challenge().await?;
+        let _connected = self.metrics().builder_connected();
         connection.handle().await?;
         Ok(())
     }
@@ -143,7 +753,7 @@ async fn jobs_websocket(State(backend): State<Backend>, ws: WebSocketUpgrade) ->
     ws.on_upgrade(move |socket| {
         let backend = backend.clone();
         async move {
-            match backend.handle_jobs(socket).await {
+            match backend.handle_jobs(websocket_transport(socket)).await {
                 Ok(()) => {}
                 Err(error) => error!("{error:#}"),
             }
@@ -151,6 +761,188 @@ async fn jobs_websocket(State(backend): State<Backend>, ws: WebSocketUpgrade) ->
     })
 }
 
-pub fn routes() -> Router<Backend> {
-    Router::new().route("/jobs", get(jobs_websocket))
+/// Upload an artifact of a job, authenticated by the token issued with the job.
+///
+/// The artifact is stored and recorded right away, it is only served once the job has completed
+/// successfully.
+async fn job_artifact_upload(
+    State(backend): State<Backend>,
+    path: Result<Path<(Uuid, String)>, PathRejection>,
+    query: Result<Query<ArtifactUploadQuery>, QueryRejection>,
+    headers: HeaderMap,
+    data: Bytes,
+) -> Result<StatusCode, ApiError> {
+    let Path((job, name)) = path?;
+    let token =
+        bearer_token(&headers).ok_or_else(|| ApiError::Unauthorized("missing job token".into()))?;
+    backend.job_tokens().verify(job, token, SystemTime::now())?;
+
+    let Query(query) = query?;
+    let kind: ArtifactKind = name
+        .parse()
+        .map_err(|_| ApiError::BadRequest(format!("invalid artifact kind {name:?}")))?;
+    let artifact = JobArtifact {
+        name,
+        hash: query.hash,
+        size: query.size,
+        signature: None,
+    };
+    uploads::verify(&artifact, &data)?;
+
+    let reader = backend.read().await?;
+    let info = reader.job_info(job).await?;
+    if info.ended.is_some() {
+        return Err(ApiError::BadRequest(format!("job {job} has ended")));
+    }
+    let builder = reader.builder_get(info.builder).await?;
+    drop(reader);
+    artifact
+        .verify(job, &builder.public_key, &query.signature)
+        .map_err(|_| ApiError::BadRequest("invalid artifact signature".into()))?;
+    if kind == ArtifactKind::Metadata {
+        ingest::targets(&data, &info.name, &info.version)?;
+    }
+
+    // uploading extends the lease, jobs whose lease has expired no longer accept artifacts
+    let writer = backend.write().await?;
+    if !writer.job_heartbeat(job, JOB_LEASE).await? {
+        return Err(ApiError::BadRequest(format!(
+            "job {job} is no longer active"
+        )));
+    }
+    backend
+        .storage()
+        .artifact_put(&artifact_id(&info, kind), &data)
+        .await?;
+    writer
+        .job_artifact_add(
+            job,
+            &JobArtifactInfo {
+                name: artifact.name,
+                hash: artifact.hash,
+                size: artifact.size,
+                signature: query.signature,
+            },
+        )
+        .await?;
+    writer.commit().await?;
+    info!("Job {job} stored {kind:?} artifact");
+    Ok(StatusCode::CREATED)
+}
+
+/// Group log lines by consecutive stages.
+fn group_logs(logs: Vec<JobLogEntry>) -> JobLogsResponse {
+    let mut stages: Vec<JobLogStage> = vec![];
+    for entry in logs {
+        match stages.last_mut() {
+            Some(stage) if stage.stage == entry.stage => stage.lines.push(entry.line),
+            _ => stages.push(JobLogStage {
+                stage: entry.stage,
+                lines: vec![entry.line],
+            }),
+        }
+    }
+    JobLogsResponse { stages }
+}
+
+/// Stream the log of a job as server-sent events.
+///
+/// The stored log is sent first as a `logs` event. While the job is running, new lines follow
+/// as `line` events, and an `end` event is sent once it has ended.
+async fn job_logs(
+    State(backend): State<Backend>,
+    path: Result<Path<Uuid>, PathRejection>,
+) -> Result<Sse<BoxStream<'static, Result<Event, Infallible>>>, ApiError> {
+    let Path(job) = path?;
+
+    // subscribe before reading the stored log, so that no lines are missed in between
+    let receiver = backend.job_logs().subscribe(job);
+    let stored = async {
+        let reader = backend.read().await?;
+        let info = reader.job_info(job).await?;
+        let logs = reader.job_logs(job).await?;
+        Ok::<_, ApiError>((info, logs))
+    };
+    let (info, logs) = match stored.await {
+        Ok(stored) => stored,
+        Err(error) => {
+            drop(receiver);
+            backend.job_logs().prune(job);
+            return Err(error);
+        }
+    };
+
+    let last = logs.last().map(|entry| entry.id).unwrap_or_default();
+    let initial = stream::once(future::ready(Ok(sse_event("logs", &group_logs(logs)))));
+    if info.ended.is_some() {
+        drop(receiver);
+        backend.job_logs().prune(job);
+        let end = JobLogEnd {
+            success: info.success.unwrap_or_default(),
+        };
+        let end = stream::once(future::ready(Ok(sse_event("end", &end))));
+        return Ok(Sse::new(initial.chain(end).boxed()));
+    }
+
+    let live = stream::unfold(Some(receiver), move |receiver| async move {
+        let mut receiver = receiver?;
+        loop {
+            match receiver.recv().await {
+                // lines which were already stored have been sent
+                Ok(LogEvent::Line { id, .. }) if id <= last => continue,
+                Ok(LogEvent::Line { stage, line, .. }) => {
+                    let event = sse_event("line", &JobLogLine { stage, line });
+                    return Some((Ok(event), Some(receiver)));
+                }
+                Ok(LogEvent::End { success }) => {
+                    let event = sse_event("end", &JobLogEnd { success });
+                    return Some((Ok(event), None));
+                }
+                Err(RecvError::Lagged(count)) => {
+                    warn!("Subscriber to logs of job {job} missed {count} events");
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    Ok(Sse::new(initial.chain(live).boxed()).keep_alive(KeepAlive::default()))
+}
+
+/// Create a named server-sent event with JSON data.
+fn sse_event<T: Serialize>(name: &str, data: &T) -> Event {
+    Event::default().event(name).json_data(data).unwrap()
+}
+
+pub fn routes() -> ApiRouter {
+    let max_artifact_size = usize::try_from(MAX_ARTIFACT_SIZE).unwrap_or(usize::MAX);
+    ApiRouter::new("jobs")
+        .route(
+            Operation::get(
+                "/jobs",
+                "jobs_websocket",
+                "Connect as a builder to receive jobs",
+            )
+            .response(StatusCode::SWITCHING_PROTOCOLS, "WebSocket connection"),
+            jobs_websocket,
+        )
+        .route(
+            Operation::get("/jobs/:uuid/logs", "job_logs", "Stream the log of a job").content(
+                StatusCode::OK,
+                "Server-sent events",
+                EVENT_STREAM,
+            ),
+            job_logs,
+        )
+        .route(
+            Operation::put(
+                "/jobs/:uuid/artifacts/:kind",
+                "job_artifact_upload",
+                "Upload an artifact of a job",
+            )
+            .security("jobToken")
+            .query::<ArtifactUploadQuery>()
+            .binary_body()
+            .response(StatusCode::CREATED, "Artifact stored"),
+            job_artifact_upload.layer(DefaultBodyLimit::max(max_artifact_size)),
+        )
 }
@@ -0,0 +1,68 @@
+//! Prometheus metrics
+//!
+//! Serves the metrics of the backend at `/metrics`, and records the requests handled by the
+//! routes of the backend.
+
+use crate::Backend;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use std::time::Instant;
+use tracing::*;
+
+/// Content type of the Prometheus text format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Encode the metrics of the backend.
+///
+/// The number of pending tasks is read from the database. If it is unavailable, the remaining
+/// metrics are still served, so that the outage itself can be observed.
+async fn metrics(State(backend): State<Backend>) -> impl IntoResponse {
+    let queue = async {
+        let reader = backend.read().await?;
+        Ok::<_, super::ApiError>(reader.task_queue().await?)
+    };
+    match queue.await {
+        Ok(queue) => backend.metrics().tasks_pending(&queue),
+        Err(error) => warn!("Cannot read pending tasks: {error}"),
+    }
+    (
+        [(header::CONTENT_TYPE, CONTENT_TYPE)],
+        backend.metrics().encode(),
+    )
+}
+
+/// Record the method, route, status and duration of a request.
+///
+/// Requests are labelled by the route they matched rather than their path, to keep the number of
+/// distinct labels bounded.
+pub async fn track_requests(
+    State(backend): State<Backend>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let method = request.method().clone();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", MatchedPath::as_str)
+        .to_owned();
+    let response = next.run(request).await;
+    backend.metrics().request(
+        method.as_str(),
+        &route,
+        response.status().as_u16(),
+        started.elapsed(),
+    );
+    response
+}
+
+pub fn routes() -> Router<Backend> {
+    Router::new().route("/metrics", get(metrics))
+}
@@ -0,0 +1,443 @@
+//! OpenAPI document
+//!
+//! Routes of the REST API are registered through an [`ApiRouter`], which records an
+//! [`Operation`] describing every route next to its handler. The OpenAPI document served at
+//! `/api/v1/openapi.json` is generated from these descriptions, using schemas generated from the
+//! types in [`buildsrs_common::api`], so that it cannot drift from the routes.
+
+use crate::Backend;
+use axum::{
+    handler::Handler,
+    http::{Method, StatusCode},
+    routing::{on, MethodFilter},
+    Json, Router,
+};
+use buildsrs_common::api::ErrorResponse;
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::{InstanceType, Schema, SchemaObject},
+    JsonSchema,
+};
+use serde_json::{json, Map, Value};
+
+/// Prefix under which the REST API is nested.
+pub const PREFIX: &str = "/api/v1";
+
+/// Media type of JSON bodies.
+pub const JSON: &str = "application/json";
+
+/// Media type of binary bodies, such as artifacts.
+pub const BINARY: &str = "application/octet-stream";
+
+/// Media type of server-sent event streams.
+pub const EVENT_STREAM: &str = "text/event-stream";
+
+type SchemaFn = fn(&mut SchemaGenerator) -> Schema;
+
+fn schema<T: JsonSchema>(generator: &mut SchemaGenerator) -> Schema {
+    generator.subschema_for::<T>()
+}
+
+fn object_schema<T: JsonSchema>(generator: &mut SchemaGenerator) -> SchemaObject {
+    generator.root_schema_for::<T>().schema
+}
+
+fn binary_schema(_generator: &mut SchemaGenerator) -> Schema {
+    SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        format: Some("binary".into()),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// Body of a request or response.
+#[derive(Clone, Debug)]
+struct Content {
+    media_type: &'static str,
+    schema: Option<SchemaFn>,
+}
+
+impl Content {
+    fn document(&self, generator: &mut SchemaGenerator) -> Value {
+        let schema = match self.schema {
+            Some(schema) => json!({ "schema": schema(generator) }),
+            None => json!({}),
+        };
+        json!({ self.media_type: schema })
+    }
+}
+
+/// Description of an operation of the REST API.
+#[derive(Clone, Debug)]
+pub struct Operation {
+    method: Method,
+    path: &'static str,
+    id: &'static str,
+    summary: &'static str,
+    tag: &'static str,
+    security: Option<&'static str>,
+    query: Option<fn(&mut SchemaGenerator) -> SchemaObject>,
+    body: Option<Content>,
+    responses: Vec<(StatusCode, &'static str, Option<Content>)>,
+}
+
+impl Operation {
+    /// Describe an operation, identified by a unique ID.
+    ///
+    /// The path uses the syntax of the router, with parameters written as `:name`.
+    pub fn new(
+        method: Method,
+        path: &'static str,
+        id: &'static str,
+        summary: &'static str,
+    ) -> Self {
+        Self {
+            method,
+            path,
+            id,
+            summary,
+            tag: "",
+            security: None,
+            query: None,
+            body: None,
+            responses: vec![],
+        }
+    }
+
+    /// Describe a `GET` operation.
+    pub fn get(path: &'static str, id: &'static str, summary: &'static str) -> Self {
+        Self::new(Method::GET, path, id, summary)
+    }
+
+    /// Describe a `POST` operation.
+    pub fn post(path: &'static str, id: &'static str, summary: &'static str) -> Self {
+        Self::new(Method::POST, path, id, summary)
+    }
+
+    /// Describe a `PUT` operation.
+    pub fn put(path: &'static str, id: &'static str, summary: &'static str) -> Self {
+        Self::new(Method::PUT, path, id, summary)
+    }
+
+    /// Describe a `PATCH` operation.
+    pub fn patch(path: &'static str, id: &'static str, summary: &'static str) -> Self {
+        Self::new(Method::PATCH, path, id, summary)
+    }
+
+    /// Describe a `DELETE` operation.
+    pub fn delete(path: &'static str, id: &'static str, summary: &'static str) -> Self {
+        Self::new(Method::DELETE, path, id, summary)
+    }
+
+    /// Require a bearer token of the given security scheme.
+    pub fn security(mut self, scheme: &'static str) -> Self {
+        self.security = Some(scheme);
+        self
+    }
+
+    /// Accept query parameters, which are the fields of `T`.
+    pub fn query<T: JsonSchema>(mut self) -> Self {
+        self.query = Some(object_schema::<T>);
+        self
+    }
+
+    /// Accept a JSON request body.
+    pub fn json_body<T: JsonSchema>(mut self) -> Self {
+        self.body = Some(Content {
+            media_type: JSON,
+            schema: Some(schema::<T>),
+        });
+        self
+    }
+
+    /// Accept a binary request body.
+    pub fn binary_body(mut self) -> Self {
+        self.body = Some(Content {
+            media_type: BINARY,
+            schema: Some(binary_schema),
+        });
+        self
+    }
+
+    /// Respond with a JSON body.
+    pub fn json<T: JsonSchema>(mut self, status: StatusCode, description: &'static str) -> Self {
+        let content = Content {
+            media_type: JSON,
+            schema: Some(schema::<T>),
+        };
+        self.responses.push((status, description, Some(content)));
+        self
+    }
+
+    /// Respond with a binary body.
+    pub fn binary(mut self, status: StatusCode, description: &'static str) -> Self {
+        let content = Content {
+            media_type: BINARY,
+            schema: Some(binary_schema),
+        };
+        self.responses.push((status, description, Some(content)));
+        self
+    }
+
+    /// Respond with a body of the given media type, which has no schema.
+    pub fn content(
+        mut self,
+        status: StatusCode,
+        description: &'static str,
+        media_type: &'static str,
+    ) -> Self {
+        let content = Content {
+            media_type,
+            schema: None,
+        };
+        self.responses.push((status, description, Some(content)));
+        self
+    }
+
+    /// Respond without a body.
+    pub fn response(mut self, status: StatusCode, description: &'static str) -> Self {
+        self.responses.push((status, description, None));
+        self
+    }
+
+    fn document(&self, generator: &mut SchemaGenerator) -> Value {
+        let mut parameters: Vec<Value> = self
+            .path
+            .split('/')
+            .filter_map(|segment| segment.strip_prefix(':'))
+            .map(|name| {
+                json!({
+                    "name": name,
+                    "in": "path",
+                    "required": true,
+                    "schema": { "type": "string" },
+                })
+            })
+            .collect();
+        if let Some(query) = self.query {
+            let query = query(generator);
+            if let Some(object) = query.object {
+                for (name, schema) in &object.properties {
+                    parameters.push(json!({
+                        "name": name,
+                        "in": "query",
+                        "required": object.required.contains(name),
+                        "schema": schema,
+                    }));
+                }
+            }
+        }
+
+        let mut responses = Map::new();
+        for (status, description, content) in &self.responses {
+            let mut response = json!({ "description": description });
+            if let Some(content) = content {
+                response["content"] = content.document(generator);
+            }
+            responses.insert(status.as_u16().to_string(), response);
+        }
+        let error = Content {
+            media_type: JSON,
+            schema: Some(schema::<ErrorResponse>),
+        };
+        responses.insert(
+            "default".into(),
+            json!({ "description": "Error", "content": error.document(generator) }),
+        );
+
+        let mut operation = json!({
+            "operationId": self.id,
+            "summary": self.summary,
+            "tags": [self.tag],
+            "responses": responses,
+        });
+        if !parameters.is_empty() {
+            operation["parameters"] = parameters.into();
+        }
+        if let Some(body) = &self.body {
+            operation["requestBody"] = json!({
+                "required": true,
+                "content": body.document(generator),
+            });
+        }
+        if let Some(scheme) = self.security {
+            operation["security"] = json!([{ scheme: [] }]);
+        }
+        operation
+    }
+}
+
+/// Convert a path using the syntax of the router into an OpenAPI path.
+pub fn openapi_path(path: &str) -> String {
+    let path: Vec<String> = path
+        .split('/')
+        .map(|segment| match segment.strip_prefix(':') {
+            Some(name) => format!("{{{name}}}"),
+            None => segment.into(),
+        })
+        .collect();
+    format!("{PREFIX}{}", path.join("/"))
+}
+
+/// Generate the OpenAPI document describing the given operations.
+pub fn document(operations: &[Operation]) -> Value {
+    let mut generator = SchemaSettings::openapi3().into_generator();
+    let mut paths = Map::new();
+    for operation in operations {
+        let path = paths
+            .entry(openapi_path(operation.path))
+            .or_insert_with(|| json!({}));
+        path[operation.method.as_str().to_lowercase()] = operation.document(&mut generator);
+    }
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "buildsrs",
+            "description": "REST API of the buildsrs backend.",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": {
+            "schemas": generator.take_definitions(),
+            "securitySchemes": {
+                "adminToken": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "Admin token, created using `buildsrs-database token add`.",
+                },
+                "jobToken": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "Token issued to a builder along with a job.",
+                },
+            },
+        },
+    })
+}
+
+/// Router which records a description of every route registered with it.
+pub struct ApiRouter {
+    tag: &'static str,
+    router: Router<Backend>,
+    operations: Vec<Operation>,
+}
+
+impl ApiRouter {
+    /// Create a router whose operations are grouped under the given tag.
+    pub fn new(tag: &'static str) -> Self {
+        Self {
+            tag,
+            router: Router::new(),
+            operations: vec![],
+        }
+    }
+
+    /// Register a handler for the given operation.
+    pub fn route<H, T>(mut self, mut operation: Operation, handler: H) -> Self
+    where
+        H: Handler<T, Backend>,
+        T: 'static,
+    {
+        let filter = MethodFilter::try_from(operation.method.clone())
+            .expect("operation has unsupported method");
+        self.router = self.router.route(operation.path, on(filter, handler));
+        operation.tag = self.tag;
+        self.operations.push(operation);
+        self
+    }
+
+    /// Merge the routes of another router into this one.
+    pub fn merge(mut self, other: ApiRouter) -> Self {
+        self.router = self.router.merge(other.router);
+        self.operations.extend(other.operations);
+        self
+    }
+
+    /// Serve the OpenAPI document of all routes, including the one serving it.
+    pub fn with_document(mut self) -> Self {
+        let operation = Operation::get(
+            "/openapi.json",
+            "openapi",
+            "Get the OpenAPI document of this API",
+        )
+        .content(StatusCode::OK, "OpenAPI document", JSON);
+        self.operations.push(operation.clone());
+        let document = document(&self.operations);
+        self.operations.pop();
+        self.route(operation, || async move { Json(document) })
+    }
+
+    /// Router for the registered routes.
+    pub fn into_router(self) -> Router<Backend> {
+        self.router
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn path_conversion() {
+        assert_eq!(openapi_path("/crates"), "/api/v1/crates");
+        assert_eq!(
+            openapi_path("/crates/:crate/:version"),
+            "/api/v1/crates/{crate}/{version}"
+        );
+    }
+
+    #[test]
+    fn document_covers_routes() {
+        let api = crate::api::api();
+        let document = document(&api.operations);
+        for operation in &api.operations {
+            let path = openapi_path(operation.path);
+            let method = operation.method.as_str().to_lowercase();
+            assert!(
+                document["paths"][&path][&method].is_object(),
+                "{method} {path} missing from OpenAPI document"
+            );
+        }
+        assert!(document["paths"]["/api/v1/openapi.json"]["get"].is_object());
+    }
+
+    #[test]
+    fn operation_ids_are_unique() {
+        let api = crate::api::api();
+        let mut ids = BTreeSet::new();
+        for operation in &api.operations {
+            assert!(ids.insert(operation.id), "duplicate id {}", operation.id);
+        }
+    }
+
+    /// Collect all references in a document.
+    fn references<'a>(value: &'a Value, output: &mut Vec<&'a str>) {
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::String(reference)) = map.get("$ref") {
+                    output.push(reference);
+                }
+                map.values().for_each(|value| references(value, output));
+            }
+            Value::Array(values) => values.iter().for_each(|value| references(value, output)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn references_resolve() {
+        let document = document(&crate::api::api().operations);
+        let mut found = vec![];
+        references(&document, &mut found);
+        assert!(!found.is_empty());
+        for reference in found {
+            let name = reference.strip_prefix("#/components/schemas/").unwrap();
+            assert!(
+                document["components"]["schemas"][name].is_object(),
+                "unresolved reference {reference}"
+            );
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Build status badges
+//!
+//! Badges are small SVG images in the flat style of shields.io, which crate authors can embed in
+//! their README to show whether their crate builds. The status of a crate version is summarized
+//! from the most recent job of each of its tasks.
+
+use buildsrs_common::entities::TaskStatus;
+
+/// Label shown on the left side of every badge.
+const LABEL: &str = "builds.rs";
+
+/// Approximate width of a character, in pixels, for 11px Verdana.
+const CHAR_WIDTH: usize = 7;
+
+/// Horizontal padding around each text, in pixels.
+const PADDING: usize = 10;
+
+/// Build status of a crate version.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuildStatus {
+    /// All tasks have been built successfully.
+    Passing,
+    /// The most recent job of some task has failed.
+    Failing,
+    /// Some tasks have not been built yet.
+    Pending,
+    /// There are no tasks.
+    NotBuilt,
+}
+
+impl BuildStatus {
+    /// Summarize the status of the tasks of a crate version.
+    ///
+    /// Failures take precedence over tasks which are still pending.
+    pub fn of(tasks: &[TaskStatus]) -> Self {
+        if tasks.is_empty() {
+            Self::NotBuilt
+        } else if tasks.iter().any(|task| task.success == Some(false)) {
+            Self::Failing
+        } else if tasks.iter().any(|task| task.success.is_none()) {
+            Self::Pending
+        } else {
+            Self::Passing
+        }
+    }
+
+    fn message(self) -> &'static str {
+        match self {
+            Self::Passing => "passing",
+            Self::Failing => "failing",
+            Self::Pending => "pending",
+            Self::NotBuilt => "not built",
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Self::Passing => "#4c1",
+            Self::Failing => "#e05d44",
+            Self::Pending => "#dfb317",
+            Self::NotBuilt => "#9f9f9f",
+        }
+    }
+}
+
+/// Width of a text in the badge, including its padding.
+fn text_width(text: &str) -> usize {
+    text.chars().count() * CHAR_WIDTH + PADDING
+}
+
+/// Render the badge of a build status as SVG.
+pub fn render(status: BuildStatus) -> String {
+    let (label, message, color) = (LABEL, status.message(), status.color());
+    let label_width = text_width(label);
+    let message_width = text_width(message);
+    let width = label_width + message_width;
+    #[allow(clippy::cast_precision_loss)]
+    let (label_x, message_x) = (
+        label_width as f64 / 2.0,
+        label_width as f64 + message_width as f64 / 2.0,
+    );
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{label}: {message}">
+  <title>{label}: {message}</title>
+  <linearGradient id="s" x2="0" y2="100%">
+    <stop offset="0" stop-color="#bbb" stop-opacity=".1"/>
+    <stop offset="1" stop-opacity=".1"/>
+  </linearGradient>
+  <clipPath id="r">
+    <rect width="{width}" height="20" rx="3" fill="#fff"/>
+  </clipPath>
+  <g clip-path="url(#r)">
+    <rect width="{label_width}" height="20" fill="#555"/>
+    <rect x="{label_width}" width="{message_width}" height="20" fill="{color}"/>
+    <rect width="{width}" height="20" fill="url(#s)"/>
+  </g>
+  <g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11">
+    <text x="{label_x}" y="15" fill="#010101" fill-opacity=".3">{label}</text>
+    <text x="{label_x}" y="14">{label}</text>
+    <text x="{message_x}" y="15" fill="#010101" fill-opacity=".3">{message}</text>
+    <text x="{message_x}" y="14">{message}</text>
+  </g>
+</svg>
+"##
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn task(triple: &str, success: Option<bool>) -> TaskStatus {
+        TaskStatus {
+            kind: "metadata".into(),
+            triple: triple.into(),
+            success,
+        }
+    }
+
+    /// Compare a rendered badge against its snapshot, or update the snapshot if
+    /// `UPDATE_SNAPSHOTS` is set.
+    fn assert_snapshot(name: &str, rendered: &str) {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("src/badge")
+            .join(format!("{name}.svg"));
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            std::fs::write(&path, rendered).unwrap();
+        }
+        let snapshot = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(rendered, snapshot, "{path:?}");
+    }
+
+    #[test]
+    fn can_summarize_status() {
+        let passing = task("x86_64-unknown-linux-gnu", Some(true));
+        let failing = task("aarch64-unknown-linux-gnu", Some(false));
+        let pending = task("x86_64-apple-darwin", None);
+        assert_eq!(BuildStatus::of(&[]), BuildStatus::NotBuilt);
+        assert_eq!(BuildStatus::of(&[passing.clone()]), BuildStatus::Passing);
+        assert_eq!(
+            BuildStatus::of(&[passing.clone(), pending.clone()]),
+            BuildStatus::Pending
+        );
+        assert_eq!(
+            BuildStatus::of(&[passing, pending, failing]),
+            BuildStatus::Failing
+        );
+    }
+
+    #[test]
+    fn can_render_badges() {
+        for (name, status) in [
+            ("passing", BuildStatus::Passing),
+            ("failing", BuildStatus::Failing),
+            ("pending", BuildStatus::Pending),
+            ("not-built", BuildStatus::NotBuilt),
+        ] {
+            assert_snapshot(name, &render(status));
+        }
+    }
+}
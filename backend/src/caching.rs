@@ -0,0 +1,152 @@
+//! HTTP caching
+//!
+//! Downloads carry a strong `ETag` derived from the SHA-256 hash of the artifact, and clients can
+//! revalidate them with `If-None-Match`. Rebuilding an artifact can change it, so even artifacts
+//! of exact versions are only cached for a bounded time.
+//! Downloads can be resumed with a `Range` request, guarded by `If-Range`.
+
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use buildsrs_storage::ByteRange;
+
+/// Cache control for artifacts with an exact version, which only change when they are rebuilt.
+pub const CACHE_VERSION: &str = "public, max-age=3600";
+
+/// Cache control for artifacts with a version requirement, which change on new releases.
+pub const CACHE_ALIAS: &str = "public, max-age=300";
+
+/// Cache control for badges, whose status changes as jobs run.
+pub const CACHE_BADGE: &str = "public, max-age=300";
+
+/// Strong entity tag of an artifact with the given hash.
+pub fn etag(hash: &str) -> String {
+    format!("\"{hash}\"")
+}
+
+/// Determine if an `If-None-Match` header matches the entity tag.
+///
+/// Uses weak comparison, as required for `If-None-Match`.
+pub fn none_match(headers: &HeaderMap, etag: &str) -> bool {
+    let weak = |tag: &str| tag.trim().trim_start_matches("W/").to_owned();
+    let etag = weak(etag);
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|tag| tag.trim() == "*" || weak(tag) == etag)
+}
+
+/// Respond that the resource has not been modified since the client cached it.
+pub fn not_modified(etag: &str, cache_control: &'static str) -> Response {
+    (
+        StatusCode::NOT_MODIFIED,
+        [
+            (header::ETAG, etag.to_owned()),
+            (header::CACHE_CONTROL, cache_control.into()),
+        ],
+    )
+        .into_response()
+}
+
+/// Requested range, if any.
+///
+/// The range is ignored unless it is a single byte range, and unless an `If-Range` header matches
+/// the entity tag using strong comparison. Dates in `If-Range` are not supported, so they never
+/// match and the whole artifact is served.
+pub fn range(headers: &HeaderMap, etag: &str) -> Option<ByteRange> {
+    if let Some(if_range) = headers.get(header::IF_RANGE) {
+        if if_range.as_bytes() != etag.as_bytes() {
+            return None;
+        }
+    }
+    parse_range(headers.get(header::RANGE)?)
+}
+
+/// Parse a `Range` header with a single byte range.
+fn parse_range(value: &HeaderValue) -> Option<ByteRange> {
+    let range = value.to_str().ok()?.trim().strip_prefix("bytes=")?;
+    if range.contains(',') {
+        return None;
+    }
+    let (start, end) = range.trim().split_once('-')?;
+    if start.is_empty() {
+        return Some(ByteRange::Suffix(end.parse().ok()?));
+    }
+    let start = start.parse().ok()?;
+    let end = match end {
+        "" => None,
+        end => Some(end.parse().ok()?),
+    };
+    Some(ByteRange::From { start, end })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(values: &[(header::HeaderName, &'static str)]) -> HeaderMap {
+        values
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    #[test]
+    fn can_match_etag() {
+        let etag = etag("abc");
+        assert_eq!(etag, "\"abc\"");
+        for value in ["\"abc\"", "W/\"abc\"", "\"def\", \"abc\"", "*"] {
+            let headers = headers(&[(header::IF_NONE_MATCH, value)]);
+            assert!(none_match(&headers, &etag), "{value}");
+        }
+        for value in ["\"def\"", "abc", ""] {
+            let headers = headers(&[(header::IF_NONE_MATCH, value)]);
+            assert!(!none_match(&headers, &etag), "{value}");
+        }
+        assert!(!none_match(&HeaderMap::new(), &etag));
+    }
+
+    #[test]
+    fn can_parse_range() {
+        let range = |value| range(&headers(&[(header::RANGE, value)]), &etag("abc"));
+        assert_eq!(
+            range("bytes=0-"),
+            Some(ByteRange::From {
+                start: 0,
+                end: None
+            })
+        );
+        assert_eq!(
+            range("bytes=2-5"),
+            Some(ByteRange::From {
+                start: 2,
+                end: Some(5)
+            })
+        );
+        assert_eq!(range("bytes=-3"), Some(ByteRange::Suffix(3)));
+        for value in [
+            "bytes=0-1,3-4",
+            "items=0-1",
+            "bytes=a-",
+            "bytes=-",
+            "bytes=5",
+        ] {
+            assert_eq!(range(value), None, "{value}");
+        }
+    }
+
+    #[test]
+    fn can_check_if_range() {
+        let etag = etag("abc");
+        let matching = headers(&[(header::RANGE, "bytes=-3"), (header::IF_RANGE, "\"abc\"")]);
+        assert_eq!(range(&matching, &etag), Some(ByteRange::Suffix(3)));
+
+        for value in ["\"def\"", "W/\"abc\"", "Wed, 21 Oct 2015 07:28:00 GMT"] {
+            let headers = headers(&[(header::RANGE, "bytes=-3"), (header::IF_RANGE, value)]);
+            assert_eq!(range(&headers, &etag), None, "{value}");
+        }
+    }
+}
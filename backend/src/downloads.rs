@@ -0,0 +1,140 @@
+//! Download counts
+//!
+//! Downloads of artifacts are counted in memory and written to the database in batches, so that
+//! serving an artifact does not wait for a database write.
+
+use buildsrs_common::entities::{ArtifactDownloads, ArtifactId};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Interval at which buffered download counts are written to the database.
+pub const DOWNLOADS_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Length of a day, in seconds.
+const DAY: u64 = 24 * 60 * 60;
+
+/// Start of the UTC day of a point in time, as a UNIX timestamp.
+fn day(time: SystemTime) -> i64 {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    i64::try_from(seconds - seconds % DAY).unwrap_or(i64::MAX)
+}
+
+/// Download counts which have not been written to the database yet, by artifact and day.
+#[derive(Clone, Debug, Default)]
+pub struct Downloads {
+    counts: Arc<Mutex<BTreeMap<(ArtifactId, i64), u64>>>,
+}
+
+impl Downloads {
+    /// Count a download of an artifact.
+    pub fn record(&self, artifact: &ArtifactId) {
+        self.record_at(artifact, SystemTime::now());
+    }
+
+    fn record_at(&self, artifact: &ArtifactId, time: SystemTime) {
+        *self
+            .counts
+            .lock()
+            .unwrap()
+            .entry((artifact.clone(), day(time)))
+            .or_default() += 1;
+    }
+
+    /// Take all buffered download counts.
+    pub fn take(&self) -> Vec<ArtifactDownloads> {
+        std::mem::take(&mut *self.counts.lock().unwrap())
+            .into_iter()
+            .map(|((artifact, date), downloads)| ArtifactDownloads {
+                artifact,
+                date,
+                downloads,
+            })
+            .collect()
+    }
+
+    /// Return download counts which could not be written, so that they are written later.
+    pub fn restore(&self, downloads: Vec<ArtifactDownloads>) {
+        let mut counts = self.counts.lock().unwrap();
+        for entry in downloads {
+            *counts.entry((entry.artifact, entry.date)).or_default() += entry.downloads;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buildsrs_common::entities::{ArtifactKind, Task};
+
+    fn artifact(kind: ArtifactKind) -> ArtifactId {
+        ArtifactId {
+            task: Task {
+                krate: "serde".into(),
+                version: "1.0.0".into(),
+                triple: "x86_64-unknown-linux-gnu".into(),
+                kind,
+            },
+        }
+    }
+
+    #[test]
+    fn can_compute_day() {
+        assert_eq!(day(UNIX_EPOCH), 0);
+        assert_eq!(day(UNIX_EPOCH + Duration::from_secs(DAY - 1)), 0);
+        assert_eq!(
+            day(UNIX_EPOCH + Duration::from_secs(3 * DAY + 5)),
+            3 * 86400
+        );
+    }
+
+    #[test]
+    fn can_count_downloads() {
+        let downloads = Downloads::default();
+        let metadata = artifact(ArtifactKind::Metadata);
+        let tarball = artifact(ArtifactKind::Tarball);
+        let time = UNIX_EPOCH + Duration::from_secs(DAY);
+        downloads.record_at(&metadata, time);
+        downloads.record_at(&metadata, time + Duration::from_secs(60));
+        downloads.record_at(&metadata, time + Duration::from_secs(DAY));
+        downloads.record_at(&tarball, time);
+
+        let entry = |artifact: &ArtifactId, date, downloads| ArtifactDownloads {
+            artifact: artifact.clone(),
+            date,
+            downloads,
+        };
+        assert_eq!(
+            downloads.take(),
+            [
+                entry(&metadata, 86400, 2),
+                entry(&metadata, 2 * 86400, 1),
+                entry(&tarball, 86400, 1),
+            ]
+        );
+        assert!(downloads.take().is_empty());
+    }
+
+    #[test]
+    fn can_restore_downloads() {
+        let downloads = Downloads::default();
+        let metadata = artifact(ArtifactKind::Metadata);
+        downloads.record_at(&metadata, UNIX_EPOCH);
+        let taken = downloads.take();
+        downloads.record_at(&metadata, UNIX_EPOCH);
+        downloads.restore(taken);
+        assert_eq!(
+            downloads.take(),
+            [ArtifactDownloads {
+                artifact: metadata,
+                date: 0,
+                downloads: 2,
+            }]
+        );
+    }
+}
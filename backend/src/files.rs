@@ -1,5 +1,10 @@
 use bytes::Bytes;
-use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
 
 /// Mapping of file path to content
 pub type Files = BTreeMap<PathBuf, Bytes>;
@@ -7,6 +12,53 @@ pub type Files = BTreeMap<PathBuf, Bytes>;
 /// Read-only, shared files
 pub type SharedFiles = Arc<Files>;
 
+/// Source of the frontend files.
+#[derive(Clone, Debug)]
+pub enum Frontend {
+    /// Files held in memory, such as the vendored frontend.
+    Files(SharedFiles),
+    /// Directory on disk, which files are read from on every request.
+    Directory(PathBuf),
+}
+
+impl Default for Frontend {
+    fn default() -> Self {
+        Self::Files(Default::default())
+    }
+}
+
+impl Frontend {
+    /// Read a file by its path relative to the frontend, returning `None` if it does not exist.
+    ///
+    /// Paths which are not relative, or which would escape the directory of the frontend, are
+    /// never read.
+    pub async fn read(&self, path: &Path) -> io::Result<Option<Bytes>> {
+        if !path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Ok(None);
+        }
+        match self {
+            Self::Files(files) => Ok(files.get(path).cloned()),
+            Self::Directory(root) => {
+                let resolved = match tokio::fs::canonicalize(root.join(path)).await {
+                    Ok(resolved) => resolved,
+                    Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+                    Err(error) => return Err(error),
+                };
+                // symbolic links could still point outside of the directory
+                if !resolved.starts_with(tokio::fs::canonicalize(root).await?)
+                    || !tokio::fs::metadata(&resolved).await?.is_file()
+                {
+                    return Ok(None);
+                }
+                Ok(Some(tokio::fs::read(&resolved).await?.into()))
+            }
+        }
+    }
+}
+
 #[cfg(feature = "frontend-vendor")]
 mod vendor {
     use super::{Bytes, Files, PathBuf};
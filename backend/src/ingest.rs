@@ -0,0 +1,208 @@
+//! Metadata ingestion
+//!
+//! Metadata jobs upload the output of `cargo metadata` for a crate version. The targets of the
+//! crate found in it are recorded, and determine which follow-up tasks are created: binary crates
+//! get binary releases and Debian packages, library crates get coverage reports, and crates with
+//! a `cdylib` target also get library releases.
+
+use buildsrs_common::entities::{ArtifactKind, CrateTarget};
+use buildsrs_database::{BoxError, WriteHandle};
+use cargo_metadata::Metadata;
+use std::collections::BTreeSet;
+
+/// Kinds of targets which are recorded.
+const TARGET_KINDS: [&str; 3] = ["bin", "lib", "cdylib"];
+
+/// Triple of tasks which do not depend on the platform, such as building metadata.
+const GENERIC_TRIPLE: &str = "generic";
+
+/// Error in metadata ingestion.
+#[derive(thiserror::Error, Debug)]
+pub enum IngestError {
+    /// Metadata is not valid cargo metadata output
+    #[error("invalid metadata: {0}")]
+    Invalid(#[from] serde_json::Error),
+    /// Metadata has no package for the crate version
+    #[error("metadata does not describe {0} {1}")]
+    MissingPackage(String, String),
+}
+
+/// Find the targets of a crate version in its metadata.
+///
+/// Only targets of the crate itself are considered, not those of its dependencies.
+pub fn targets(data: &[u8], krate: &str, version: &str) -> Result<Vec<CrateTarget>, IngestError> {
+    let metadata: Metadata = serde_json::from_slice(data)?;
+    let package = metadata
+        .packages
+        .iter()
+        .find(|package| package.name == krate && package.version.to_string() == version)
+        .ok_or_else(|| IngestError::MissingPackage(krate.into(), version.into()))?;
+    let targets: BTreeSet<CrateTarget> = package
+        .targets
+        .iter()
+        .flat_map(|target| {
+            target
+                .kind
+                .iter()
+                .filter(|kind| TARGET_KINDS.contains(&kind.as_str()))
+                .map(|kind| CrateTarget {
+                    name: target.name.clone(),
+                    kind: kind.clone(),
+                })
+        })
+        .collect();
+    Ok(targets.into_iter().collect())
+}
+
+/// Kinds of tasks to create for a crate with the given targets.
+pub fn tasks(targets: &[CrateTarget]) -> BTreeSet<ArtifactKind> {
+    let mut tasks = BTreeSet::new();
+    for target in targets {
+        match target.kind.as_str() {
+            "bin" => tasks.extend([ArtifactKind::Tarball, ArtifactKind::Debian]),
+            "lib" => tasks.extend([ArtifactKind::Coverage]),
+            "cdylib" => tasks.extend([ArtifactKind::Tarball, ArtifactKind::Coverage]),
+            _ => {}
+        }
+    }
+    tasks
+}
+
+/// Determine if tasks of a kind are built for a triple.
+///
+/// Debian packages are only built for Linux.
+fn builds_for(kind: ArtifactKind, triple: &str) -> bool {
+    match kind {
+        ArtifactKind::Debian => triple.split('-').nth(2) == Some("linux"),
+        _ => true,
+    }
+}
+
+/// Record the targets of a crate version, and create its follow-up tasks for all enabled triples.
+pub async fn ingest(
+    writer: &dyn WriteHandle,
+    krate: &str,
+    version: &str,
+    targets: &[CrateTarget],
+) -> Result<(), BoxError> {
+    for target in targets {
+        writer
+            .crate_version_target_add(krate, version, target)
+            .await?;
+    }
+
+    let kinds = tasks(targets);
+    for triple in writer.triple_list().await? {
+        if triple == GENERIC_TRIPLE || !writer.triple_info(&triple).await?.enabled {
+            continue;
+        }
+        for &kind in &kinds {
+            if builds_for(kind, &triple) {
+                writer
+                    .task_create(krate, version, kind.as_ref(), &triple)
+                    .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn target(name: &str, kind: &str) -> CrateTarget {
+        CrateTarget {
+            name: name.into(),
+            kind: kind.into(),
+        }
+    }
+
+    fn metadata() -> Vec<u8> {
+        let package = |name: &str, targets: serde_json::Value| {
+            json!({
+                "name": name,
+                "version": "1.0.0",
+                "id": format!("{name} 1.0.0"),
+                "dependencies": [],
+                "targets": targets,
+                "features": {},
+                "manifest_path": format!("/{name}/Cargo.toml"),
+            })
+        };
+        let target = |name: &str, kind: &[&str]| {
+            json!({
+                "name": name,
+                "kind": kind,
+                "src_path": format!("/src/{name}.rs"),
+            })
+        };
+        serde_json::to_vec(&json!({
+            "packages": [
+                package("dependency", json!([target("dependency", &["lib"])])),
+                package("ripgrep", json!([
+                    target("rg", &["bin"]),
+                    target("ripgrep", &["lib", "cdylib"]),
+                    target("build-script-build", &["custom-build"]),
+                    target("integration", &["test"]),
+                ])),
+            ],
+            "workspace_members": ["ripgrep 1.0.0"],
+            "resolve": null,
+            "workspace_root": "/ripgrep",
+            "target_directory": "/ripgrep/target",
+            "version": 1,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn can_find_targets() {
+        let targets = targets(&metadata(), "ripgrep", "1.0.0").unwrap();
+        assert_eq!(
+            targets,
+            [
+                target("rg", "bin"),
+                target("ripgrep", "cdylib"),
+                target("ripgrep", "lib"),
+            ]
+        );
+    }
+
+    #[test]
+    fn cannot_find_targets_missing() {
+        assert!(matches!(
+            targets(&metadata(), "ripgrep", "2.0.0"),
+            Err(IngestError::MissingPackage(..))
+        ));
+        assert!(matches!(
+            targets(b"{}", "ripgrep", "1.0.0"),
+            Err(IngestError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn can_determine_tasks() {
+        assert_eq!(
+            tasks(&[target("rg", "bin")]),
+            [ArtifactKind::Tarball, ArtifactKind::Debian].into()
+        );
+        assert_eq!(
+            tasks(&[target("serde", "lib")]),
+            [ArtifactKind::Coverage].into()
+        );
+        assert_eq!(
+            tasks(&[target("ffi", "cdylib")]),
+            [ArtifactKind::Tarball, ArtifactKind::Coverage].into()
+        );
+        assert!(tasks(&[]).is_empty());
+    }
+
+    #[test]
+    fn can_check_triple() {
+        assert!(builds_for(ArtifactKind::Debian, "x86_64-unknown-linux-gnu"));
+        assert!(!builds_for(ArtifactKind::Debian, "x86_64-apple-darwin"));
+        assert!(builds_for(ArtifactKind::Tarball, "x86_64-apple-darwin"));
+    }
+}
@@ -9,12 +9,25 @@
 //! traits.
 
 mod api;
+mod badge;
+mod caching;
+mod downloads;
 mod files;
+mod ingest;
+mod logs;
+mod metrics;
+mod resolve;
 mod state;
+mod tokens;
+mod uploads;
 
 #[cfg(feature = "frontend-vendor")]
 pub use crate::files::frontend;
 pub use crate::{
-    files::{Files, SharedFiles},
+    api::WebSocketError,
+    downloads::DOWNLOADS_FLUSH_INTERVAL,
+    files::{Files, Frontend, SharedFiles},
+    metrics::Metrics,
     state::Backend,
+    tokens::{JobTokens, JOB_TOKEN_VALIDITY},
 };
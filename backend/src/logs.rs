@@ -0,0 +1,132 @@
+//! Live job logs
+//!
+//! Log lines received from builders are stored in the database, and also published here so that
+//! clients watching a job receive them as they arrive, without polling the database.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// Number of events buffered for each subscriber before it starts missing events.
+const CAPACITY: usize = 1024;
+
+/// Event in the log of a job.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LogEvent {
+    /// Line was added to the log.
+    Line {
+        /// ID of the line in the database
+        id: i64,
+        /// Stage the job was in
+        stage: String,
+        /// Contents of the line
+        line: String,
+    },
+    /// Job has ended, no more lines will be added.
+    End {
+        /// Whether the job was successful
+        success: bool,
+    },
+}
+
+/// Subscribers to the logs of running jobs.
+#[derive(Clone, Debug, Default)]
+pub struct JobLogs {
+    jobs: Arc<Mutex<BTreeMap<Uuid, broadcast::Sender<LogEvent>>>>,
+}
+
+impl JobLogs {
+    /// Subscribe to the log events of a job.
+    pub fn subscribe(&self, job: Uuid) -> broadcast::Receiver<LogEvent> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .entry(job)
+            .or_insert_with(|| broadcast::channel(CAPACITY).0)
+            .subscribe()
+    }
+
+    /// Publish a log event of a job to its subscribers.
+    ///
+    /// Once the job has ended, or if it has no subscribers left, it is forgotten.
+    pub fn publish(&self, job: Uuid, event: LogEvent) {
+        let mut jobs = self.jobs.lock().unwrap();
+        let Some(sender) = jobs.get(&job) else {
+            return;
+        };
+        let ended = matches!(event, LogEvent::End { .. });
+        if sender.send(event).is_err() || ended {
+            jobs.remove(&job);
+        }
+    }
+
+    /// Forget a job if it has no subscribers left.
+    pub fn prune(&self, job: Uuid) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs
+            .get(&job)
+            .is_some_and(|sender| sender.receiver_count() == 0)
+        {
+            jobs.remove(&job);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::broadcast::error::TryRecvError;
+
+    fn line(id: i64) -> LogEvent {
+        LogEvent::Line {
+            id,
+            stage: "build".into(),
+            line: format!("line {id}"),
+        }
+    }
+
+    #[test]
+    fn subscribers_receive_events() {
+        let logs = JobLogs::default();
+        let job = Uuid::new_v4();
+        let mut first = logs.subscribe(job);
+        let mut second = logs.subscribe(job);
+        logs.publish(job, line(1));
+        assert_eq!(first.try_recv().unwrap(), line(1));
+        assert_eq!(second.try_recv().unwrap(), line(1));
+
+        // events of other jobs are not received
+        logs.publish(Uuid::new_v4(), line(2));
+        assert_eq!(first.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn end_closes_subscribers() {
+        let logs = JobLogs::default();
+        let job = Uuid::new_v4();
+        let mut receiver = logs.subscribe(job);
+        logs.publish(job, LogEvent::End { success: true });
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            LogEvent::End { success: true }
+        );
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Closed));
+        assert!(logs.jobs.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn forgets_jobs_without_subscribers() {
+        let logs = JobLogs::default();
+        let job = Uuid::new_v4();
+        drop(logs.subscribe(job));
+        logs.publish(job, line(1));
+        assert!(logs.jobs.lock().unwrap().is_empty());
+
+        drop(logs.subscribe(job));
+        logs.prune(job);
+        assert!(logs.jobs.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,389 @@
+//! Prometheus metrics
+//!
+//! Metrics are recorded while handling requests and builder connections, and exposed in the
+//! Prometheus text format at `/metrics`. Values owned by other components, such as the statistics
+//! of the storage cache and of the database pool, are read whenever the metrics are scraped.
+
+use buildsrs_database::AnyMetadata;
+use buildsrs_storage::{
+    AnyStorage, ArtifactData, ArtifactId, ByteRange, CacheStats, Storage, StorageError,
+};
+use prometheus_client::{
+    collector::Collector,
+    encoding::{text::encode, DescriptorEncoder, EncodeLabelSet, EncodeMetric},
+    metrics::{
+        counter::{ConstCounter, Counter},
+        family::Family,
+        gauge::{ConstGauge, Gauge},
+        histogram::{exponential_buckets, Histogram},
+    },
+    registry::Registry,
+};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Prefix of the names of all metrics.
+const PREFIX: &str = "buildsrs";
+
+/// Family of histograms, which need a constructor to set their buckets.
+type HistogramFamily<L> = Family<L, Histogram, fn() -> Histogram>;
+
+/// Histogram for durations, with buckets from 1ms to about 30s.
+fn duration_histogram() -> Histogram {
+    Histogram::new(exponential_buckets(0.001, 2.0, 16))
+}
+
+/// Labels of a handled HTTP request.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct RequestLabels {
+    method: String,
+    route: String,
+    status: u16,
+}
+
+/// Labels of the route of an HTTP request.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct RouteLabels {
+    method: String,
+    route: String,
+}
+
+/// Labels of a job or task, by the triple it is built for.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct TripleLabels {
+    triple: String,
+}
+
+/// Labels of a storage operation.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct StorageLabels {
+    backend: &'static str,
+    operation: &'static str,
+}
+
+#[derive(Debug)]
+struct Inner {
+    registry: Registry,
+    requests: Family<RequestLabels, Counter>,
+    request_duration: HistogramFamily<RouteLabels>,
+    builders_connected: Gauge,
+    jobs_dispatched: Family<TripleLabels, Counter>,
+    jobs_completed: Family<TripleLabels, Counter>,
+    jobs_failed: Family<TripleLabels, Counter>,
+    tasks_pending: Family<TripleLabels, Gauge>,
+    storage_duration: HistogramFamily<StorageLabels>,
+    storage_errors: Family<StorageLabels, Counter>,
+}
+
+/// Metrics of the backend.
+#[derive(Clone, Debug)]
+pub struct Metrics {
+    inner: Arc<Inner>,
+}
+
+impl Metrics {
+    /// Create metrics, reading the status of the database pool and storage cache when scraped.
+    pub fn new(database: AnyMetadata, storage: AnyStorage) -> Self {
+        let mut inner = Inner {
+            registry: Registry::with_prefix(PREFIX),
+            requests: Default::default(),
+            request_duration: Family::new_with_constructor(duration_histogram),
+            builders_connected: Default::default(),
+            jobs_dispatched: Default::default(),
+            jobs_completed: Default::default(),
+            jobs_failed: Default::default(),
+            tasks_pending: Default::default(),
+            storage_duration: Family::new_with_constructor(duration_histogram),
+            storage_errors: Default::default(),
+        };
+
+        inner.registry.register(
+            "http_requests",
+            "Number of handled HTTP requests",
+            inner.requests.clone(),
+        );
+        inner.registry.register(
+            "http_request_duration_seconds",
+            "Time taken to handle HTTP requests",
+            inner.request_duration.clone(),
+        );
+        inner.registry.register(
+            "builders_connected",
+            "Number of connected builders",
+            inner.builders_connected.clone(),
+        );
+        inner.registry.register(
+            "jobs_dispatched",
+            "Number of jobs handed out to builders",
+            inner.jobs_dispatched.clone(),
+        );
+        inner.registry.register(
+            "jobs_completed",
+            "Number of jobs which were completed successfully",
+            inner.jobs_completed.clone(),
+        );
+        inner.registry.register(
+            "jobs_failed",
+            "Number of jobs which failed",
+            inner.jobs_failed.clone(),
+        );
+        inner.registry.register(
+            "tasks_pending",
+            "Number of tasks waiting for a job",
+            inner.tasks_pending.clone(),
+        );
+        inner.registry.register(
+            "storage_operation_duration_seconds",
+            "Time taken by storage operations",
+            inner.storage_duration.clone(),
+        );
+        inner.registry.register(
+            "storage_operation_errors",
+            "Number of failed storage operations",
+            inner.storage_errors.clone(),
+        );
+        inner
+            .registry
+            .register_collector(Box::new(StatusCollector { database, storage }));
+
+        Self {
+            inner: Arc::new(inner),
+        }
+    }
+
+    /// Record a handled HTTP request.
+    pub fn request(&self, method: &str, route: &str, status: u16, duration: Duration) {
+        self.inner
+            .requests
+            .get_or_create(&RequestLabels {
+                method: method.into(),
+                route: route.into(),
+                status,
+            })
+            .inc();
+        self.inner
+            .request_duration
+            .get_or_create(&RouteLabels {
+                method: method.into(),
+                route: route.into(),
+            })
+            .observe(duration.as_secs_f64());
+    }
+
+    /// Record a connected builder, until the returned guard is dropped.
+    pub fn builder_connected(&self) -> BuilderConnected {
+        self.inner.builders_connected.inc();
+        BuilderConnected {
+            metrics: self.clone(),
+        }
+    }
+
+    /// Record a job handed out to a builder.
+    pub fn job_dispatched(&self, triple: &str) {
+        self.inner
+            .jobs_dispatched
+            .get_or_create(&TripleLabels {
+                triple: triple.into(),
+            })
+            .inc();
+    }
+
+    /// Record a job which has ended.
+    pub fn job_ended(&self, triple: &str, success: bool) {
+        let family = if success {
+            &self.inner.jobs_completed
+        } else {
+            &self.inner.jobs_failed
+        };
+        family
+            .get_or_create(&TripleLabels {
+                triple: triple.into(),
+            })
+            .inc();
+    }
+
+    /// Replace the number of pending tasks of every triple.
+    pub fn tasks_pending(&self, queue: &BTreeMap<String, u64>) {
+        self.inner.tasks_pending.clear();
+        for (triple, &count) in queue {
+            self.inner
+                .tasks_pending
+                .get_or_create(&TripleLabels {
+                    triple: triple.clone(),
+                })
+                .set(i64::try_from(count).unwrap_or(i64::MAX));
+        }
+    }
+
+    /// Record a storage operation.
+    fn storage_operation<T>(
+        &self,
+        labels: &StorageLabels,
+        started: Instant,
+        result: &Result<T, StorageError>,
+    ) {
+        self.inner
+            .storage_duration
+            .get_or_create(labels)
+            .observe(started.elapsed().as_secs_f64());
+        // missing artifacts are expected, and not counted as errors
+        if let Err(StorageError::Other(_)) = result {
+            self.inner.storage_errors.get_or_create(labels).inc();
+        }
+    }
+
+    /// Encode all metrics in the Prometheus text format.
+    pub fn encode(&self) -> String {
+        let mut output = String::new();
+        encode(&mut output, &self.inner.registry).expect("writing to string cannot fail");
+        output
+    }
+}
+
+/// Guard which keeps a builder counted as connected.
+#[derive(Debug)]
+pub struct BuilderConnected {
+    metrics: Metrics,
+}
+
+impl Drop for BuilderConnected {
+    fn drop(&mut self) {
+        self.metrics.inner.builders_connected.dec();
+    }
+}
+
+/// Collects the status of the database pool and storage cache when scraped.
+#[derive(Debug)]
+struct StatusCollector {
+    database: AnyMetadata,
+    storage: AnyStorage,
+}
+
+/// Encode a single metric without labels.
+fn encode_metric(
+    encoder: &mut DescriptorEncoder,
+    name: &str,
+    help: &str,
+    metric: &impl EncodeMetric,
+) -> Result<(), fmt::Error> {
+    let metric_encoder = encoder.encode_descriptor(name, help, None, metric.metric_type())?;
+    metric.encode(metric_encoder)
+}
+
+impl Collector for StatusCollector {
+    fn encode(&self, mut encoder: DescriptorEncoder) -> Result<(), fmt::Error> {
+        if let Some(status) = self.database.status() {
+            let gauge = |value: usize| ConstGauge::new(i64::try_from(value).unwrap_or(i64::MAX));
+            encode_metric(
+                &mut encoder,
+                "database_connections",
+                "Number of connections in the database pool",
+                &gauge(status.size),
+            )?;
+            encode_metric(
+                &mut encoder,
+                "database_connections_idle",
+                "Number of idle connections in the database pool",
+                &gauge(status.available),
+            )?;
+            encode_metric(
+                &mut encoder,
+                "database_connections_waiting",
+                "Number of requests waiting for a database connection",
+                &gauge(status.waiting),
+            )?;
+        }
+
+        if let Some(CacheStats { hits, misses }) = self.storage.cache_stats() {
+            encode_metric(
+                &mut encoder,
+                "storage_cache_hits",
+                "Number of artifact lookups answered from the cache",
+                &ConstCounter::new(hits),
+            )?;
+            encode_metric(
+                &mut encoder,
+                "storage_cache_misses",
+                "Number of artifact lookups not answered from the cache",
+                &ConstCounter::new(misses),
+            )?;
+            #[allow(clippy::cast_precision_loss)]
+            let ratio = match hits + misses {
+                0 => 0.0,
+                total => hits as f64 / total as f64,
+            };
+            encode_metric(
+                &mut encoder,
+                "storage_cache_hit_ratio",
+                "Ratio of artifact lookups answered from the cache",
+                &ConstGauge::new(ratio),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Storage layer which records the duration and errors of operations.
+#[derive(Debug)]
+pub struct MeteredStorage {
+    storage: AnyStorage,
+    metrics: Metrics,
+}
+
+impl MeteredStorage {
+    /// Record the operations of a storage.
+    pub fn new(storage: AnyStorage, metrics: Metrics) -> Self {
+        Self { storage, metrics }
+    }
+
+    fn labels(&self, operation: &'static str) -> StorageLabels {
+        StorageLabels {
+            backend: self.storage.kind(),
+            operation,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Storage for MeteredStorage {
+    async fn artifact_put(&self, version: &ArtifactId, data: &[u8]) -> Result<(), StorageError> {
+        let started = Instant::now();
+        let result = self.storage.artifact_put(version, data).await;
+        self.metrics
+            .storage_operation(&self.labels("put"), started, &result);
+        result
+    }
+
+    async fn artifact_get(&self, version: &ArtifactId) -> Result<ArtifactData, StorageError> {
+        let started = Instant::now();
+        let result = self.storage.artifact_get(version).await;
+        self.metrics
+            .storage_operation(&self.labels("get"), started, &result);
+        result
+    }
+
+    async fn artifact_get_range(
+        &self,
+        version: &ArtifactId,
+        range: ByteRange,
+    ) -> Result<ArtifactData, StorageError> {
+        let started = Instant::now();
+        let result = self.storage.artifact_get_range(version, range).await;
+        self.metrics
+            .storage_operation(&self.labels("get_range"), started, &result);
+        result
+    }
+
+    fn kind(&self) -> &'static str {
+        self.storage.kind()
+    }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        self.storage.cache_stats()
+    }
+}
@@ -1,5 +1,5 @@
 use anyhow::Result;
-use buildsrs_backend::Backend;
+use buildsrs_backend::{Backend, JobTokens};
 use buildsrs_database::DatabaseOptions;
 use buildsrs_storage::StorageOptions;
 use clap::Parser;
@@ -15,16 +15,44 @@ pub struct Options {
 
     #[clap(flatten)]
     pub database: DatabaseOptions,
+
+    /// Secret used to sign job tokens, random if unset.
+    ///
+    /// Backends sharing a secret accept each other's job tokens.
+    #[clap(long, env = "BUILDSRS_JOB_TOKEN_SECRET")]
+    pub job_token_secret: Option<String>,
+
+    /// Identity of this server, which builder authentication challenges are bound to.
+    ///
+    /// This must be the host (and port, if any) that builders connect to.
+    #[clap(long, env = "BUILDSRS_SERVER_NAME", default_value = "localhost:8000")]
+    pub server_name: String,
+
+    /// Directory to serve the frontend from, replacing the vendored frontend.
+    #[cfg(feature = "frontend")]
+    #[clap(long, env = "BUILDSRS_FRONTEND_PATH")]
+    pub frontend_path: Option<std::path::PathBuf>,
 }
 
 impl Options {
     pub async fn build(&self) -> Result<Backend> {
         let database = self.database.build().await.unwrap();
         let storage = self.storage.build().await.unwrap();
-        let backend = Backend::new(database, storage);
+        let mut backend = Backend::new(database, storage).with_server_name(&self.server_name);
+        if let Some(secret) = &self.job_token_secret {
+            backend = backend.with_job_tokens(JobTokens::new(secret.as_bytes()));
+        }
 
         #[cfg(feature = "frontend-vendor")]
-        let backend = backend.with_frontend(buildsrs_backend::frontend().into());
+        let mut backend = backend.with_frontend(buildsrs_backend::frontend().into());
+
+        #[cfg(feature = "frontend")]
+        if let Some(path) = &self.frontend_path {
+            if !path.is_dir() {
+                anyhow::bail!("frontend path {} is not a directory", path.display());
+            }
+            backend = backend.with_frontend_path(path);
+        }
 
         Ok(backend)
     }
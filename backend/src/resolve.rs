@@ -0,0 +1,175 @@
+//! Version resolution
+//!
+//! Download routes accept a version selector in place of a version: either an exact version such
+//! as `1.2.3`, `latest`, or a version requirement such as `^1.2` or `=1.2.3`. Selectors other than
+//! exact versions are resolved to the newest matching version, among the versions which have been
+//! built, so that downloads fall back to an older version if the newest one has no artifact yet.
+
+use buildsrs_common::entities::{ArtifactVersion, VersionInfo};
+use semver::{Version, VersionReq};
+use std::str::FromStr;
+
+/// Selects the version of a crate to download.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VersionSelector {
+    /// Exact version, which is looked up as-is even if it is yanked.
+    Exact(String),
+    /// Version requirement, which `latest` is an alias for.
+    Requirement(VersionReq),
+}
+
+impl FromStr for VersionSelector {
+    type Err = semver::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        if input == "latest" {
+            return Ok(Self::Requirement(VersionReq::STAR));
+        }
+        if Version::parse(input).is_ok() {
+            return Ok(Self::Exact(input.into()));
+        }
+        Ok(Self::Requirement(input.parse()?))
+    }
+}
+
+/// Version of a crate which a selector can resolve to.
+pub trait Candidate {
+    /// Version string, which may not be valid semver.
+    fn version(&self) -> &str;
+    /// Whether this version is yanked.
+    fn yanked(&self) -> bool;
+}
+
+impl Candidate for VersionInfo {
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn yanked(&self) -> bool {
+        self.yanked
+    }
+}
+
+impl Candidate for ArtifactVersion {
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn yanked(&self) -> bool {
+        self.yanked
+    }
+}
+
+impl VersionSelector {
+    /// Newest of the versions which matches this selector.
+    ///
+    /// Exact versions match even if they are yanked. Requirements skip yanked versions, as well as
+    /// pre-releases unless the requirement explicitly asks for them. Versions which are not valid
+    /// semver are ignored.
+    pub fn resolve<'a, T: Candidate>(&self, versions: &'a [T]) -> Option<&'a T> {
+        let requirement = match self {
+            Self::Exact(version) => return versions.iter().find(|info| info.version() == version),
+            Self::Requirement(requirement) => requirement,
+        };
+        versions
+            .iter()
+            .filter(|info| !info.yanked())
+            .filter_map(|info| Some((Version::parse(info.version()).ok()?, info)))
+            .filter(|(version, _)| requirement.matches(version))
+            .max_by(|a, b| a.0.cmp(&b.0))
+            .map(|(_, info)| info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versions() -> Vec<VersionInfo> {
+        [
+            ("0.9.0", false),
+            ("1.2.0", false),
+            ("1.10.0", false),
+            ("1.2.3", false),
+            ("1.3.0", true),
+            ("2.0.0-beta.1", false),
+            ("invalid", false),
+        ]
+        .into_iter()
+        .map(|(version, yanked)| VersionInfo {
+            name: "serde".into(),
+            version: version.into(),
+            checksum: "abcdef".into(),
+            yanked,
+        })
+        .collect()
+    }
+
+    fn resolve<T: Candidate>(selector: &str, versions: &[T]) -> Option<String> {
+        selector
+            .parse::<VersionSelector>()
+            .unwrap()
+            .resolve(versions)
+            .map(|info| info.version().into())
+    }
+
+    #[test]
+    fn selector_parse() {
+        assert_eq!(
+            "1.2.3".parse::<VersionSelector>().unwrap(),
+            VersionSelector::Exact("1.2.3".into())
+        );
+        assert_eq!(
+            "latest".parse::<VersionSelector>().unwrap(),
+            VersionSelector::Requirement(VersionReq::STAR)
+        );
+        assert!(matches!(
+            "^1.2".parse::<VersionSelector>().unwrap(),
+            VersionSelector::Requirement(_)
+        ));
+        assert!("newest".parse::<VersionSelector>().is_err());
+    }
+
+    #[test]
+    fn latest_uses_semver_order() {
+        assert_eq!(resolve("latest", &versions()).as_deref(), Some("1.10.0"));
+    }
+
+    #[test]
+    fn requirement_skips_yanked() {
+        assert_eq!(resolve("^1.2", &versions()).as_deref(), Some("1.10.0"));
+        assert_eq!(resolve("=1.3.0", &versions()), None);
+    }
+
+    #[test]
+    fn requirement_includes_requested_prerelease() {
+        assert_eq!(
+            resolve("^2.0.0-beta", &versions()).as_deref(),
+            Some("2.0.0-beta.1")
+        );
+        assert_eq!(resolve("^2", &versions()), None);
+    }
+
+    #[test]
+    fn resolve_newest_built() {
+        let built: Vec<ArtifactVersion> = versions()
+            .into_iter()
+            .filter(|info| info.version != "1.10.0")
+            .map(|info| ArtifactVersion {
+                version: info.version,
+                yanked: info.yanked,
+                hash: "abcdef".into(),
+            })
+            .collect();
+        assert_eq!(resolve("latest", &built).as_deref(), Some("1.2.3"));
+        assert_eq!(resolve("^0.9", &built).as_deref(), Some("0.9.0"));
+        assert_eq!(resolve("1.10.0", &built), None);
+        assert_eq!(resolve("^3", &built), None);
+    }
+
+    #[test]
+    fn exact_matches_yanked() {
+        assert_eq!(resolve("1.3.0", &versions()).as_deref(), Some("1.3.0"));
+        assert_eq!(resolve("3.0.0", &versions()), None);
+    }
+}
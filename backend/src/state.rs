@@ -1,7 +1,21 @@
+use crate::{
+    downloads::Downloads,
+    logs::JobLogs,
+    metrics::{MeteredStorage, Metrics},
+    tokens::JobTokens,
+    uploads::Uploads,
+};
 #[cfg(feature = "frontend")]
-use crate::SharedFiles;
+use crate::{Frontend, SharedFiles};
 use buildsrs_database::AnyMetadata;
 use buildsrs_storage::AnyStorage;
+use std::{sync::Arc, time::Duration};
+
+/// Default time to wait for a database connection to become available.
+const DATABASE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default identity of this server, which builder authentication challenges are bound to.
+const SERVER_NAME: &str = "localhost:8000";
 
 /// Backend state.
 ///
@@ -10,16 +24,33 @@ use buildsrs_storage::AnyStorage;
 pub struct Backend {
     database: AnyMetadata,
     storage: AnyStorage,
+    database_timeout: Duration,
+    server_name: String,
+    job_tokens: JobTokens,
+    job_logs: JobLogs,
+    uploads: Uploads,
+    downloads: Downloads,
+    metrics: Metrics,
     #[cfg(feature = "frontend")]
-    frontend: SharedFiles,
+    frontend: Frontend,
 }
 
 impl Backend {
     /// Create new backend state from a database connection and storage instance.
+    ///
+    /// Operations on the storage are recorded in the metrics of the backend.
     pub fn new(database: AnyMetadata, storage: AnyStorage) -> Self {
+        let metrics = Metrics::new(database.clone(), storage.clone());
         Backend {
             database,
-            storage,
+            storage: Arc::new(MeteredStorage::new(storage, metrics.clone())),
+            metrics,
+            database_timeout: DATABASE_TIMEOUT,
+            server_name: SERVER_NAME.into(),
+            job_tokens: Default::default(),
+            job_logs: Default::default(),
+            uploads: Default::default(),
+            downloads: Default::default(),
             #[cfg(feature = "frontend")]
             frontend: Default::default(),
         }
@@ -29,12 +60,54 @@ impl Backend {
     #[cfg(feature = "frontend")]
     #[must_use]
     pub fn with_frontend(self, frontend: SharedFiles) -> Self {
-        Self { frontend, ..self }
+        Self {
+            frontend: Frontend::Files(frontend),
+            ..self
+        }
+    }
+
+    /// Serve frontend files from a directory, reading them on every request.
+    #[cfg(feature = "frontend")]
+    #[must_use]
+    pub fn with_frontend_path(self, path: impl Into<std::path::PathBuf>) -> Self {
+        Self {
+            frontend: Frontend::Directory(path.into()),
+            ..self
+        }
+    }
+
+    /// Replace time to wait for a database connection to become available.
+    #[must_use]
+    pub fn with_database_timeout(self, database_timeout: Duration) -> Self {
+        Self {
+            database_timeout,
+            ..self
+        }
+    }
+
+    /// Replace identity of this server, which builder authentication challenges are bound to.
+    ///
+    /// This must match the host (and port, if any) that builders connect to.
+    #[must_use]
+    pub fn with_server_name(self, server_name: impl Into<String>) -> Self {
+        Self {
+            server_name: server_name.into(),
+            ..self
+        }
+    }
+
+    /// Replace issuer of job tokens.
+    ///
+    /// By default, job tokens are signed with a random secret, so they are only accepted by
+    /// this instance.
+    #[must_use]
+    pub fn with_job_tokens(self, job_tokens: JobTokens) -> Self {
+        Self { job_tokens, ..self }
     }
 
     /// Frontend files
     #[cfg(feature = "frontend")]
-    pub fn frontend(&self) -> &SharedFiles {
+    pub fn frontend(&self) -> &Frontend {
         &self.frontend
     }
 
@@ -43,8 +116,43 @@ impl Backend {
         &self.database
     }
 
+    /// Time to wait for a database connection to become available.
+    pub fn database_timeout(&self) -> Duration {
+        self.database_timeout
+    }
+
+    /// Identity of this server, which builder authentication challenges are bound to.
+    pub fn server_name(&self) -> &str {
+        &self.server_name
+    }
+
     /// Return a reference to the storage.
     pub fn storage(&self) -> &AnyStorage {
         &self.storage
     }
+
+    /// Return a reference to the issuer of job tokens.
+    pub fn job_tokens(&self) -> &JobTokens {
+        &self.job_tokens
+    }
+
+    /// Return a reference to the metrics.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Return a reference to the subscribers to job logs.
+    pub(crate) fn job_logs(&self) -> &JobLogs {
+        &self.job_logs
+    }
+
+    /// Return a reference to the partial artifact uploads.
+    pub(crate) fn uploads(&self) -> &Uploads {
+        &self.uploads
+    }
+
+    /// Return a reference to the download counts which have not been written yet.
+    pub(crate) fn downloads(&self) -> &Downloads {
+        &self.downloads
+    }
 }
@@ -0,0 +1,160 @@
+//! Job tokens
+//!
+//! Every job handed out to a builder comes with a short-lived token, which authorizes uploading
+//! artifacts of that job over HTTP. Tokens are signed with a secret key of the backend, so that
+//! they can be verified without storing them.
+
+use hmac::{Hmac, Mac};
+use rand_core::{OsRng, RngCore};
+use sha2::Sha256;
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+/// Duration for which a job token is valid after it was issued.
+pub const JOB_TOKEN_VALIDITY: Duration = Duration::from_secs(60 * 60);
+
+/// Length of the randomly generated secret key, in bytes.
+const SECRET_LENGTH: usize = 32;
+
+/// Error verifying a job token.
+#[derive(thiserror::Error, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TokenError {
+    /// Token could not be parsed
+    #[error("job token is malformed")]
+    Malformed,
+    /// Signature of the token does not match the job
+    #[error("job token is invalid")]
+    Invalid,
+    /// Token is past its expiry time
+    #[error("job token has expired")]
+    Expired,
+}
+
+/// Issues and verifies job tokens.
+#[derive(Clone)]
+pub struct JobTokens {
+    secret: Arc<[u8]>,
+}
+
+impl fmt::Debug for JobTokens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JobTokens").finish_non_exhaustive()
+    }
+}
+
+impl Default for JobTokens {
+    fn default() -> Self {
+        let mut secret = [0; SECRET_LENGTH];
+        OsRng.fill_bytes(&mut secret);
+        Self::new(&secret)
+    }
+}
+
+impl JobTokens {
+    /// Create new job tokens signed with the given secret.
+    ///
+    /// Backends which share a secret accept each other's tokens.
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            secret: secret.into(),
+        }
+    }
+
+    fn mac(&self, job: Uuid, expires: u64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).unwrap();
+        mac.update(job.as_bytes());
+        mac.update(&expires.to_be_bytes());
+        mac
+    }
+
+    /// Issue a token for the given job at the given time.
+    pub fn issue(&self, job: Uuid, now: SystemTime) -> String {
+        let expires = (now + JOB_TOKEN_VALIDITY)
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let signature = self.mac(job, expires).finalize().into_bytes();
+        format!("{expires}.{}", hex::encode(signature))
+    }
+
+    /// Verify that a token was issued for the given job and has not expired at the given time.
+    pub fn verify(&self, job: Uuid, token: &str, now: SystemTime) -> Result<(), TokenError> {
+        let (expires, signature) = token.split_once('.').ok_or(TokenError::Malformed)?;
+        let expires: u64 = expires.parse().map_err(|_| TokenError::Malformed)?;
+        let signature = hex::decode(signature).map_err(|_| TokenError::Malformed)?;
+        self.mac(job, expires)
+            .verify_slice(&signature)
+            .map_err(|_| TokenError::Invalid)?;
+        if now > UNIX_EPOCH + Duration::from_secs(expires) {
+            return Err(TokenError::Expired);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_verify_token() {
+        let tokens = JobTokens::default();
+        let job = Uuid::new_v4();
+        let now = SystemTime::now();
+        let token = tokens.issue(job, now);
+        assert_eq!(tokens.verify(job, &token, now), Ok(()));
+    }
+
+    #[test]
+    fn token_is_bound_to_job() {
+        let tokens = JobTokens::default();
+        let now = SystemTime::now();
+        let token = tokens.issue(Uuid::new_v4(), now);
+        assert_eq!(
+            tokens.verify(Uuid::new_v4(), &token, now),
+            Err(TokenError::Invalid)
+        );
+    }
+
+    #[test]
+    fn token_is_bound_to_secret() {
+        let job = Uuid::new_v4();
+        let now = SystemTime::now();
+        let token = JobTokens::default().issue(job, now);
+        assert_eq!(
+            JobTokens::default().verify(job, &token, now),
+            Err(TokenError::Invalid)
+        );
+        let token = JobTokens::new(b"secret").issue(job, now);
+        assert_eq!(JobTokens::new(b"secret").verify(job, &token, now), Ok(()));
+    }
+
+    #[test]
+    fn token_expires() {
+        let tokens = JobTokens::default();
+        let job = Uuid::new_v4();
+        let now = SystemTime::now();
+        let token = tokens.issue(job, now);
+        let later = now + JOB_TOKEN_VALIDITY + Duration::from_secs(1);
+        assert_eq!(tokens.verify(job, &token, later), Err(TokenError::Expired));
+    }
+
+    #[test]
+    fn rejects_tampered_token() {
+        let tokens = JobTokens::default();
+        let job = Uuid::new_v4();
+        let now = SystemTime::now();
+        let token = tokens.issue(job, now);
+        let (expires, signature) = token.split_once('.').unwrap();
+        let extended = format!("{}.{signature}", expires.parse::<u64>().unwrap() + 3600);
+        assert_eq!(tokens.verify(job, &extended, now), Err(TokenError::Invalid));
+        assert_eq!(
+            tokens.verify(job, "garbage", now),
+            Err(TokenError::Malformed)
+        );
+    }
+}
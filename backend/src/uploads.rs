@@ -0,0 +1,344 @@
+//! Partial artifact uploads
+//!
+//! Builders upload artifacts in chunks over their `WebSocket` connection. Partial uploads are
+//! kept here, outside of the connection, so that a builder which reconnects can resume an upload
+//! from the last acknowledged offset.
+
+use buildsrs_common::entities::ArtifactKind;
+use buildsrs_protocol::{JobArtifact, ARTIFACT_CHUNK_SIZE};
+use bytes::{Bytes, BytesMut};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+use uuid::Uuid;
+
+/// Maximum size of an artifact, in bytes.
+pub const MAX_ARTIFACT_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Maximum size of the partial uploads of a job, in bytes.
+pub const MAX_JOB_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Maximum size of all partial uploads, in bytes.
+pub const MAX_UPLOADS_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+
+/// Error in artifact upload.
+#[derive(thiserror::Error, Debug)]
+pub enum UploadError {
+    /// Chunk is larger than the maximum chunk size
+    #[error("chunk of {0} bytes exceeds maximum chunk size")]
+    ChunkTooLarge(usize),
+    /// Artifact is larger than the maximum artifact size
+    #[error("artifact exceeds maximum size")]
+    ArtifactTooLarge,
+    /// Partial uploads of the job are larger than the maximum job size
+    #[error("artifacts of job exceed maximum size")]
+    JobTooLarge,
+    /// Partial uploads of all jobs are larger than the maximum total size
+    #[error("too many uploads in progress")]
+    Capacity,
+    /// Artifact was declared but no data was uploaded for it
+    #[error("artifact {0:?} was not uploaded")]
+    Missing(String),
+    /// Artifact name is not a known artifact kind
+    #[error("artifact {0:?} is not of a known kind")]
+    UnknownKind(String),
+    /// Artifact has no signature
+    #[error("artifact {0:?} is not signed")]
+    Unsigned(String),
+    /// Signature of the artifact does not match the builder key
+    #[error("artifact {0:?} has an invalid signature")]
+    InvalidSignature(String),
+    /// Size of the uploaded data differs from the declared size
+    #[error("artifact {name:?} has size {actual}, expected {expected}")]
+    SizeMismatch {
+        name: String,
+        expected: u64,
+        actual: u64,
+    },
+    /// Hash of the uploaded data differs from the declared hash
+    #[error("artifact {name:?} has hash {actual}, expected {expected}")]
+    HashMismatch {
+        name: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+/// Verify the size and hash of artifact data against the declared artifact.
+pub fn verify(artifact: &JobArtifact, data: &[u8]) -> Result<(), UploadError> {
+    if data.len() as u64 != artifact.size {
+        return Err(UploadError::SizeMismatch {
+            name: artifact.name.clone(),
+            expected: artifact.size,
+            actual: data.len() as u64,
+        });
+    }
+
+    let hash = hex::encode(Sha256::digest(data));
+    if !hash.eq_ignore_ascii_case(&artifact.hash) {
+        return Err(UploadError::HashMismatch {
+            name: artifact.name.clone(),
+            expected: artifact.hash.clone(),
+            actual: hash,
+        });
+    }
+
+    Ok(())
+}
+
+/// Limits on the size of partial uploads, in bytes.
+#[derive(Clone, Copy, Debug)]
+pub struct UploadLimits {
+    /// Maximum size of an artifact.
+    pub artifact: u64,
+    /// Maximum size of the partial uploads of a job.
+    pub job: u64,
+    /// Maximum size of all partial uploads.
+    pub total: u64,
+}
+
+impl Default for UploadLimits {
+    fn default() -> Self {
+        Self {
+            artifact: MAX_ARTIFACT_SIZE,
+            job: MAX_JOB_SIZE,
+            total: MAX_UPLOADS_SIZE,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct UploadsInner {
+    /// Partial uploads, by job and artifact name.
+    uploads: BTreeMap<(Uuid, String), BytesMut>,
+    /// Size of all partial uploads.
+    size: u64,
+}
+
+impl UploadsInner {
+    /// Size of the partial uploads of a job.
+    fn job_size(&self, job: Uuid) -> u64 {
+        self.uploads
+            .range((job, String::new())..)
+            .take_while(|((upload_job, _), _)| *upload_job == job)
+            .map(|(_, upload)| upload.len() as u64)
+            .sum()
+    }
+
+    fn remove(&mut self, job: Uuid, artifact: &str) -> Option<BytesMut> {
+        let upload = self.uploads.remove(&(job, artifact.into()))?;
+        self.size -= upload.len() as u64;
+        Some(upload)
+    }
+}
+
+/// Partial artifact uploads, by job and artifact name.
+#[derive(Clone, Debug, Default)]
+pub struct Uploads {
+    inner: Arc<Mutex<UploadsInner>>,
+    limits: UploadLimits,
+}
+
+impl Uploads {
+    /// Append a chunk to an upload, returning the offset up to which it has been received.
+    ///
+    /// Chunks which do not start at the current offset are ignored, the builder continues from
+    /// the returned offset. This makes retransmitted chunks harmless.
+    pub fn append(
+        &self,
+        job: Uuid,
+        artifact: &str,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<u64, UploadError> {
+        if data.len() > ARTIFACT_CHUNK_SIZE {
+            return Err(UploadError::ChunkTooLarge(data.len()));
+        }
+        artifact
+            .parse::<ArtifactKind>()
+            .map_err(|_| UploadError::UnknownKind(artifact.into()))?;
+        let mut inner = self.inner.lock().unwrap();
+        let current = inner
+            .uploads
+            .get(&(job, artifact.into()))
+            .map_or(0, |upload| upload.len() as u64);
+        if offset == current && !data.is_empty() {
+            let length = data.len() as u64;
+            if current + length > self.limits.artifact {
+                return Err(UploadError::ArtifactTooLarge);
+            }
+            if inner.job_size(job) + length > self.limits.job {
+                return Err(UploadError::JobTooLarge);
+            }
+            if inner.size + length > self.limits.total {
+                return Err(UploadError::Capacity);
+            }
+            inner
+                .uploads
+                .entry((job, artifact.into()))
+                .or_default()
+                .extend_from_slice(data);
+            inner.size += length;
+            return Ok(current + length);
+        }
+        Ok(current)
+    }
+
+    /// Take a completed upload, verifying its size and hash against the declared artifact.
+    pub fn finish(&self, job: Uuid, artifact: &JobArtifact) -> Result<Bytes, UploadError> {
+        let data = self
+            .inner
+            .lock()
+            .unwrap()
+            .remove(job, &artifact.name)
+            .ok_or_else(|| UploadError::Missing(artifact.name.clone()))?
+            .freeze();
+
+        verify(artifact, &data)?;
+        Ok(data)
+    }
+
+    /// Discard all uploads of a job.
+    pub fn discard(&self, job: Uuid) {
+        let mut inner = self.inner.lock().unwrap();
+        let UploadsInner { uploads, size } = &mut *inner;
+        uploads.retain(|(upload_job, _), upload| {
+            if *upload_job == job {
+                *size -= upload.len() as u64;
+            }
+            *upload_job != job
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn artifact(name: &str, data: &[u8]) -> JobArtifact {
+        JobArtifact {
+            name: name.into(),
+            hash: hex::encode(Sha256::digest(data)),
+            size: data.len() as u64,
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn upload_in_chunks() {
+        let uploads = Uploads::default();
+        let job = Uuid::new_v4();
+        assert_eq!(uploads.append(job, "metadata", 0, b"hello ").unwrap(), 6);
+        assert_eq!(uploads.append(job, "metadata", 6, b"world").unwrap(), 11);
+        let data = uploads
+            .finish(job, &artifact("metadata", b"hello world"))
+            .unwrap();
+        assert_eq!(&data[..], b"hello world");
+    }
+
+    #[test]
+    fn upload_resumes_from_offset() {
+        let uploads = Uploads::default();
+        let job = Uuid::new_v4();
+        uploads.append(job, "metadata", 0, b"hello ").unwrap();
+
+        // probing, retransmitted and out of order chunks report the current offset
+        assert_eq!(uploads.append(job, "metadata", 0, b"").unwrap(), 6);
+        assert_eq!(uploads.append(job, "metadata", 0, b"hello ").unwrap(), 6);
+        assert_eq!(uploads.append(job, "metadata", 11, b"!").unwrap(), 6);
+
+        assert_eq!(uploads.append(job, "metadata", 6, b"world").unwrap(), 11);
+        uploads
+            .finish(job, &artifact("metadata", b"hello world"))
+            .unwrap();
+    }
+
+    #[test]
+    fn upload_rejects_large_chunk() {
+        let uploads = Uploads::default();
+        let data = vec![0; ARTIFACT_CHUNK_SIZE + 1];
+        assert!(matches!(
+            uploads.append(Uuid::new_v4(), "metadata", 0, &data),
+            Err(UploadError::ChunkTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn finish_verifies_artifact() {
+        let uploads = Uploads::default();
+        let job = Uuid::new_v4();
+
+        assert!(matches!(
+            uploads.finish(job, &artifact("metadata", b"hello")),
+            Err(UploadError::Missing(_))
+        ));
+
+        uploads.append(job, "metadata", 0, b"hello").unwrap();
+        assert!(matches!(
+            uploads.finish(job, &artifact("metadata", b"hallo")),
+            Err(UploadError::HashMismatch { .. })
+        ));
+
+        uploads.append(job, "metadata", 0, b"hello").unwrap();
+        assert!(matches!(
+            uploads.finish(job, &artifact("metadata", b"hello!")),
+            Err(UploadError::SizeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn upload_rejects_unknown_kind() {
+        let uploads = Uploads::default();
+        assert!(matches!(
+            uploads.append(Uuid::new_v4(), "unknown", 0, b"hello"),
+            Err(UploadError::UnknownKind(_))
+        ));
+    }
+
+    #[test]
+    fn upload_enforces_limits() {
+        let uploads = Uploads {
+            inner: Default::default(),
+            limits: UploadLimits {
+                artifact: 4,
+                job: 6,
+                total: 10,
+            },
+        };
+        let job = Uuid::new_v4();
+        assert!(matches!(
+            uploads.append(job, "metadata", 0, b"hello"),
+            Err(UploadError::ArtifactTooLarge)
+        ));
+        assert_eq!(uploads.append(job, "metadata", 0, b"abcd").unwrap(), 4);
+        assert!(matches!(
+            uploads.append(job, "tarball", 0, b"abc"),
+            Err(UploadError::JobTooLarge)
+        ));
+
+        let other = Uuid::new_v4();
+        assert_eq!(uploads.append(other, "metadata", 0, b"abcd").unwrap(), 4);
+        let third = Uuid::new_v4();
+        assert!(matches!(
+            uploads.append(third, "metadata", 0, b"abc"),
+            Err(UploadError::Capacity)
+        ));
+
+        // finished and discarded uploads free capacity
+        uploads.finish(job, &artifact("metadata", b"abcd")).unwrap();
+        uploads.discard(other);
+        assert_eq!(uploads.append(third, "metadata", 0, b"abcd").unwrap(), 4);
+        assert_eq!(uploads.append(third, "tarball", 0, b"ab").unwrap(), 2);
+    }
+
+    #[test]
+    fn discard_uploads_of_job() {
+        let uploads = Uploads::default();
+        let job = Uuid::new_v4();
+        uploads.append(job, "metadata", 0, b"hello").unwrap();
+        uploads.discard(job);
+        assert_eq!(uploads.append(job, "metadata", 0, b"").unwrap(), 0);
+    }
+}
@@ -1,22 +1,39 @@
-use axum::{body::Body, http::Request};
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    response::Response,
+};
 use buildsrs_backend::*;
+use buildsrs_common::{
+    api::*,
+    entities::{CrateTarget, JobArtifactInfo, Task},
+};
 use buildsrs_database::*;
+use buildsrs_protocol::{ssh_key::HashAlg, testing::*, *};
 use buildsrs_storage::*;
 use http_body_util::BodyExt;
-use std::{future::Future, path::Path, sync::Arc};
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeSet, future::Future, sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
 use tower::ServiceExt;
+use url::Url;
+use uuid::Uuid;
 
 async fn with_backend<O: Future<Output = ()>, F: FnOnce(Backend) -> O>(f: F) {
+    with_backend_pool(|backend, _pool| f(backend)).await;
+}
+
+async fn with_backend_pool<O: Future<Output = ()>, F: FnOnce(Backend, Pool) -> O>(f: F) {
     let storage = S3::new_temp().await;
     let host = std::env::var("DATABASE").expect("DATABASE env var must be present to run tests");
     let temp_database = TempDatabase::create(&host, None).await.unwrap();
 
     let backend = Backend::new(
         Arc::new(temp_database.pool().clone()),
-        Arc::new((&*storage).clone()),
+        Arc::new((*storage).clone()),
     );
 
-    f(backend).await;
+    f(backend, temp_database.pool().clone()).await;
 
     temp_database.delete().await.unwrap();
     storage.cleanup().await;
@@ -37,6 +54,108 @@ async fn can_get_frontend_file() {
     .await;
 }
 
+#[cfg(feature = "frontend")]
+#[tokio::test]
+async fn can_get_frontend_fallback() {
+    with_backend(|backend| async move {
+        let backend = backend.with_frontend(Arc::new(
+            [("index.html".into(), (b"index" as &[u8]).into())].into(),
+        ));
+
+        // unknown paths are routed by the frontend
+        let response = get_with(&backend, "/crates/serde", &[]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/html");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, &b"index"[..]);
+
+        // unknown API paths and missing files are not
+        for path in ["/api/v1/unknown", "/app-abc123.js"] {
+            let response = get_with(&backend, path, &[]).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+    })
+    .await;
+}
+
+#[cfg(feature = "frontend")]
+#[tokio::test]
+async fn can_get_frontend_compressed() {
+    with_backend(|backend| async move {
+        let backend = backend.with_frontend(Arc::new(
+            [
+                ("app.js".into(), (b"plain" as &[u8]).into()),
+                ("app.js.br".into(), (b"brotli" as &[u8]).into()),
+                ("app.js.gz".into(), (b"gzip" as &[u8]).into()),
+            ]
+            .into(),
+        ));
+
+        let cases = [
+            ("gzip, deflate, br", Some("br"), &b"brotli"[..]),
+            ("gzip", Some("gzip"), &b"gzip"[..]),
+            ("identity", None, &b"plain"[..]),
+        ];
+        for (accept, encoding, content) in cases {
+            let response =
+                get_with(&backend, "/app.js", &[(header::ACCEPT_ENCODING, accept)]).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()[header::VARY], "accept-encoding");
+            assert_eq!(
+                response.headers()[header::CONTENT_TYPE],
+                "application/javascript"
+            );
+            assert_eq!(
+                response
+                    .headers()
+                    .get(header::CONTENT_ENCODING)
+                    .map(|value| value.to_str().unwrap()),
+                encoding
+            );
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body, content);
+        }
+    })
+    .await;
+}
+
+#[cfg(feature = "frontend")]
+#[tokio::test]
+async fn can_get_frontend_directory() {
+    with_backend(|backend| async move {
+        let parent = std::env::temp_dir().join(format!("buildsrs-frontend-{}", Uuid::new_v4()));
+        let root = parent.join("frontend");
+        std::fs::create_dir_all(root.join("assets")).unwrap();
+        std::fs::write(root.join("index.html"), "index").unwrap();
+        std::fs::write(root.join("assets").join("style.css"), "style").unwrap();
+        std::fs::write(parent.join("secret.txt"), "secret").unwrap();
+        let backend = backend.with_frontend_path(&root);
+
+        let body = |response: Response| async move {
+            response.into_body().collect().await.unwrap().to_bytes()
+        };
+        let response = get_with(&backend, "/assets/style.css", &[]).await;
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/css");
+        assert_eq!(body(response).await, &b"style"[..]);
+        let response = get_with(&backend, "/crates/serde", &[]).await;
+        assert_eq!(body(response).await, &b"index"[..]);
+
+        // files are read on every request
+        std::fs::write(root.join("assets").join("style.css"), "rebuilt").unwrap();
+        let response = get_with(&backend, "/assets/style.css", &[]).await;
+        assert_eq!(body(response).await, &b"rebuilt"[..]);
+
+        // files outside of the directory and missing files are not found
+        for path in ["/..%2Fsecret.txt", "/assets/missing.js"] {
+            let response = get_with(&backend, path, &[]).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{path}");
+        }
+
+        std::fs::remove_dir_all(&parent).unwrap();
+    })
+    .await;
+}
+
 #[cfg(feature = "frontend-vendor")]
 #[tokio::test]
 async fn can_get_frontend_vendored() {
@@ -49,7 +168,2154 @@ async fn can_get_frontend_vendored() {
         let response = backend.router().oneshot(request).await.unwrap();
         assert_eq!(response.headers().get("Content-Type").unwrap(), "text/html");
         let body = response.into_body().collect().await.unwrap().to_bytes();
-        assert_eq!(body, frontend.get(Path::new("index.html")).unwrap());
+        assert_eq!(
+            body,
+            frontend.get(std::path::Path::new("index.html")).unwrap()
+        );
+    })
+    .await;
+}
+
+fn artifact_id(kind: ArtifactKind) -> ArtifactId {
+    ArtifactId {
+        task: Task {
+            krate: "serde".into(),
+            version: "1.0.0".into(),
+            triple: "x86_64-unknown-linux-gnu".into(),
+            kind,
+        },
+    }
+}
+
+/// Record an artifact as built by a successful job, creating its crate version if needed.
+async fn artifact_record(pool: &Pool, artifact: &ArtifactId, data: &[u8]) {
+    let task = &artifact.task;
+    let writer = pool.write().await.unwrap();
+    if writer.crate_info(&task.krate).await.is_err() {
+        writer.crate_add(&task.krate).await.unwrap();
+    }
+    if writer
+        .crate_version_info(&task.krate, &task.version)
+        .await
+        .is_err()
+    {
+        writer
+            .crate_version_add(&task.krate, &task.version, "abcdef", false)
+            .await
+            .unwrap();
+    }
+    if writer.triple_info(&task.triple).await.is_err() {
+        writer.triple_add(&task.triple).await.unwrap();
+        writer.triple_enabled(&task.triple, true).await.unwrap();
+    }
+    let builder = Uuid::new_v4();
+    writer
+        .builder_add(builder, random_key().public_key(), "test")
+        .await
+        .unwrap();
+    writer.builder_set_enabled(builder, true).await.unwrap();
+    writer
+        .builder_triple_add(builder, &task.triple)
+        .await
+        .unwrap();
+    writer
+        .task_create(&task.krate, &task.version, task.kind.as_ref(), &task.triple)
+        .await
+        .unwrap();
+    let job = writer
+        .job_request(builder, &[task.triple.as_str()], Duration::from_secs(60))
+        .await
+        .unwrap()
+        .unwrap();
+    writer
+        .job_artifact_add(
+            job,
+            &JobArtifactInfo {
+                name: task.kind.as_ref().into(),
+                hash: hex::encode(Sha256::digest(data)),
+                size: data.len() as u64,
+                signature: "signature".into(),
+            },
+        )
+        .await
+        .unwrap();
+    writer.job_finish(job, true).await.unwrap();
+    writer.commit().await.unwrap();
+}
+
+/// Record an artifact as built by a successful job, and store its data.
+async fn artifact_add(backend: &Backend, pool: &Pool, artifact: &ArtifactId, data: &[u8]) {
+    artifact_record(pool, artifact, data).await;
+    backend
+        .storage()
+        .artifact_put(artifact, data)
+        .await
+        .unwrap();
+}
+
+async fn get(backend: &Backend, uri: &str) -> Response {
+    let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    backend.router().oneshot(request).await.unwrap()
+}
+
+#[tokio::test]
+async fn can_get_artifact() {
+    with_backend_pool(|backend, pool| async move {
+        artifact_add(&backend, &pool, &artifact_id(ArtifactKind::Metadata), b"{}").await;
+        let response = get(
+            &backend,
+            "/api/v1/crates/serde/1.0.0/x86_64-unknown-linux-gnu/metadata",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "application/json"
+        );
+        assert_eq!(
+            response.headers().get(header::CONTENT_DISPOSITION).unwrap(),
+            "attachment; filename=\"serde_1.0.0_x86_64-unknown-linux-gnu.metadata.json\""
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, &b"{}"[..]);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn cannot_get_artifact_missing() {
+    with_backend(|backend| async move {
+        let response = get(
+            &backend,
+            "/api/v1/crates/serde/1.0.0/x86_64-unknown-linux-gnu/tarball",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // artifacts which were not recorded by a successful job are not served
+        backend
+            .storage()
+            .artifact_put(&artifact_id(ArtifactKind::Metadata), b"{}")
+            .await
+            .unwrap();
+        let response = get(
+            &backend,
+            "/api/v1/crates/serde/1.0.0/x86_64-unknown-linux-gnu/metadata",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn cannot_get_artifact_unknown_kind() {
+    with_backend(|backend| async move {
+        let response = get(
+            &backend,
+            "/api/v1/crates/serde/1.0.0/x86_64-unknown-linux-gnu/executable",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(error_body(response).await.error, ErrorKind::BadRequest);
+    })
+    .await;
+}
+
+/// Storage which redirects to a fixed URL for every artifact.
+#[derive(Debug)]
+struct RedirectStorage(Url);
+
+#[async_trait::async_trait]
+impl Storage for RedirectStorage {
+    async fn artifact_put(&self, _version: &ArtifactId, _data: &[u8]) -> Result<(), StorageError> {
+        unimplemented!()
+    }
+
+    async fn artifact_get(&self, _version: &ArtifactId) -> Result<ArtifactData, StorageError> {
+        Ok(ArtifactData::Redirect {
+            validity: Duration::from_secs(60),
+            url: self.0.clone(),
+        })
+    }
+}
+
+#[tokio::test]
+async fn can_get_artifact_redirect() {
+    let host = std::env::var("DATABASE").expect("DATABASE env var must be present to run tests");
+    let temp_database = TempDatabase::create(&host, None).await.unwrap();
+    let url: Url = "https://example.com/artifact.deb".parse().unwrap();
+    let backend = Backend::new(
+        Arc::new(temp_database.pool().clone()),
+        Arc::new(RedirectStorage(url.clone())),
+    );
+    artifact_record(
+        temp_database.pool(),
+        &artifact_id(ArtifactKind::Debian),
+        b"debian",
+    )
+    .await;
+
+    let response = get(
+        &backend,
+        "/api/v1/crates/serde/1.0.0/x86_64-unknown-linux-gnu/debian",
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert_eq!(
+        response.headers().get(header::LOCATION).unwrap(),
+        url.as_str()
+    );
+
+    temp_database.delete().await.unwrap();
+}
+
+/// Add versions of serde as `(version, yanked, built)`, storing metadata of built versions.
+async fn versions_add(backend: &Backend, pool: &Pool, versions: &[(&str, bool, bool)]) {
+    let writer = pool.write().await.unwrap();
+    writer.crate_add("serde").await.unwrap();
+    for (version, yanked, _) in versions {
+        writer
+            .crate_version_add("serde", version, "abcdef", *yanked)
+            .await
+            .unwrap();
+    }
+    writer.commit().await.unwrap();
+    for (version, _, built) in versions {
+        if *built {
+            let mut artifact = artifact_id(ArtifactKind::Metadata);
+            artifact.task.version = (*version).into();
+            artifact_add(backend, pool, &artifact, version.as_bytes()).await;
+        }
+    }
+}
+
+/// Get the metadata artifact of serde for a version selector, returning the version served.
+async fn get_resolved(backend: &Backend, selector: &str) -> Option<String> {
+    let uri = format!("/api/v1/crates/serde/{selector}/x86_64-unknown-linux-gnu/metadata");
+    let response = get(backend, &uri).await;
+    match response.status() {
+        StatusCode::OK => {
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            Some(String::from_utf8(body.to_vec()).unwrap())
+        }
+        StatusCode::NOT_FOUND => None,
+        status => panic!("unexpected status {status}"),
+    }
+}
+
+#[tokio::test]
+async fn can_get_artifact_latest() {
+    with_backend_pool(|backend, pool| async move {
+        versions_add(
+            &backend,
+            &pool,
+            &[
+                ("0.9.0", false, true),
+                ("1.0.0", false, true),
+                ("1.1.0", true, true),
+                ("2.0.0-beta.1", false, true),
+            ],
+        )
+        .await;
+        assert_eq!(get_resolved(&backend, "latest").await.unwrap(), "1.0.0");
+        assert_eq!(get_resolved(&backend, "^0.9").await.unwrap(), "0.9.0");
+        assert_eq!(
+            get_resolved(&backend, "^2.0.0-beta").await.unwrap(),
+            "2.0.0-beta.1"
+        );
+
+        // exact versions are served even if yanked
+        assert_eq!(get_resolved(&backend, "1.1.0").await.unwrap(), "1.1.0");
+        assert_eq!(get_resolved(&backend, "=1.1.0").await, None);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_get_artifact_latest_built() {
+    with_backend_pool(|backend, pool| async move {
+        versions_add(
+            &backend,
+            &pool,
+            &[("1.0.0", false, true), ("1.1.0", false, false)],
+        )
+        .await;
+        assert_eq!(get_resolved(&backend, "latest").await.unwrap(), "1.0.0");
+        assert_eq!(get_resolved(&backend, "^1.1").await, None);
+    })
+    .await;
+}
+
+/// Get a URI with additional request headers.
+async fn get_with(
+    backend: &Backend,
+    uri: &str,
+    headers: &[(header::HeaderName, &str)],
+) -> Response {
+    let mut request = Request::builder().uri(uri);
+    for (name, value) in headers {
+        request = request.header(name, *value);
+    }
+    let request = request.body(Body::empty()).unwrap();
+    backend.router().oneshot(request).await.unwrap()
+}
+
+#[tokio::test]
+async fn can_get_artifact_cached() {
+    with_backend_pool(|backend, pool| async move {
+        let data = b"{\"name\":\"serde\"}";
+        artifact_add(&backend, &pool, &artifact_id(ArtifactKind::Metadata), data).await;
+        let uri = "/api/v1/crates/serde/1.0.0/x86_64-unknown-linux-gnu/metadata";
+        let etag = format!("\"{}\"", hex::encode(Sha256::digest(data)));
+
+        let response = get(&backend, uri).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get(header::ETAG).unwrap(), &etag);
+        assert_eq!(
+            response.headers().get(header::CACHE_CONTROL).unwrap(),
+            "public, max-age=3600"
+        );
+        assert_eq!(
+            response.headers().get(header::ACCEPT_RANGES).unwrap(),
+            "bytes"
+        );
+
+        let response = get_with(&backend, uri, &[(header::IF_NONE_MATCH, &etag)]).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers().get(header::ETAG).unwrap(), &etag);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(body.is_empty());
+
+        let response = get_with(&backend, uri, &[(header::IF_NONE_MATCH, "\"other\"")]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_get_artifact_range() {
+    with_backend_pool(|backend, pool| async move {
+        artifact_add(
+            &backend,
+            &pool,
+            &artifact_id(ArtifactKind::Metadata),
+            b"0123456789",
+        )
+        .await;
+        let uri = "/api/v1/crates/serde/1.0.0/x86_64-unknown-linux-gnu/metadata";
+        let etag = format!("\"{}\"", hex::encode(Sha256::digest(b"0123456789")));
+
+        let response = get_with(&backend, uri, &[(header::RANGE, "bytes=2-4")]).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            response.headers().get(header::CONTENT_RANGE).unwrap(),
+            "bytes 2-4/10"
+        );
+        assert_eq!(response.headers().get(header::ETAG).unwrap(), &etag);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, &b"234"[..]);
+
+        let response = get_with(&backend, uri, &[(header::RANGE, "bytes=-3")]).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, &b"789"[..]);
+
+        // ranges are served when the artifact is unchanged, and ignored otherwise
+        let response = get_with(
+            &backend,
+            uri,
+            &[(header::RANGE, "bytes=2-4"), (header::IF_RANGE, &etag)],
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        let response = get_with(
+            &backend,
+            uri,
+            &[
+                (header::RANGE, "bytes=2-4"),
+                (header::IF_RANGE, "\"other\""),
+            ],
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, &b"0123456789"[..]);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn cannot_get_artifact_range_unsatisfiable() {
+    with_backend_pool(|backend, pool| async move {
+        artifact_add(
+            &backend,
+            &pool,
+            &artifact_id(ArtifactKind::Metadata),
+            b"0123456789",
+        )
+        .await;
+        let uri = "/api/v1/crates/serde/1.0.0/x86_64-unknown-linux-gnu/metadata";
+        let response = get_with(&backend, uri, &[(header::RANGE, "bytes=20-")]).await;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(
+            response.headers().get(header::CONTENT_RANGE).unwrap(),
+            "bytes */10"
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_get_artifact_latest_cached() {
+    with_backend_pool(|backend, pool| async move {
+        versions_add(&backend, &pool, &[("1.0.0", false, true)]).await;
+        let response = get(
+            &backend,
+            "/api/v1/crates/serde/latest/x86_64-unknown-linux-gnu/metadata",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CACHE_CONTROL).unwrap(),
+            "public, max-age=300"
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn cannot_get_artifact_invalid_version() {
+    with_backend(|backend| async move {
+        let response = get(
+            &backend,
+            "/api/v1/crates/serde/newest/x86_64-unknown-linux-gnu/metadata",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(error_body(response).await.error, ErrorKind::BadRequest);
+    })
+    .await;
+}
+
+async fn error_body(response: Response) -> ErrorResponse {
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn can_get_crate() {
+    with_backend_pool(|backend, pool| async move {
+        versions_add(&backend, &pool, &[("1.0.0", false, false)]).await;
+
+        let response = get(&backend, "/api/v1/crates/serde").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let info: CrateResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(info.name, "serde");
+        assert_eq!(info.versions, ["1.0.0".to_string()].into());
+
+        let response = get(&backend, "/api/v1/crates/serde/1.0.0").await;
+        assert_eq!(response.status(), StatusCode::OK);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn cannot_get_crate_missing() {
+    with_backend(|backend| async move {
+        for uri in ["/api/v1/crates/serde", "/api/v1/crates/serde/1.0.0"] {
+            let response = get(&backend, uri).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{uri}");
+            assert_eq!(error_body(response).await.error, ErrorKind::NotFound);
+        }
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_get_openapi_document() {
+    with_backend(|backend| async move {
+        let response = get(&backend, "/api/v1/openapi.json").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let document: serde_json::Value = json_body(response).await;
+        assert_eq!(document["openapi"], "3.0.3");
+        let version = &document["paths"]["/api/v1/crates/{crate}/{version}"]["get"];
+        assert_eq!(version["operationId"], "crate_version");
+        let upload = &document["paths"]["/api/v1/jobs/{uuid}/artifacts/{kind}"]["put"];
+        assert_eq!(upload["security"][0]["jobToken"], serde_json::json!([]));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn cannot_list_crates_without_name() {
+    with_backend(|backend| async move {
+        let response = get(&backend, "/api/v1/crates").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(error_body(response).await.error, ErrorKind::BadRequest);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_search_crates() {
+    with_backend_pool(|backend, pool| async move {
+        let writer = pool.write().await.unwrap();
+        for name in ["serde_json", "serdes", "serde"] {
+            writer.crate_add(name).await.unwrap();
+        }
+        for (version, yanked) in [
+            ("1.0.0", false),
+            ("1.10.0", false),
+            ("1.11.0", true),
+            ("2.0.0-rc.1", false),
+        ] {
+            writer
+                .crate_version_add("serde", version, "abcdef", yanked)
+                .await
+                .unwrap();
+        }
+        writer.commit().await.unwrap();
+
+        let response = get(&backend, "/api/v1/crates?name=serde&limit=2").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let page: CratesResponse = json_body(response).await;
+        assert_eq!(page.total, 3);
+        assert_eq!(
+            page.crates,
+            [
+                CrateSummary {
+                    name: "serde".into(),
+                    latest_version: Some("1.10.0".into()),
+                    has_artifacts: false,
+                },
+                CrateSummary {
+                    name: "serdes".into(),
+                    latest_version: None,
+                    has_artifacts: false,
+                },
+            ]
+        );
+
+        let cursor = page.next_cursor.unwrap();
+        let response = get(
+            &backend,
+            &format!("/api/v1/crates?name=serde&limit=2&cursor={cursor}"),
+        )
+        .await;
+        let page: CratesResponse = json_body(response).await;
+        assert_eq!(page.total, 3);
+        assert_eq!(page.crates.len(), 1);
+        assert_eq!(page.crates[0].name, "serde_json");
+        assert_eq!(page.next_cursor, None);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn cannot_search_crates_invalid_page() {
+    with_backend(|backend| async move {
+        for uri in [
+            "/api/v1/crates?name=serde&limit=0",
+            "/api/v1/crates?name=serde&limit=1000",
+            "/api/v1/crates?name=serde&cursor=invalid",
+        ] {
+            let response = get(&backend, uri).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{uri}");
+        }
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn cannot_get_crate_database_unavailable() {
+    with_backend_pool(|backend, pool| async move {
+        let backend = backend.with_database_timeout(Duration::from_millis(100));
+
+        // hold the only connection of the pool
+        let _writer = pool.write().await.unwrap();
+
+        let response = get(&backend, "/api/v1/crates/serde").await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(error_body(response).await.error, ErrorKind::Unavailable);
+    })
+    .await;
+}
+
+/// Server identity used by jobs connection tests.
+const SERVER: &str = "builds.rs";
+
+/// Triple used by jobs connection tests.
+const TRIPLE: &str = "x86_64-unknown-linux-gnu";
+
+fn builder_features() -> BTreeSet<Feature> {
+    [
+        Feature::JobEvents,
+        Feature::Capabilities,
+        Feature::ArtifactUpload,
+        Feature::Cbor,
+    ]
+    .into()
+}
+
+/// Register a builder which is allowed to build [`TRIPLE`], returning its key.
+async fn builder_add(pool: &Pool, enabled: bool) -> ssh_key::PrivateKey {
+    let key = random_key();
+    let uuid = Uuid::new_v4();
+    let writer = pool.write().await.unwrap();
+    writer.triple_add(TRIPLE).await.unwrap();
+    writer.triple_enabled(TRIPLE, true).await.unwrap();
+    writer
+        .builder_add(uuid, key.public_key(), "test")
+        .await
+        .unwrap();
+    writer.builder_set_enabled(uuid, enabled).await.unwrap();
+    writer.builder_triple_add(uuid, TRIPLE).await.unwrap();
+    writer.commit().await.unwrap();
+    key
+}
+
+/// Handle a jobs connection in the background, returning the other end of it.
+fn jobs_connect(backend: &Backend) -> (MemoryTransport, JoinHandle<Result<(), WebSocketError>>) {
+    let (left, right) = duplex();
+    let backend = backend.clone().with_server_name(SERVER);
+    let handle = tokio::spawn(async move { backend.handle_jobs(right.boxed()).await });
+    (left, handle)
+}
+
+#[tokio::test]
+async fn jobs_handshake_and_dispatch() {
+    with_backend_pool(|backend, pool| async move {
+        let key = builder_add(&pool, true).await;
+        let writer = pool.write().await.unwrap();
+        writer.crate_add("serde").await.unwrap();
+        writer
+            .crate_version_add("serde", "1.0.0", "abcdef", false)
+            .await
+            .unwrap();
+        writer.tasks_create_all("metadata", TRIPLE).await.unwrap();
+        writer.commit().await.unwrap();
+
+        let (transport, handle) = jobs_connect(&backend);
+        let mut builder = FakeBuilder::new(transport, key);
+        let welcome = builder.authenticate(builder_features()).await.unwrap();
+        assert_eq!(welcome.version, PROTOCOL_VERSION);
+        assert_eq!(welcome.features, builder_features());
+
+        builder
+            .send(ClientMessage::Capabilities(Capabilities {
+                triples: [TRIPLE.into()].into(),
+                ..Default::default()
+            }))
+            .await
+            .unwrap();
+        builder
+            .send(ClientMessage::JobRequest(JobRequest::default()))
+            .await
+            .unwrap();
+        let ServerMessage::JobResponse(job) = builder.recv().await.unwrap() else {
+            panic!("expected job response");
+        };
+        assert_eq!(job.name, "serde");
+        assert_eq!(job.version, "1.0.0");
+
+        builder.close().await.unwrap();
+        assert!(matches!(
+            handle.await.unwrap(),
+            Err(WebSocketError::StreamClosed)
+        ));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn jobs_skip_disabled_triples_and_crates() {
+    with_backend_pool(|backend, pool| async move {
+        let key = builder_add(&pool, true).await;
+        let writer = pool.write().await.unwrap();
+        writer.crate_add("serde").await.unwrap();
+        writer
+            .crate_version_add("serde", "1.0.0", "abcdef", false)
+            .await
+            .unwrap();
+        writer.tasks_create_all("metadata", TRIPLE).await.unwrap();
+        writer.crate_set_enabled("serde", false).await.unwrap();
+        writer.triple_enabled(TRIPLE, false).await.unwrap();
+        writer.commit().await.unwrap();
+
+        let (transport, handle) = jobs_connect(&backend);
+        let mut builder = FakeBuilder::new(transport, key);
+        builder.authenticate(builder_features()).await.unwrap();
+        builder
+            .send(ClientMessage::Capabilities(Capabilities {
+                triples: [TRIPLE.into()].into(),
+                ..Default::default()
+            }))
+            .await
+            .unwrap();
+
+        // no job is handed out while either the crate or the triple is disabled
+        for step in 0..2 {
+            builder
+                .send(ClientMessage::JobRequest(JobRequest::default()))
+                .await
+                .unwrap();
+            assert!(tokio::time::timeout(Duration::from_secs(1), builder.recv())
+                .await
+                .is_err());
+            let writer = pool.write().await.unwrap();
+            if step == 0 {
+                writer.crate_set_enabled("serde", true).await.unwrap();
+            } else {
+                writer.triple_enabled(TRIPLE, true).await.unwrap();
+            }
+            writer.commit().await.unwrap();
+        }
+
+        builder
+            .send(ClientMessage::JobRequest(JobRequest::default()))
+            .await
+            .unwrap();
+        let ServerMessage::JobResponse(job) = builder.recv().await.unwrap() else {
+            panic!("expected job response");
+        };
+        assert_eq!(job.name, "serde");
+
+        builder.close().await.unwrap();
+        assert!(matches!(
+            handle.await.unwrap(),
+            Err(WebSocketError::StreamClosed)
+        ));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn jobs_skip_disabled_builder() {
+    with_backend_pool(|backend, pool| async move {
+        admin_token_add(&pool).await;
+        let key = builder_add(&pool, true).await;
+        let writer = pool.write().await.unwrap();
+        writer.crate_add("serde").await.unwrap();
+        writer
+            .crate_version_add("serde", "1.0.0", "abcdef", false)
+            .await
+            .unwrap();
+        writer.tasks_create_all("metadata", TRIPLE).await.unwrap();
+        let uuid = writer
+            .builder_lookup(&key.public_key().fingerprint(HashAlg::Sha512).to_string())
+            .await
+            .unwrap();
+        writer.commit().await.unwrap();
+
+        let (transport, handle) = jobs_connect(&backend);
+        let mut builder = FakeBuilder::new(transport, key);
+        builder.authenticate(builder_features()).await.unwrap();
+
+        // builders disabled while connected get no more jobs
+        let response = admin_request(
+            &backend,
+            "PATCH",
+            &format!("/api/v1/admin/builders/{uuid}"),
+            Some(ADMIN_TOKEN),
+            Some(serde_json::json!({ "enabled": false })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        builder
+            .send(ClientMessage::JobRequest(JobRequest {
+                target: Some(TRIPLE.into()),
+            }))
+            .await
+            .unwrap();
+        assert!(tokio::time::timeout(Duration::from_secs(1), builder.recv())
+            .await
+            .is_err());
+
+        builder.close().await.unwrap();
+        assert!(matches!(
+            handle.await.unwrap(),
+            Err(WebSocketError::StreamClosed)
+        ));
+        let queue = pool.read().await.unwrap().task_queue().await.unwrap();
+        assert_eq!(queue[TRIPLE], 1);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn jobs_rejects_disabled_builder() {
+    with_backend_pool(|backend, pool| async move {
+        let key = builder_add(&pool, false).await;
+        let (transport, handle) = jobs_connect(&backend);
+        let mut builder = FakeBuilder::new(transport, key);
+        assert!(matches!(
+            builder.hello(builder_features()).await,
+            Err(HarnessError::Rejected(Rejection::BuilderDisabled))
+        ));
+        assert!(matches!(
+            handle.await.unwrap(),
+            Err(WebSocketError::BuilderDisabled(_))
+        ));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn jobs_rejects_unsupported_version() {
+    with_backend_pool(|backend, pool| async move {
+        let key = builder_add(&pool, true).await;
+        let (transport, handle) = jobs_connect(&backend);
+        let mut builder = FakeBuilder::new(transport, key.clone());
+
+        // version 1 builders sign the message itself and send it in the legacy envelope
+        let message = serde_json::json!({
+            "Hello": key.public_key().fingerprint(HashAlg::Sha512).to_string(),
+        });
+        let signature = key
+            .sign(
+                "builder@builds.rs",
+                HashAlg::Sha512,
+                message.to_string().as_bytes(),
+            )
+            .unwrap()
+            .to_pem(Default::default())
+            .unwrap();
+        let envelope = serde_json::json!({"message": message, "signature": signature});
+        builder
+            .send_frame(Frame::Text(envelope.to_string()))
+            .await
+            .unwrap();
+        let rejection = Rejection::UnsupportedVersion {
+            version: PROTOCOL_VERSION_LEGACY,
+            min: PROTOCOL_VERSION_MIN,
+            max: PROTOCOL_VERSION,
+        };
+        assert!(matches!(
+            builder.recv().await,
+            Ok(ServerMessage::Rejected(received)) if received == rejection
+        ));
+        assert!(matches!(
+            handle.await.unwrap(),
+            Err(WebSocketError::Rejected(received)) if received == rejection
+        ));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn jobs_rejects_wrong_key() {
+    with_backend_pool(|backend, pool| async move {
+        let key = builder_add(&pool, true).await;
+        let (transport, handle) = jobs_connect(&backend);
+
+        // claim to be the registered builder, but sign with another key
+        let mut builder = FakeBuilder::new(transport, random_key());
+        let hello = Hello::new(
+            key.public_key().fingerprint(HashAlg::Sha512),
+            builder_features(),
+        );
+        builder.send(ClientMessage::Hello(hello)).await.unwrap();
+        assert!(matches!(
+            handle.await.unwrap(),
+            Err(WebSocketError::Signature(_))
+        ));
+        assert!(matches!(builder.recv().await, Err(HarnessError::Closed)));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn jobs_missing_hello() {
+    with_backend_pool(|backend, _pool| async move {
+        let (transport, handle) = jobs_connect(&backend);
+        let mut builder = FakeBuilder::new(transport, random_key());
+        builder.close().await.unwrap();
+        assert!(matches!(
+            handle.await.unwrap(),
+            Err(WebSocketError::MissingHello)
+        ));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn jobs_closed_during_challenge() {
+    with_backend_pool(|backend, pool| async move {
+        let key = builder_add(&pool, true).await;
+        let (transport, handle) = jobs_connect(&backend);
+        let mut builder = FakeBuilder::new(transport, key);
+        builder.hello(builder_features()).await.unwrap();
+        assert!(matches!(
+            builder.recv().await.unwrap(),
+            ServerMessage::ChallengeRequest(_)
+        ));
+        builder.close().await.unwrap();
+        assert!(matches!(
+            handle.await.unwrap(),
+            Err(WebSocketError::StreamClosed)
+        ));
+    })
+    .await;
+}
+
+/// Connect a builder which takes a metadata job of serde.
+async fn job_connect(
+    backend: &Backend,
+    pool: &Pool,
+) -> (FakeBuilder, JoinHandle<Result<(), WebSocketError>>, Job) {
+    let key = builder_add(pool, true).await;
+    let writer = pool.write().await.unwrap();
+    writer.crate_add("serde").await.unwrap();
+    writer
+        .crate_version_add("serde", "1.0.0", "abcdef", false)
+        .await
+        .unwrap();
+    writer.tasks_create_all("metadata", TRIPLE).await.unwrap();
+    writer.commit().await.unwrap();
+
+    let (transport, handle) = jobs_connect(backend);
+    let mut builder = FakeBuilder::new(transport, key);
+    builder.authenticate(builder_features()).await.unwrap();
+    builder
+        .send(ClientMessage::JobRequest(JobRequest {
+            target: Some(TRIPLE.into()),
+        }))
+        .await
+        .unwrap();
+    let ServerMessage::JobResponse(job) = builder.recv().await.unwrap() else {
+        panic!("expected job response");
+    };
+    (builder, handle, job)
+}
+
+/// Have a builder take a metadata job of serde, returning the key of the builder and the job.
+async fn job_assign(backend: &Backend, pool: &Pool) -> (ssh_key::PrivateKey, Job) {
+    let (mut builder, handle, job) = job_connect(backend, pool).await;
+    let key = builder.key().clone();
+    builder.close().await.unwrap();
+    handle.await.unwrap().unwrap_err();
+    (key, job)
+}
+
+/// Complete a job declaring the given artifacts signed by the builder, and disconnect.
+async fn job_complete(
+    mut builder: FakeBuilder,
+    handle: JoinHandle<Result<(), WebSocketError>>,
+    job: Uuid,
+    artifacts: &[JobArtifact],
+) {
+    let artifacts = artifacts
+        .iter()
+        .map(|artifact| JobArtifact {
+            signature: Some(artifact.sign(job, builder.key()).unwrap()),
+            ..artifact.clone()
+        })
+        .collect();
+    builder
+        .send(ClientMessage::JobComplete(JobComplete { job, artifacts }))
+        .await
+        .unwrap();
+    builder.close().await.unwrap();
+    handle.await.unwrap().unwrap_err();
+}
+
+/// Upload an artifact of a job over HTTP.
+async fn job_artifact_upload(
+    backend: &Backend,
+    job: Uuid,
+    token: Option<&str>,
+    artifact: &JobArtifact,
+    signature: &str,
+    data: &[u8],
+) -> Response {
+    let query = url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("hash", &artifact.hash)
+        .append_pair("size", &artifact.size.to_string())
+        .append_pair("signature", signature)
+        .finish();
+    let mut request = Request::builder().method("PUT").uri(format!(
+        "/api/v1/jobs/{job}/artifacts/{}?{query}",
+        artifact.name
+    ));
+    if let Some(token) = token {
+        request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+    }
+    let request = request.body(Body::from(data.to_vec())).unwrap();
+    backend.router().oneshot(request).await.unwrap()
+}
+
+/// Metadata of serde with the given targets, as `(name, kind)`.
+fn metadata(targets: &[(&str, &str)]) -> Vec<u8> {
+    let targets: Vec<_> = targets
+        .iter()
+        .map(|(name, kind)| {
+            serde_json::json!({
+                "name": name,
+                "kind": [kind],
+                "src_path": format!("/serde/src/{name}.rs"),
+            })
+        })
+        .collect();
+    serde_json::to_vec(&serde_json::json!({
+        "packages": [{
+            "name": "serde",
+            "version": "1.0.0",
+            "id": "serde 1.0.0",
+            "dependencies": [],
+            "targets": targets,
+            "features": {},
+            "manifest_path": "/serde/Cargo.toml",
+        }],
+        "workspace_members": ["serde 1.0.0"],
+        "resolve": null,
+        "workspace_root": "/serde",
+        "target_directory": "/serde/target",
+        "version": 1,
+    }))
+    .unwrap()
+}
+
+fn job_artifact(data: &[u8]) -> JobArtifact {
+    JobArtifact {
+        name: "metadata".into(),
+        hash: hex::encode(Sha256::digest(data)),
+        size: data.len() as u64,
+        signature: None,
+    }
+}
+
+#[tokio::test]
+async fn can_upload_job_artifact() {
+    with_backend_pool(|backend, pool| async move {
+        let (builder, handle, job) = job_connect(&backend, &pool).await;
+        let data = &metadata(&[("serde", "lib")]);
+        let artifact = job_artifact(data);
+        let signature = artifact.sign(job.uuid, builder.key()).unwrap();
+
+        let response = job_artifact_upload(
+            &backend,
+            job.uuid,
+            Some(&job.token),
+            &artifact,
+            &signature,
+            data,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // artifact is stored and recorded right away
+        let stored = backend
+            .storage()
+            .artifact_get(&artifact_id(ArtifactKind::Metadata))
+            .await
+            .unwrap();
+        assert!(matches!(stored, ArtifactData::Data { bytes } if bytes == data[..]));
+        let recorded = [JobArtifactInfo {
+            name: artifact.name.clone(),
+            hash: artifact.hash.clone(),
+            size: artifact.size,
+            signature,
+        }];
+        let reader = pool.read().await.unwrap();
+        assert_eq!(reader.job_artifacts(job.uuid).await.unwrap(), recorded);
+        drop(reader);
+
+        // completing the job does not require uploading it again
+        job_complete(builder, handle, job.uuid, &[artifact]).await;
+        let reader = pool.read().await.unwrap();
+        assert_eq!(reader.job_info(job.uuid).await.unwrap().success, Some(true));
+        assert_eq!(reader.job_artifacts(job.uuid).await.unwrap(), recorded);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_ingest_metadata() {
+    with_backend_pool(|backend, pool| async move {
+        let (builder, handle, job) = job_connect(&backend, &pool).await;
+        let writer = pool.write().await.unwrap();
+        writer.triple_enabled(TRIPLE, true).await.unwrap();
+        writer.triple_add("x86_64-apple-darwin").await.unwrap();
+        writer
+            .triple_enabled("x86_64-apple-darwin", true)
+            .await
+            .unwrap();
+        writer.triple_add("aarch64-apple-darwin").await.unwrap();
+        writer.commit().await.unwrap();
+
+        let data = &metadata(&[("serde", "lib"), ("serde-cli", "bin"), ("tests", "test")]);
+        let artifact = job_artifact(data);
+        let signature = artifact.sign(job.uuid, builder.key()).unwrap();
+        let response = job_artifact_upload(
+            &backend,
+            job.uuid,
+            Some(&job.token),
+            &artifact,
+            &signature,
+            data,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // metadata is only ingested once the job completes
+        let reader = pool.read().await.unwrap();
+        let targets = reader
+            .crate_version_targets("serde", "1.0.0")
+            .await
+            .unwrap();
+        assert!(targets.is_empty());
+        drop(reader);
+        job_complete(builder, handle, job.uuid, &[artifact]).await;
+
+        let reader = pool.read().await.unwrap();
+        let targets = reader
+            .crate_version_targets("serde", "1.0.0")
+            .await
+            .unwrap();
+        assert_eq!(
+            targets,
+            [
+                CrateTarget {
+                    name: "serde-cli".into(),
+                    kind: "bin".into(),
+                },
+                CrateTarget {
+                    name: "serde".into(),
+                    kind: "lib".into(),
+                },
+            ]
+        );
+
+        // follow-up tasks are created for enabled triples, debian packages only for linux
+        let tasks = reader
+            .task_list(Some("serde"), Some("1.0.0"), None, None)
+            .await
+            .unwrap();
+        let tasks: BTreeSet<_> = tasks
+            .into_iter()
+            .map(|task| (task.kind, task.triple))
+            .collect();
+        let darwin = "x86_64-apple-darwin";
+        assert_eq!(
+            tasks,
+            [
+                (ArtifactKind::Metadata, TRIPLE),
+                (ArtifactKind::Tarball, TRIPLE),
+                (ArtifactKind::Debian, TRIPLE),
+                (ArtifactKind::Coverage, TRIPLE),
+                (ArtifactKind::Tarball, darwin),
+                (ArtifactKind::Coverage, darwin),
+            ]
+            .into_iter()
+            .map(|(kind, triple)| (kind, triple.to_string()))
+            .collect()
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn cannot_ingest_metadata_invalid() {
+    with_backend_pool(|backend, pool| async move {
+        let (key, job) = job_assign(&backend, &pool).await;
+        for data in [&b"{}"[..], b"garbage"] {
+            let artifact = job_artifact(data);
+            let signature = artifact.sign(job.uuid, &key).unwrap();
+            let response = job_artifact_upload(
+                &backend,
+                job.uuid,
+                Some(&job.token),
+                &artifact,
+                &signature,
+                data,
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+
+        let reader = pool.read().await.unwrap();
+        assert!(reader.job_artifacts(job.uuid).await.unwrap().is_empty());
+        let tasks = reader
+            .task_list(Some("serde"), None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(tasks.len(), 1);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn job_complete_skips_metadata_invalid() {
+    with_backend_pool(|backend, pool| async move {
+        let (mut builder, handle, job) = job_connect(&backend, &pool).await;
+        let metadata = b"garbage";
+        let tarball = b"tarball";
+        job_artifact_chunk(&mut builder, job.uuid, metadata).await;
+        builder
+            .send(ClientMessage::ArtifactChunk(ArtifactChunk {
+                job: job.uuid,
+                artifact: "tarball".into(),
+                offset: 0,
+                data: tarball.to_vec().into(),
+            }))
+            .await
+            .unwrap();
+        let ServerMessage::ArtifactAck(_) = builder.recv().await.unwrap() else {
+            panic!("expected artifact acknowledgement");
+        };
+        let tarball_artifact = JobArtifact {
+            name: "tarball".into(),
+            ..job_artifact(tarball)
+        };
+        let artifacts: Vec<_> = [job_artifact(metadata), tarball_artifact]
+            .into_iter()
+            .map(|artifact| JobArtifact {
+                signature: Some(artifact.sign(job.uuid, builder.key()).unwrap()),
+                ..artifact
+            })
+            .collect();
+        builder
+            .send(ClientMessage::JobComplete(JobComplete {
+                job: job.uuid,
+                artifacts,
+            }))
+            .await
+            .unwrap();
+
+        // only the metadata is rejected, the connection stays open
+        builder.close().await.unwrap();
+        assert!(matches!(
+            handle.await.unwrap(),
+            Err(WebSocketError::StreamClosed)
+        ));
+
+        let reader = pool.read().await.unwrap();
+        let info = reader.job_info(job.uuid).await.unwrap();
+        assert_eq!(info.success, Some(true));
+        assert_eq!(info.reason, None);
+        let artifacts = reader.job_artifacts(job.uuid).await.unwrap();
+        let names: Vec<_> = artifacts
+            .iter()
+            .map(|artifact| &artifact.name[..])
+            .collect();
+        assert_eq!(names, ["tarball"]);
+        let targets = reader
+            .crate_version_targets("serde", "1.0.0")
+            .await
+            .unwrap();
+        assert!(targets.is_empty());
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_list_version_artifacts() {
+    with_backend_pool(|backend, pool| async move {
+        let (builder, handle, job) = job_connect(&backend, &pool).await;
+        let data = &metadata(&[("serde", "lib")]);
+        let artifact = job_artifact(data);
+        let signature = artifact.sign(job.uuid, builder.key()).unwrap();
+        let response = job_artifact_upload(
+            &backend,
+            job.uuid,
+            Some(&job.token),
+            &artifact,
+            &signature,
+            data,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // artifacts are listed once the job was successful
+        let response = get(&backend, "/api/v1/crates/serde/1.0.0").await;
+        let version: CrateVersionResponse = json_body(response).await;
+        assert!(version.artifacts.is_empty());
+        job_complete(builder, handle, job.uuid, &[artifact.clone()]).await;
+
+        let response = get(&backend, "/api/v1/crates/serde/1.0.0").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let version: CrateVersionResponse = json_body(response).await;
+        let builder = pool
+            .read()
+            .await
+            .unwrap()
+            .job_info(job.uuid)
+            .await
+            .unwrap()
+            .builder;
+        let [listed] = &version.artifacts[..] else {
+            panic!("expected one artifact");
+        };
+        assert_eq!(
+            listed,
+            &ArtifactResponse {
+                name: "serde".into(),
+                version: "1.0.0".into(),
+                triple: TRIPLE.into(),
+                kind: "metadata".into(),
+                size: artifact.size,
+                hash: artifact.hash,
+                builder,
+                built: listed.built,
+                url: format!("/api/v1/crates/serde/1.0.0/{TRIPLE}/metadata"),
+            }
+        );
+
+        // the listed url downloads the artifact
+        let response = get(&backend, &listed.url).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_count_downloads() {
+    with_backend_pool(|backend, pool| async move {
+        let (builder, handle, job) = job_connect(&backend, &pool).await;
+        let data = &metadata(&[("serde", "lib")]);
+        let artifact = job_artifact(data);
+        let signature = artifact.sign(job.uuid, builder.key()).unwrap();
+        let response = job_artifact_upload(
+            &backend,
+            job.uuid,
+            Some(&job.token),
+            &artifact,
+            &signature,
+            data,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        job_complete(builder, handle, job.uuid, &[artifact.clone()]).await;
+
+        // partial and revalidated downloads are not counted
+        let uri = format!("/api/v1/crates/serde/1.0.0/{TRIPLE}/metadata");
+        let etag = format!("\"{}\"", artifact.hash);
+        for (headers, status) in [
+            (&[][..], StatusCode::OK),
+            (&[], StatusCode::OK),
+            (&[(header::RANGE, "bytes=0-0")], StatusCode::PARTIAL_CONTENT),
+            (
+                &[(header::IF_NONE_MATCH, etag.as_str())],
+                StatusCode::NOT_MODIFIED,
+            ),
+        ] {
+            let response = get_with(&backend, &uri, headers).await;
+            assert_eq!(response.status(), status);
+        }
+
+        // downloads are only visible once they have been flushed
+        let response = get(&backend, "/api/v1/crates/serde/downloads").await;
+        let downloads: CrateDownloadsResponse = json_body(response).await;
+        assert!(downloads.series.is_empty());
+        backend.flush_downloads().await.unwrap();
+
+        let response = get(&backend, "/api/v1/crates/serde/downloads").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let downloads: CrateDownloadsResponse = json_body(response).await;
+        assert_eq!(downloads.name, "serde");
+        let [series] = &downloads.series[..] else {
+            panic!("expected one series");
+        };
+        assert_eq!(series.version, "1.0.0");
+        assert_eq!(series.triple, TRIPLE);
+        assert_eq!(series.total, 2);
+        assert_eq!(series.days.len(), 1);
+        assert_eq!(series.days[0].downloads, 2);
+
+        let response = get(&backend, "/api/v1/crates/tokio/downloads").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn cannot_upload_job_artifact_without_token() {
+    with_backend_pool(|backend, pool| async move {
+        let (key, job) = job_assign(&backend, &pool).await;
+        let data = &metadata(&[("serde", "lib")]);
+        let artifact = job_artifact(data);
+        let signature = artifact.sign(job.uuid, &key).unwrap();
+
+        let other = JobTokens::default().issue(job.uuid, std::time::SystemTime::now());
+        for token in [None, Some("garbage"), Some(other.as_str())] {
+            let response =
+                job_artifact_upload(&backend, job.uuid, token, &artifact, &signature, data).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{token:?}");
+            assert_eq!(error_body(response).await.error, ErrorKind::Unauthorized);
+        }
+
+        let reader = pool.read().await.unwrap();
+        assert!(reader.job_artifacts(job.uuid).await.unwrap().is_empty());
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn cannot_upload_job_artifact_expired() {
+    with_backend_pool(|backend, pool| async move {
+        let (key, job) = job_assign(&backend, &pool).await;
+        let data = &metadata(&[("serde", "lib")]);
+        let artifact = job_artifact(data);
+        let signature = artifact.sign(job.uuid, &key).unwrap();
+
+        // let the lease of the job run out
+        let writer = pool.write().await.unwrap();
+        assert!(writer
+            .job_heartbeat(job.uuid, Duration::ZERO)
+            .await
+            .unwrap());
+        writer.commit().await.unwrap();
+        tokio::time::sleep(Duration::from_secs(2)).await;
+
+        let response = job_artifact_upload(
+            &backend,
+            job.uuid,
+            Some(&job.token),
+            &artifact,
+            &signature,
+            data,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(error_body(response).await.error, ErrorKind::BadRequest);
+    })
+    .await;
+}
+
+/// Upload an artifact of a job in a single chunk over the `WebSocket`.
+async fn job_artifact_chunk(builder: &mut FakeBuilder, job: Uuid, data: &[u8]) {
+    builder
+        .send(ClientMessage::ArtifactChunk(ArtifactChunk {
+            job,
+            artifact: "metadata".into(),
+            offset: 0,
+            data: data.to_vec().into(),
+        }))
+        .await
+        .unwrap();
+    let ServerMessage::ArtifactAck(ack) = builder.recv().await.unwrap() else {
+        panic!("expected artifact acknowledgement");
+    };
+    assert_eq!(ack.offset, data.len() as u64);
+}
+
+#[tokio::test]
+async fn can_upload_job_artifact_chunks() {
+    with_backend_pool(|backend, pool| async move {
+        let (mut builder, handle, job) = job_connect(&backend, &pool).await;
+        let data = &metadata(&[("serde", "lib")]);
+        let artifact = job_artifact(data);
+        let signature = artifact.sign(job.uuid, builder.key()).unwrap();
+        job_artifact_chunk(&mut builder, job.uuid, data).await;
+        job_complete(builder, handle, job.uuid, &[artifact.clone()]).await;
+
+        let stored = backend
+            .storage()
+            .artifact_get(&artifact_id(ArtifactKind::Metadata))
+            .await
+            .unwrap();
+        assert!(matches!(stored, ArtifactData::Data { bytes } if bytes == data[..]));
+
+        // the artifact is recorded with the signature of the builder
+        let reader = pool.read().await.unwrap();
+        assert_eq!(reader.job_info(job.uuid).await.unwrap().success, Some(true));
+        assert_eq!(
+            reader.job_artifacts(job.uuid).await.unwrap(),
+            [JobArtifactInfo {
+                name: artifact.name,
+                hash: artifact.hash,
+                size: artifact.size,
+                signature,
+            }]
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn job_complete_rejects_unsigned_artifacts() {
+    with_backend_pool(|backend, pool| async move {
+        let (mut builder, handle, job) = job_connect(&backend, &pool).await;
+        let data = &metadata(&[("serde", "lib")]);
+        job_artifact_chunk(&mut builder, job.uuid, data).await;
+        builder
+            .send(ClientMessage::JobComplete(JobComplete {
+                job: job.uuid,
+                artifacts: vec![job_artifact(data)],
+            }))
+            .await
+            .unwrap();
+        builder.close().await.unwrap();
+        handle.await.unwrap().unwrap_err();
+
+        let reader = pool.read().await.unwrap();
+        let info = reader.job_info(job.uuid).await.unwrap();
+        assert_eq!(info.success, Some(false));
+        assert_eq!(
+            info.reason.as_deref(),
+            Some("artifacts rejected: artifact \"metadata\" is not signed")
+        );
+        assert!(reader.job_artifacts(job.uuid).await.unwrap().is_empty());
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn cannot_upload_job_artifact_mismatch() {
+    with_backend_pool(|backend, pool| async move {
+        let (key, job) = job_assign(&backend, &pool).await;
+        let data = &metadata(&[("serde", "lib")]);
+        let artifact = job_artifact(data);
+        let signature = artifact.sign(job.uuid, &key).unwrap();
+        let wrong_size = JobArtifact {
+            size: 3,
+            ..artifact.clone()
+        };
+        let wrong_hash = job_artifact(b"[]");
+        let forged = artifact.sign(job.uuid, &random_key()).unwrap();
+
+        for (artifact, signature) in [
+            (&wrong_size, &signature),
+            (&wrong_hash, &signature),
+            (&artifact, &forged),
+        ] {
+            let response = job_artifact_upload(
+                &backend,
+                job.uuid,
+                Some(&job.token),
+                artifact,
+                signature,
+                data,
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            assert_eq!(error_body(response).await.error, ErrorKind::BadRequest);
+        }
+
+        let reader = pool.read().await.unwrap();
+        assert!(reader.job_artifacts(job.uuid).await.unwrap().is_empty());
+    })
+    .await;
+}
+
+/// Send log lines of a job, waiting until they have been stored.
+async fn job_log_send(builder: &mut FakeBuilder, pool: &Pool, job: Uuid, lines: &[&str]) {
+    let stored = || async {
+        pool.read()
+            .await
+            .unwrap()
+            .job_logs(job)
+            .await
+            .unwrap()
+            .len()
+    };
+    let count = stored().await + lines.len();
+    builder
+        .send(ClientMessage::JobLog(JobLog {
+            job,
+            lines: lines.iter().map(|line| (*line).into()).collect(),
+        }))
+        .await
+        .unwrap();
+    while stored().await < count {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+/// Read server-sent events until the stream ends, returning their names and data.
+async fn sse_events(response: Response) -> Vec<(String, serde_json::Value)> {
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    body.split("\n\n")
+        .filter(|event| !event.trim().is_empty())
+        .map(|event| {
+            let mut name = String::new();
+            let mut data = String::new();
+            for line in event.lines() {
+                if let Some(value) = line.strip_prefix("event: ") {
+                    name = value.into();
+                } else if let Some(value) = line.strip_prefix("data: ") {
+                    data.push_str(value);
+                }
+            }
+            (name, serde_json::from_str(&data).unwrap())
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn can_stream_job_logs() {
+    with_backend_pool(|backend, pool| async move {
+        let (mut builder, handle, job) = job_connect(&backend, &pool).await;
+        builder
+            .send(ClientMessage::JobStage(JobStageUpdate {
+                job: job.uuid,
+                stage: JobStage::Fetch,
+            }))
+            .await
+            .unwrap();
+        job_log_send(&mut builder, &pool, job.uuid, &["fetching"]).await;
+
+        let response = get(&backend, &format!("/api/v1/jobs/{}/logs", job.uuid)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/event-stream"
+        );
+
+        // lines received after subscribing are streamed
+        builder
+            .send(ClientMessage::JobStage(JobStageUpdate {
+                job: job.uuid,
+                stage: JobStage::Build,
+            }))
+            .await
+            .unwrap();
+        job_log_send(&mut builder, &pool, job.uuid, &["compiling", "error"]).await;
+        builder
+            .send(ClientMessage::JobFailed(JobFailed {
+                job: job.uuid,
+                reason: "build failed".into(),
+            }))
+            .await
+            .unwrap();
+
+        let events = sse_events(response).await;
+        let logs = JobLogsResponse {
+            stages: vec![JobLogStage {
+                stage: "fetch".into(),
+                lines: vec!["fetching".into()],
+            }],
+        };
+        let line = |line: &str| JobLogLine {
+            stage: "build".into(),
+            line: line.into(),
+        };
+        assert_eq!(
+            events,
+            [
+                ("logs".into(), serde_json::to_value(logs).unwrap()),
+                (
+                    "line".into(),
+                    serde_json::to_value(line("compiling")).unwrap()
+                ),
+                ("line".into(), serde_json::to_value(line("error")).unwrap()),
+                (
+                    "end".into(),
+                    serde_json::to_value(JobLogEnd { success: false }).unwrap()
+                ),
+            ]
+        );
+
+        builder.close().await.unwrap();
+        handle.await.unwrap().unwrap_err();
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_record_job_failure() {
+    with_backend_pool(|backend, pool| async move {
+        let (mut builder, handle, job) = job_connect(&backend, &pool).await;
+        builder
+            .send(ClientMessage::JobFailed(JobFailed {
+                job: job.uuid,
+                reason: "build failed".into(),
+            }))
+            .await
+            .unwrap();
+        builder.close().await.unwrap();
+        handle.await.unwrap().unwrap_err();
+
+        let reader = pool.read().await.unwrap();
+        let info = reader.job_info(job.uuid).await.unwrap();
+        assert_eq!(info.success, Some(false));
+        assert_eq!(info.reason.as_deref(), Some("build failed"));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn job_complete_ignores_duplicate() {
+    with_backend_pool(|backend, pool| async move {
+        let (mut builder, handle, job) = job_connect(&backend, &pool).await;
+        builder
+            .send(ClientMessage::JobComplete(JobComplete {
+                job: job.uuid,
+                artifacts: vec![],
+            }))
+            .await
+            .unwrap();
+
+        // a duplicate completion declaring an artifact which was never uploaded
+        builder
+            .send(ClientMessage::JobComplete(JobComplete {
+                job: job.uuid,
+                artifacts: vec![JobArtifact {
+                    name: "metadata".into(),
+                    hash: hex::encode(Sha256::digest(b"")),
+                    size: 0,
+                    signature: None,
+                }],
+            }))
+            .await
+            .unwrap();
+        builder.close().await.unwrap();
+        handle.await.unwrap().unwrap_err();
+
+        let reader = pool.read().await.unwrap();
+        let info = reader.job_info(job.uuid).await.unwrap();
+        assert_eq!(info.success, Some(true));
+        assert_eq!(info.reason, None);
+        drop(reader);
+
+        let metrics = metrics_get(&backend).await;
+        let completed = format!("buildsrs_jobs_completed_total{{triple=\"{TRIPLE}\"}} 1");
+        assert!(metrics.contains(&completed), "missing {completed:?}");
+        assert!(!metrics
+            .iter()
+            .any(|line| line.starts_with("buildsrs_jobs_failed_total")));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_get_job_logs_ended() {
+    with_backend_pool(|backend, pool| async move {
+        let (mut builder, handle, job) = job_connect(&backend, &pool).await;
+        job_log_send(&mut builder, &pool, job.uuid, &["starting", "failed"]).await;
+        builder
+            .send(ClientMessage::JobFailed(JobFailed {
+                job: job.uuid,
+                reason: "build failed".into(),
+            }))
+            .await
+            .unwrap();
+        builder.close().await.unwrap();
+        handle.await.unwrap().unwrap_err();
+
+        let response = get(&backend, &format!("/api/v1/jobs/{}/logs", job.uuid)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let events = sse_events(response).await;
+        let logs = JobLogsResponse {
+            stages: vec![JobLogStage {
+                stage: "init".into(),
+                lines: vec!["starting".into(), "failed".into()],
+            }],
+        };
+        assert_eq!(
+            events,
+            [
+                ("logs".into(), serde_json::to_value(logs).unwrap()),
+                (
+                    "end".into(),
+                    serde_json::to_value(JobLogEnd { success: false }).unwrap()
+                ),
+            ]
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn expired_jobs_end_job_logs() {
+    with_backend_pool(|backend, pool| async move {
+        let (_, job) = job_assign(&backend, &pool).await;
+
+        // let the lease of the job run out
+        let writer = pool.write().await.unwrap();
+        assert!(writer
+            .job_heartbeat(job.uuid, Duration::ZERO)
+            .await
+            .unwrap());
+        writer.commit().await.unwrap();
+        tokio::time::sleep(Duration::from_secs(2)).await;
+
+        let response = get(&backend, &format!("/api/v1/jobs/{}/logs", job.uuid)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        backend.expire_jobs().await.unwrap();
+
+        let events = sse_events(response).await;
+        assert_eq!(
+            events.last(),
+            Some(&(
+                "end".into(),
+                serde_json::to_value(JobLogEnd { success: false }).unwrap()
+            ))
+        );
+
+        let reader = pool.read().await.unwrap();
+        let info = reader.job_info(job.uuid).await.unwrap();
+        assert_eq!(info.success, Some(false));
+        assert_eq!(info.reason.as_deref(), Some("lease expired"));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn cannot_get_job_logs_missing() {
+    with_backend(|backend| async move {
+        let response = get(&backend, &format!("/api/v1/jobs/{}/logs", Uuid::new_v4())).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(error_body(response).await.error, ErrorKind::NotFound);
+    })
+    .await;
+}
+
+/// Admin token added by [`admin_token_add`].
+const ADMIN_TOKEN: &str = "admin-secret";
+
+async fn admin_token_add(pool: &Pool) {
+    let writer = pool.write().await.unwrap();
+    writer.admin_token_add("admin", ADMIN_TOKEN).await.unwrap();
+    writer.commit().await.unwrap();
+}
+
+/// Send an admin API request, with an optional JSON body.
+async fn admin_request(
+    backend: &Backend,
+    method: &str,
+    uri: &str,
+    token: Option<&str>,
+    body: Option<serde_json::Value>,
+) -> Response {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(token) = token {
+        request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+    }
+    let body = match body {
+        Some(body) => {
+            request = request.header(header::CONTENT_TYPE, "application/json");
+            Body::from(body.to_string())
+        }
+        None => Body::empty(),
+    };
+    backend
+        .router()
+        .oneshot(request.body(body).unwrap())
+        .await
+        .unwrap()
+}
+
+async fn json_body<T: serde::de::DeserializeOwned>(response: Response) -> T {
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn cannot_admin_without_token() {
+    with_backend_pool(|backend, pool| async move {
+        admin_token_add(&pool).await;
+        for token in [None, Some("wrong")] {
+            let response =
+                admin_request(&backend, "GET", "/api/v1/admin/builders", token, None).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(error_body(response).await.error, ErrorKind::Unauthorized);
+        }
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_admin_builders() {
+    with_backend_pool(|backend, pool| async move {
+        admin_token_add(&pool).await;
+        let token = Some(ADMIN_TOKEN);
+        let key = random_key();
+        let public_key = key.public_key().to_openssh().unwrap();
+
+        let response = admin_request(
+            &backend,
+            "POST",
+            "/api/v1/admin/builders",
+            token,
+            Some(serde_json::json!({ "public_key": public_key, "comment": "test" })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let builder: BuilderResponse = json_body(response).await;
+        assert_eq!(builder.public_key, public_key);
+        assert_eq!(builder.comment, "test");
+        assert!(!builder.enabled);
+        assert!(builder.triples.is_empty());
+
+        // the same key cannot be registered twice
+        let response = admin_request(
+            &backend,
+            "POST",
+            "/api/v1/admin/builders",
+            token,
+            Some(serde_json::json!({ "public_key": public_key })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(error_body(response).await.error, ErrorKind::Conflict);
+
+        let uri = format!("/api/v1/admin/builders/{}", builder.uuid);
+        let response = admin_request(
+            &backend,
+            "PUT",
+            &format!("{uri}/triples/generic"),
+            token,
+            None,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = admin_request(
+            &backend,
+            "PATCH",
+            &uri,
+            token,
+            Some(serde_json::json!({ "enabled": true })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let edited: BuilderResponse = json_body(response).await;
+        assert!(edited.enabled);
+        assert_eq!(edited.comment, "test");
+        assert_eq!(edited.triples, ["generic".to_string()].into());
+
+        let response = admin_request(&backend, "GET", "/api/v1/admin/builders", token, None).await;
+        let builders: BuildersResponse = json_body(response).await;
+        assert_eq!(builders.builders, [edited]);
+
+        let response = admin_request(
+            &backend,
+            "DELETE",
+            &format!("{uri}/triples/generic"),
+            token,
+            None,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = admin_request(&backend, "GET", &uri, token, None).await;
+        let builder: BuilderResponse = json_body(response).await;
+        assert!(builder.triples.is_empty());
+
+        let response = admin_request(
+            &backend,
+            "GET",
+            &format!("/api/v1/admin/builders/{}", Uuid::new_v4()),
+            token,
+            None,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_admin_triples_and_crates() {
+    with_backend_pool(|backend, pool| async move {
+        admin_token_add(&pool).await;
+        let token = Some(ADMIN_TOKEN);
+
+        let response = admin_request(
+            &backend,
+            "POST",
+            "/api/v1/admin/triples",
+            token,
+            Some(serde_json::json!({ "name": TRIPLE })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let response = admin_request(
+            &backend,
+            "PATCH",
+            &format!("/api/v1/admin/triples/{TRIPLE}"),
+            token,
+            Some(serde_json::json!({ "enabled": true })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = admin_request(&backend, "GET", "/api/v1/admin/triples", token, None).await;
+        let triples: TriplesResponse = json_body(response).await;
+        assert!(triples.triples.contains(&TripleResponse {
+            name: TRIPLE.into(),
+            enabled: true,
+        }));
+
+        let uri = "/api/v1/admin/crates/serde";
+        let body = serde_json::json!({ "enabled": false });
+        let response = admin_request(&backend, "PATCH", uri, token, Some(body.clone())).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let writer = pool.write().await.unwrap();
+        writer.crate_add("serde").await.unwrap();
+        writer.commit().await.unwrap();
+        let response = admin_request(&backend, "PATCH", uri, token, Some(body)).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let reader = pool.read().await.unwrap();
+        assert!(!reader.crate_info("serde").await.unwrap().enabled);
+    })
+    .await;
+}
+
+/// Get the message of the badge at a path.
+async fn badge_message(backend: &Backend, uri: &str) -> String {
+    let response = get(backend, uri).await;
+    assert_eq!(response.status(), StatusCode::OK, "{uri}");
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/svg+xml");
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let svg = std::str::from_utf8(&body).unwrap();
+    let label = svg.split("aria-label=\"builds.rs: ").nth(1).unwrap();
+    label.split('"').next().unwrap().into()
+}
+
+#[tokio::test]
+async fn can_get_badge() {
+    with_backend_pool(|backend, pool| async move {
+        let other = "aarch64-unknown-linux-gnu";
+        let builder = Uuid::new_v4();
+        let writer = pool.write().await.unwrap();
+        writer
+            .builder_add(builder, random_key().public_key(), "test")
+            .await
+            .unwrap();
+        writer.builder_set_enabled(builder, true).await.unwrap();
+        writer.crate_add("serde").await.unwrap();
+        for version in ["1.0.0", "1.1.0"] {
+            writer
+                .crate_version_add("serde", version, "abcdef", false)
+                .await
+                .unwrap();
+        }
+        for triple in [TRIPLE, other] {
+            writer.triple_add(triple).await.unwrap();
+            writer.triple_enabled(triple, true).await.unwrap();
+            writer.builder_triple_add(builder, triple).await.unwrap();
+            writer
+                .task_create("serde", "1.0.0", "metadata", triple)
+                .await
+                .unwrap();
+        }
+        let job = writer
+            .job_request(builder, &[TRIPLE], Duration::from_secs(60))
+            .await
+            .unwrap()
+            .unwrap();
+        writer.job_finish(job, true).await.unwrap();
+        writer.commit().await.unwrap();
+
+        // the latest version has no tasks yet
+        assert_eq!(
+            badge_message(&backend, "/badge/serde.svg").await,
+            "not built"
+        );
+        assert_eq!(
+            badge_message(&backend, "/badge/serde/1.0.0.svg").await,
+            "pending"
+        );
+        assert_eq!(
+            badge_message(&backend, &format!("/badge/serde/=1.0.0/{TRIPLE}.svg")).await,
+            "passing"
+        );
+
+        let writer = pool.write().await.unwrap();
+        let job = writer
+            .job_request(builder, &[other], Duration::from_secs(60))
+            .await
+            .unwrap()
+            .unwrap();
+        writer.job_finish(job, false).await.unwrap();
+        writer.commit().await.unwrap();
+        assert_eq!(
+            badge_message(&backend, "/badge/serde/1.0.0.svg").await,
+            "failing"
+        );
+        assert_eq!(
+            badge_message(&backend, &format!("/badge/serde/1.0.0/{TRIPLE}.svg")).await,
+            "passing"
+        );
+
+        for uri in [
+            "/badge/missing.svg",
+            "/badge/serde",
+            "/badge/serde/1.0.0/a/b.svg",
+        ] {
+            let response = get(&backend, uri).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{uri}");
+        }
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_get_badge_cached() {
+    with_backend_pool(|backend, pool| async move {
+        let writer = pool.write().await.unwrap();
+        writer.crate_add("serde").await.unwrap();
+        writer.commit().await.unwrap();
+
+        let response = get(&backend, "/badge/serde.svg").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            "public, max-age=300"
+        );
+        let etag = response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_owned();
+
+        let response = get_with(
+            &backend,
+            "/badge/serde.svg",
+            &[(header::IF_NONE_MATCH, &etag)],
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], etag.as_str());
+    })
+    .await;
+}
+
+/// Get the metrics of the backend, returning the lines which are not comments.
+async fn metrics_get(backend: &Backend) -> Vec<String> {
+    let response = get(backend, "/metrics").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    std::str::from_utf8(&body)
+        .unwrap()
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(Into::into)
+        .collect()
+}
+
+#[tokio::test]
+async fn can_get_metrics() {
+    with_backend_pool(|backend, pool| async move {
+        let (mut builder, handle, _job) = job_connect(&backend, &pool).await;
+        let response = get(&backend, "/api/v1/crates/missing").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        artifact_add(&backend, &pool, &artifact_id(ArtifactKind::Tarball), b"tarball").await;
+        let response = get(
+            &backend,
+            "/api/v1/crates/serde/1.0.0/x86_64-unknown-linux-gnu/tarball",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let metrics = metrics_get(&backend).await;
+        for expected in [
+            "buildsrs_builders_connected 1".into(),
+            format!("buildsrs_jobs_dispatched_total{{triple=\"{TRIPLE}\"}} 1"),
+            format!("buildsrs_tasks_pending{{triple=\"{TRIPLE}\"}} 0"),
+            r#"buildsrs_http_requests_total{method="GET",route="/api/v1/crates/:crate",status="404"} 1"#.into(),
+            r#"buildsrs_storage_operation_duration_seconds_count{backend="s3",operation="get"} 1"#.into(),
+            "buildsrs_database_connections 1".into(),
+        ] {
+            assert!(metrics.contains(&expected), "missing {expected:?}");
+        }
+
+        builder.close().await.unwrap();
+        handle.await.unwrap().unwrap_err();
+        let metrics = metrics_get(&backend).await;
+        assert!(metrics.contains(&"buildsrs_builders_connected 0".into()));
     })
     .await;
 }
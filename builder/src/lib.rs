@@ -23,7 +23,7 @@ pub use options::StrategyOptions;
 #[cfg(feature = "websocket")]
 mod websocket;
 #[cfg(feature = "websocket")]
-pub use websocket::Connection;
+pub use websocket::{Connection, PendingUploads};
 
 /// Represents a strategy for building artifacts.
 ///
@@ -1,14 +1,20 @@
 #![allow(missing_docs)]
 use anyhow::Result;
-use buildsrs_builder::{Connection, StrategyOptions};
+use buildsrs_builder::{Connection, PendingUploads, StrategyOptions};
+use buildsrs_protocol::Capabilities;
 use clap::Parser;
 use duration_string::DurationString;
 use futures::StreamExt;
 use reqwest::Client;
 use ssh_key::{HashAlg, PrivateKey};
-use std::path::PathBuf;
+use std::{path::PathBuf, thread::available_parallelism};
+use sysinfo::{Disks, MemoryRefreshKind, RefreshKind, System};
 use tempfile::TempDir;
-use tokio::{fs::File, io::AsyncWriteExt, time::timeout};
+use tokio::{
+    fs::File,
+    io::AsyncWriteExt,
+    time::{sleep, timeout},
+};
 use tracing::*;
 use url::Url;
 
@@ -28,9 +34,23 @@ pub struct Options {
     #[clap(long, short, env)]
     pub private_key_file: PathBuf,
 
-    /// Target this builder will build.
-    #[clap(long, env, default_value = "x86_86-unknown-linux-gnu")]
-    pub target: String,
+    /// Targets this builder will build.
+    #[clap(
+        long = "target",
+        env = "TARGET",
+        value_delimiter = ',',
+        default_value = "x86_64-unknown-linux-gnu"
+    )]
+    pub targets: Vec<String>,
+
+    /// Rust toolchains installed for this builder.
+    #[clap(
+        long = "toolchain",
+        env = "TOOLCHAIN",
+        value_delimiter = ',',
+        default_value = "stable"
+    )]
+    pub toolchains: Vec<String>,
 
     #[clap(subcommand)]
     pub command: Command,
@@ -72,11 +92,46 @@ pub struct ConnectCommand {
     #[clap(long, env, default_value = "1m")]
     pub timeout_authenticate: DurationString,
 
+    /// Time to wait before reconnecting when the connection to the backend is lost.
+    #[clap(long, env, default_value = "10s")]
+    pub reconnect_delay: DurationString,
+
     /// Job many jobs to run in parallel.
     #[clap(long, env, default_value = "1")]
     pub parallel: usize,
 }
 
+impl Options {
+    /// Determine the capabilities of this builder.
+    fn capabilities(&self) -> Capabilities {
+        let system = System::new_with_specifics(
+            RefreshKind::new().with_memory(MemoryRefreshKind::new().with_ram()),
+        );
+
+        // free space of the disk that crates are extracted to
+        let temp = std::env::temp_dir();
+        let disks = Disks::new_with_refreshed_list();
+        let disk = disks
+            .list()
+            .iter()
+            .filter(|disk| temp.starts_with(disk.mount_point()))
+            .max_by_key(|disk| disk.mount_point().as_os_str().len())
+            .map(|disk| disk.available_space())
+            .unwrap_or_default();
+
+        Capabilities {
+            triples: self.targets.iter().cloned().collect(),
+            toolchains: self.toolchains.iter().cloned().collect(),
+            cpus: available_parallelism()
+                .map(|cpus| cpus.get().try_into().unwrap_or(u32::MAX))
+                .unwrap_or(1),
+            memory: system.total_memory(),
+            disk,
+            strategy: self.strategy.name().into(),
+        }
+    }
+}
+
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<()> {
     let options = Options::parse();
@@ -91,29 +146,48 @@ async fn main() -> Result<()> {
         private_key.fingerprint(HashAlg::Sha512)
     );
 
+    let capabilities = options.capabilities();
     match options.command {
         Command::Connect(options) => {
-            debug!("Connecting to WebSocket");
-            let mut connection = timeout(
-                options.timeout_connect.into(),
-                Connection::connect(private_key, &options.websocket),
-            )
-            .await??;
-            info!("Connected to {}", options.websocket);
-
-            debug!("Authenticating with WebSocket",);
-            timeout(
-                options.timeout_authenticate.into(),
-                connection.authenticate(),
-            )
-            .await??;
-            info!("Authenticated with WebSocket");
-
-            debug!("Synchronizing task list");
-            connection.tasks_sync().await?;
-
-            debug!("Handling events");
-            connection.handle().await?;
+            info!("Builder capabilities {capabilities:?}");
+
+            // uploads which are pending when the connection is lost are resumed after reconnecting
+            let mut pending = PendingUploads::default();
+            loop {
+                debug!("Connecting to WebSocket");
+                let mut connection = timeout(
+                    options.timeout_connect.into(),
+                    Connection::connect(
+                        private_key.clone(),
+                        &options.websocket,
+                        capabilities.clone(),
+                    ),
+                )
+                .await??
+                .with_pending(pending);
+                info!("Connected to {}", options.websocket);
+
+                debug!("Authenticating with WebSocket",);
+                timeout(
+                    options.timeout_authenticate.into(),
+                    connection.authenticate(),
+                )
+                .await??;
+                info!("Authenticated with WebSocket");
+
+                debug!("Resuming pending uploads");
+                connection.resume_uploads().await?;
+
+                debug!("Synchronizing task list");
+                connection.tasks_sync().await?;
+
+                debug!("Handling events");
+                if let Err(error) = connection.handle().await {
+                    warn!("Connection lost: {error:#}");
+                }
+                pending = connection.into_pending();
+                sleep(options.reconnect_delay.into()).await;
+            }
         }
         Command::Build(build) => {
             let strategy = options.strategy.build().await?;
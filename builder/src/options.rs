@@ -37,6 +37,14 @@ fn can_parse_default_options() {
 }
 
 impl StrategyOptions {
+    /// Name of the strategy, which is advertised to the server.
+    pub fn name(&self) -> &'static str {
+        match self.strategy {
+            #[cfg(feature = "docker")]
+            StrategyName::Docker => "docker",
+        }
+    }
+
     /// Build strategy
     pub async fn build(&self) -> Result<DynStrategy> {
         let strategy: DynStrategy = match self.strategy {
@@ -1,101 +1,262 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use buildsrs_protocol::*;
-use futures::{SinkExt, StreamExt};
+use bytes::Bytes;
+use futures::{future, SinkExt, StreamExt, TryStreamExt};
 use ssh_key::{HashAlg, PrivateKey};
-use std::time::Duration;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
 use tokio::{
     net::TcpStream,
     select,
     sync::mpsc::{channel, Receiver, Sender},
-    task::JoinSet,
+    task::{AbortHandle, JoinSet},
     time::{interval, Interval},
 };
 use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
 use tracing::*;
-use url::Url;
+use url::{Position, Url};
+use uuid::Uuid;
+
+#[cfg(test)]
+mod tests;
 
 /// [`WebSocketStream`] connection type alias.
 type WebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
 
+/// Adapt a `WebSocket` connection into a transport of frames, skipping control messages.
+fn websocket_transport(websocket: WebSocket) -> BoxTransport {
+    let transport = websocket
+        .map_err(TransportError::new)
+        .try_filter_map(|message| {
+            future::ready(Ok(match message {
+                Message::Text(text) => Some(Frame::Text(text)),
+                Message::Binary(data) => Some(Frame::Binary(data)),
+                _other => None,
+            }))
+        })
+        .sink_map_err(TransportError::new)
+        .with(|frame| {
+            future::ready(Ok(match frame {
+                Frame::Text(text) => Message::Text(text),
+                Frame::Binary(data) => Message::Binary(data),
+            }))
+        });
+    Box::new(transport)
+}
+
+/// Events emitted by running jobs, which are forwarded to the backend.
 #[allow(dead_code)]
 pub enum Event {
-    Build(String),
+    /// Job has entered a new stage.
+    Stage(JobStageUpdate),
+    /// Job has produced log output.
+    Log(JobLog),
+    /// Job has produced an artifact, which needs to be uploaded.
+    Artifact {
+        /// UUID of job.
+        job: Uuid,
+        /// Name of artifact.
+        name: String,
+        /// Contents of artifact.
+        data: Bytes,
+    },
+    /// Job has completed successfully.
+    ///
+    /// This is only sent to the backend once all artifacts of the job have been uploaded.
+    Complete(JobComplete),
+    /// Job has failed.
+    Failed(JobFailed),
+}
+
+/// Artifacts which are being uploaded, and completions of jobs waiting for them.
+///
+/// These are kept outside of the [`Connection`], such that uploads resume from the offset the
+/// backend has received once the builder has reconnected.
+#[derive(Default)]
+pub struct PendingUploads {
+    /// Artifacts which are being uploaded, by job and artifact name.
+    uploads: BTreeMap<(Uuid, String), Bytes>,
+    /// Completions of jobs which are waiting for their uploads to finish.
+    completions: BTreeMap<Uuid, JobComplete>,
+}
+
+impl PendingUploads {
+    /// Determine if a job has artifacts which are being uploaded.
+    fn has_uploads(&self, job: Uuid) -> bool {
+        self.uploads.keys().any(|(upload, _)| *upload == job)
+    }
+
+    /// Discard all uploads and pending completion of a job.
+    fn discard(&mut self, job: Uuid) {
+        self.uploads.retain(|(upload, _), _| *upload != job);
+        self.completions.remove(&job);
+    }
 }
 
 /// `WebSocket` connection to receive jobs
 pub struct Connection {
     poll_timer: Interval,
-    /// Private key, used for authentication and artifact signing.
-    private_key: PrivateKey,
-    /// WebSocket connection.
-    websocket: WebSocket,
+    /// Timer for sending heartbeats of running jobs.
+    heartbeat_timer: Interval,
+    /// Signs messages with the private key, used for authentication and artifact signing.
+    signer: MessageSigner,
+    /// Identity of the server, which authentication challenges must be bound to.
+    server: String,
+    /// Optional protocol features negotiated with the server.
+    features: BTreeSet<Feature>,
+    /// Codec used to encode messages, JSON until negotiated otherwise.
+    codec: Codec,
+    /// Capabilities of this builder, advertised to the server.
+    capabilities: Capabilities,
+    /// Connection to the server.
+    transport: BoxTransport,
     /// List of currently running jobs.
     tasks: JoinSet<()>,
+    /// Handles to abort running jobs, by job UUID.
+    running: BTreeMap<Uuid, AbortHandle>,
     /// Event receiver.
     receiver: Receiver<Event>,
     /// Backlog of jobs, if there are more than can fit.
     backlog: Vec<Job>,
+    /// Artifacts which are being uploaded, and completions waiting for them.
+    pending: PendingUploads,
     /// Sender of events.
     sender: Sender<Event>,
 }
 
 impl Connection {
     /// Connect to `WebSocket` endpoint.
-    pub async fn connect(private_key: PrivateKey, url: &Url) -> Result<Self> {
+    pub async fn connect(
+        private_key: PrivateKey,
+        url: &Url,
+        capabilities: Capabilities,
+    ) -> Result<Self> {
         let (websocket, _) = connect_async(url.as_str()).await?;
-        Ok(Self::new(websocket, private_key))
+        // the server identity is the host (and port, if any) that we connected to.
+        let server = url[Position::BeforeHost..Position::AfterPort].to_string();
+        Ok(Self::new(
+            websocket_transport(websocket),
+            private_key,
+            server,
+            capabilities,
+        ))
     }
 
-    /// Create new connection.
-    pub fn new(websocket: WebSocket, private_key: PrivateKey) -> Self {
+    /// Create new connection over the given transport.
+    pub fn new(
+        transport: BoxTransport,
+        private_key: PrivateKey,
+        server: String,
+        capabilities: Capabilities,
+    ) -> Self {
         let (sender, receiver) = channel(16);
         Self {
             poll_timer: interval(Duration::from_secs(1)),
-            private_key,
-            websocket,
+            heartbeat_timer: interval(JOB_HEARTBEAT_INTERVAL),
+            signer: MessageSigner::new(private_key),
+            server,
+            features: BTreeSet::new(),
+            codec: Codec::default(),
+            capabilities,
+            transport,
             sender,
             receiver,
             tasks: Default::default(),
+            running: Default::default(),
             backlog: Default::default(),
+            pending: Default::default(),
         }
     }
 
+    /// Replace pending uploads, such as those of a previous connection.
+    ///
+    /// These are resumed by [`resume_uploads`](Self::resume_uploads).
+    #[must_use]
+    pub fn with_pending(self, pending: PendingUploads) -> Self {
+        Self { pending, ..self }
+    }
+
+    /// Take the pending uploads of this connection, to resume them on another one.
+    pub fn into_pending(self) -> PendingUploads {
+        self.pending
+    }
+
+    /// Optional protocol features negotiated with the server.
+    pub fn features(&self) -> &BTreeSet<Feature> {
+        &self.features
+    }
+
     /// Send a signed [`ClientMessage`].
     pub async fn send(&mut self, message: ClientMessage) -> Result<()> {
-        let signed = SignedMessage::new(&self.private_key, message)?;
-        let json = serde_json::to_string(&signed)?;
-        self.websocket.send(Message::Text(json)).await?;
+        let signed = self.signer.sign(&message)?;
+        let frame = self.codec.encode_frame(&signed)?;
+        self.transport.send(frame).await?;
         Ok(())
     }
 
-    /// Receive a [`ServerMessage`].
-    pub async fn recv(websocket: &mut WebSocket) -> Result<ServerMessage> {
-        loop {
-            match websocket.next().await {
-                Some(Ok(Message::Text(_text))) => todo!(),
-                _ => todo!(),
-            }
-        }
+    /// Receive a [`ServerMessage`] encoded with the given codec.
+    pub async fn recv(transport: &mut BoxTransport, codec: Codec) -> Result<ServerMessage> {
+        let Some(frame) = transport.next().await else {
+            bail!("Connection closed by server");
+        };
+        Ok(codec.decode_frame(&frame?)?)
+    }
+
+    /// Switch to the given codec for all following messages.
+    fn set_codec(&mut self, codec: Codec) {
+        self.codec = codec;
+        self.signer.set_codec(codec);
     }
 
     /// Authenticate to server.
     pub async fn authenticate(&mut self) -> Result<()> {
-        let fingerprint = self.private_key.public_key().fingerprint(HashAlg::Sha512);
-        self.send(ClientMessage::Hello(fingerprint)).await?;
+        let fingerprint = self.signer.key().public_key().fingerprint(HashAlg::Sha512);
+        let hello = Hello::new(
+            fingerprint,
+            [
+                Feature::JobEvents,
+                Feature::Capabilities,
+                Feature::JobLease,
+                Feature::ArtifactUpload,
+                Feature::Cbor,
+            ]
+            .into(),
+        );
+        self.send(ClientMessage::Hello(hello)).await?;
         let challenge = loop {
-            if let Some(message) = self.websocket.next().await {
-                let message: ServerMessage = match message? {
-                    Message::Text(text) => serde_json::from_str(&text)?,
-                    _other => continue,
-                };
-                match message {
-                    ServerMessage::ChallengeRequest(challenge) => break challenge,
-                    _ => continue,
+            match Self::recv(&mut self.transport, self.codec).await? {
+                ServerMessage::Welcome(welcome) => {
+                    info!(
+                        "Negotiated protocol version {} with features {:?}",
+                        welcome.version, welcome.features
+                    );
+                    self.features = welcome.features;
+                    // everything after the welcome is encoded with the negotiated codec
+                    if self.features.contains(&Feature::Cbor) {
+                        self.set_codec(Codec::Cbor);
+                    }
                 }
+                ServerMessage::Rejected(rejection) => bail!("Rejected by server: {rejection}"),
+                ServerMessage::ChallengeRequest(challenge) => break challenge,
+                _ => continue,
             }
         };
-        self.send(ClientMessage::ChallengeResponse(challenge))
+        if challenge.server != self.server {
+            bail!(
+                "Challenge issued for {:?}, but connected to {:?}",
+                challenge.server,
+                self.server
+            );
+        }
+        let response = challenge.respond(self.signer.key())?;
+        self.send(ClientMessage::ChallengeResponse(response))
             .await?;
+        if self.features.contains(&Feature::Capabilities) {
+            self.send(ClientMessage::Capabilities(self.capabilities.clone()))
+                .await?;
+        }
         Ok(())
     }
 
@@ -103,10 +264,27 @@ impl Connection {
     pub async fn tasks_sync(&mut self) -> Result<()> {
         if self.tasks.len() < 4 {
             info!("Requesting another task");
-            self.send(ClientMessage::JobRequest(JobRequest {
-                target: "generic".to_string(),
-            }))
-            .await?;
+            // servers which do not know our capabilities need to be told which target we build
+            let target = if self.features.contains(&Feature::Capabilities) {
+                None
+            } else {
+                self.capabilities.triples.first().cloned()
+            };
+            self.send(ClientMessage::JobRequest(JobRequest { target }))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Send heartbeats for all running jobs, to keep their leases.
+    pub async fn heartbeat(&mut self) -> Result<()> {
+        if !self.features.contains(&Feature::JobLease) {
+            return Ok(());
+        }
+        let jobs: Vec<Uuid> = self.running.keys().copied().collect();
+        for job in jobs {
+            self.send(ClientMessage::JobHeartbeat(JobHeartbeat { job }))
+                .await?;
         }
         Ok(())
     }
@@ -115,9 +293,14 @@ impl Connection {
     pub async fn handle_iter(&mut self) -> Result<()> {
         select! {
             _tick = self.poll_timer.tick() => self.tasks_sync().await?,
-            message = Self::recv(&mut self.websocket) => self.handle_message(message?),
-            _result = self.tasks.join_next() => self.handle_done().await?,
-            _event = self.receiver.recv() => {},
+            _tick = self.heartbeat_timer.tick() => self.heartbeat().await?,
+            message = Self::recv(&mut self.transport, self.codec) => self.handle_message(message?).await?,
+            _result = self.tasks.join_next(), if !self.tasks.is_empty() => self.handle_done().await?,
+            event = self.receiver.recv() => {
+                if let Some(event) = event {
+                    self.handle_event(event).await?;
+                }
+            },
         }
         Ok(())
     }
@@ -130,14 +313,14 @@ impl Connection {
     }
 
     async fn handle_done(&mut self) -> Result<()> {
+        self.running.retain(|_, handle| !handle.is_finished());
         if let Some(job) = self.backlog.pop() {
-            let sender = self.sender.clone();
-            self.tasks.spawn(Self::job(job, sender));
+            self.spawn_job(job);
         }
         Ok(())
     }
 
-    fn handle_message(&mut self, message: ServerMessage) {
+    async fn handle_message(&mut self, message: ServerMessage) -> Result<()> {
         match message {
             ServerMessage::JobList(jobs) => {
                 for job in jobs {
@@ -145,17 +328,147 @@ impl Connection {
                 }
             }
             ServerMessage::JobResponse(job) => self.handle_job(job),
-            ServerMessage::ChallengeRequest(_) => unreachable!(),
+            ServerMessage::CancelJob(cancel) => self.handle_cancel(&cancel),
+            ServerMessage::ArtifactAck(ack) => self.handle_artifact_ack(ack).await?,
+            ServerMessage::ChallengeRequest(_)
+            | ServerMessage::Welcome(_)
+            | ServerMessage::Rejected(_) => unreachable!(),
         }
+        Ok(())
+    }
+
+    async fn handle_event(&mut self, event: Event) -> Result<()> {
+        match event {
+            Event::Stage(update) => self.send(ClientMessage::JobStage(update)).await?,
+            Event::Log(log) => self.send(ClientMessage::JobLog(log)).await?,
+            Event::Artifact { job, name, data } => self.upload_start(job, name, data).await?,
+            Event::Complete(complete) => {
+                if self.pending.has_uploads(complete.job) {
+                    self.pending.completions.insert(complete.job, complete);
+                } else {
+                    self.send_complete(complete).await?;
+                }
+            }
+            Event::Failed(failed) => {
+                self.pending.discard(failed.job);
+                self.send(ClientMessage::JobFailed(failed)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Send the completion of a job, signing the artifacts it declares.
+    async fn send_complete(&mut self, mut complete: JobComplete) -> Result<()> {
+        for artifact in &mut complete.artifacts {
+            artifact.signature = Some(artifact.sign(complete.job, self.signer.key())?);
+        }
+        self.send(ClientMessage::JobComplete(complete)).await
+    }
+
+    /// Start uploading an artifact.
+    ///
+    /// The upload starts with an empty chunk, which the backend acknowledges with the offset it
+    /// has already received. This resumes uploads that were interrupted.
+    async fn upload_start(&mut self, job: Uuid, name: String, data: Bytes) -> Result<()> {
+        if !self.features.contains(&Feature::ArtifactUpload) {
+            warn!(
+                "Server does not support artifact uploads, dropping artifact {name:?} of job {job}"
+            );
+            return Ok(());
+        }
+        self.pending.uploads.insert((job, name.clone()), data);
+        self.upload_probe(job, name).await
+    }
+
+    /// Ask the backend for the offset it has received of an upload, by sending an empty chunk.
+    async fn upload_probe(&mut self, job: Uuid, artifact: String) -> Result<()> {
+        self.send(ClientMessage::ArtifactChunk(ArtifactChunk {
+            job,
+            artifact,
+            offset: 0,
+            data: Bytes::new(),
+        }))
+        .await
+    }
+
+    /// Resume pending uploads, after reconnecting.
+    ///
+    /// Completions of jobs whose artifacts have all been uploaded are sent right away.
+    pub async fn resume_uploads(&mut self) -> Result<()> {
+        if !self.features.contains(&Feature::ArtifactUpload) {
+            self.pending = PendingUploads::default();
+            return Ok(());
+        }
+        let uploads: Vec<_> = self.pending.uploads.keys().cloned().collect();
+        for (job, artifact) in uploads {
+            self.upload_probe(job, artifact).await?;
+        }
+        let jobs: Vec<Uuid> = self.pending.completions.keys().copied().collect();
+        for job in jobs {
+            if !self.pending.has_uploads(job) {
+                if let Some(complete) = self.pending.completions.remove(&job) {
+                    self.send_complete(complete).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Continue an upload from the offset acknowledged by the backend.
+    async fn handle_artifact_ack(&mut self, ack: ArtifactAck) -> Result<()> {
+        let key = (ack.job, ack.artifact);
+        let Some(data) = self.pending.uploads.get(&key) else {
+            return Ok(());
+        };
+
+        let offset = usize::try_from(ack.offset).unwrap_or(usize::MAX);
+        if offset >= data.len() {
+            debug!("Uploaded artifact {:?} of job {}", key.1, key.0);
+            self.pending.uploads.remove(&key);
+            let (job, _) = key;
+            if !self.pending.has_uploads(job) {
+                if let Some(complete) = self.pending.completions.remove(&job) {
+                    self.send_complete(complete).await?;
+                }
+            }
+            return Ok(());
+        }
+
+        let end = data.len().min(offset + ARTIFACT_CHUNK_SIZE);
+        let chunk = data.slice(offset..end);
+        let (job, artifact) = key;
+        self.send(ClientMessage::ArtifactChunk(ArtifactChunk {
+            job,
+            artifact,
+            offset: ack.offset,
+            data: chunk,
+        }))
+        .await
     }
 
     fn handle_job(&mut self, job: Job) {
         if self.tasks.len() > 8 {
             self.backlog.push(job);
         } else {
-            let sender = self.sender.clone();
-            self.tasks.spawn(Self::job(job, sender));
+            self.spawn_job(job);
+        }
+    }
+
+    fn spawn_job(&mut self, job: Job) {
+        let uuid = job.uuid;
+        let sender = self.sender.clone();
+        let handle = self.tasks.spawn(Self::job(job, sender));
+        self.running.insert(uuid, handle);
+    }
+
+    /// Abort a job that was cancelled by the server.
+    fn handle_cancel(&mut self, cancel: &JobCancel) {
+        info!("Job {} cancelled: {}", cancel.job, cancel.reason);
+        if let Some(handle) = self.running.remove(&cancel.job) {
+            handle.abort();
         }
+        self.backlog.retain(|job| job.uuid != cancel.job);
+        self.pending.discard(cancel.job);
     }
 
     #[allow(clippy::no_effect_underscore_binding)]
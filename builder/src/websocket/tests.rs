@@ -0,0 +1,198 @@
+use super::*;
+use buildsrs_protocol::testing::*;
+
+const SERVER: &str = "builds.rs";
+const TRIPLE: &str = "x86_64-unknown-linux-gnu";
+
+fn server_features() -> BTreeSet<Feature> {
+    [
+        Feature::JobEvents,
+        Feature::Capabilities,
+        Feature::JobLease,
+        Feature::ArtifactUpload,
+        Feature::Cbor,
+    ]
+    .into()
+}
+
+fn capabilities() -> Capabilities {
+    Capabilities {
+        triples: [TRIPLE.into()].into(),
+        toolchains: ["stable".into()].into(),
+        cpus: 4,
+        strategy: "docker".into(),
+        ..Default::default()
+    }
+}
+
+/// Create a builder connection and a fake server it is connected to.
+fn connection(server: &str) -> (Connection, FakeServer) {
+    let (left, right) = duplex();
+    let key = random_key();
+    let fake = FakeServer::new(right, key.public_key().clone(), server);
+    let connection = Connection::new(left.boxed(), key, SERVER.into(), capabilities());
+    (connection, fake)
+}
+
+#[tokio::test]
+async fn authenticate_handshake() {
+    let (mut connection, mut server) = connection(SERVER);
+    let (result, advertised) = tokio::join!(connection.authenticate(), async {
+        server.accept(&server_features()).await.unwrap();
+        server.recv().await.unwrap()
+    });
+    result.unwrap();
+    assert_eq!(connection.features(), &server_features());
+    assert!(
+        matches!(advertised, ClientMessage::Capabilities(advertised) if advertised == capabilities())
+    );
+}
+
+#[tokio::test]
+async fn authenticate_rejected() {
+    let (mut connection, mut server) = connection(SERVER);
+    let (result, ()) = tokio::join!(connection.authenticate(), async {
+        server.recv().await.unwrap();
+        server
+            .send(ServerMessage::Rejected(Rejection::BuilderDisabled))
+            .await
+            .unwrap();
+    });
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn authenticate_rejects_other_server() {
+    let (mut connection, mut server) = connection("evil.rs");
+    let builder = async move {
+        // dropping the connection closes it, like exiting the builder would
+        let result = connection.authenticate().await;
+        drop(connection);
+        result
+    };
+    let (result, challenge) = tokio::join!(builder, async {
+        server.welcome(&server_features()).await.unwrap();
+        server.challenge().await
+    });
+    assert!(result.is_err());
+    assert!(matches!(challenge, Err(HarnessError::Closed)));
+}
+
+#[tokio::test]
+async fn authenticate_closed_stream() {
+    let (mut connection, mut server) = connection(SERVER);
+    let (result, ()) = tokio::join!(connection.authenticate(), async {
+        server.recv().await.unwrap();
+        server.close().await.unwrap();
+    });
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn request_jobs_with_capabilities() {
+    let (mut connection, mut server) = connection(SERVER);
+    let (result, _) = tokio::join!(connection.authenticate(), async {
+        server.accept(&server_features()).await.unwrap();
+        server.recv().await.unwrap()
+    });
+    result.unwrap();
+
+    connection.tasks_sync().await.unwrap();
+    assert!(matches!(
+        server.recv().await.unwrap(),
+        ClientMessage::JobRequest(JobRequest { target: None })
+    ));
+}
+
+#[tokio::test]
+async fn request_jobs_without_capabilities() {
+    let (mut connection, mut server) = connection(SERVER);
+    let (result, _) = tokio::join!(connection.authenticate(), async {
+        server.accept(&[Feature::JobEvents].into()).await.unwrap()
+    });
+    result.unwrap();
+
+    // servers which do not know the capabilities are told the target
+    connection.tasks_sync().await.unwrap();
+    assert!(matches!(
+        server.recv().await.unwrap(),
+        ClientMessage::JobRequest(JobRequest { target: Some(target) }) if target == TRIPLE
+    ));
+}
+
+/// Create a builder connection which has been authenticated by a fake server.
+async fn connection_authenticated() -> (Connection, FakeServer) {
+    let (mut connection, mut server) = connection(SERVER);
+    let (result, _) = tokio::join!(connection.authenticate(), async {
+        server.accept(&server_features()).await.unwrap();
+        server.recv().await.unwrap()
+    });
+    result.unwrap();
+    (connection, server)
+}
+
+#[tokio::test]
+async fn uploads_resume_after_reconnect() {
+    let job = Uuid::new_v4();
+    let data = Bytes::from_static(b"metadata");
+    let complete = JobComplete {
+        job,
+        artifacts: vec![JobArtifact {
+            name: "metadata".into(),
+            hash: "hash".into(),
+            size: data.len() as u64,
+            signature: None,
+        }],
+    };
+
+    // connection is lost while the artifact is being uploaded
+    let (mut connection, mut server) = connection_authenticated().await;
+    connection
+        .handle_event(Event::Artifact {
+            job,
+            name: "metadata".into(),
+            data: data.clone(),
+        })
+        .await
+        .unwrap();
+    connection
+        .handle_event(Event::Complete(complete.clone()))
+        .await
+        .unwrap();
+    assert!(matches!(
+        server.recv().await.unwrap(),
+        ClientMessage::ArtifactChunk(chunk) if chunk.offset == 0 && chunk.data.is_empty()
+    ));
+    drop(server);
+    let pending = connection.into_pending();
+
+    // upload is resumed on the new connection, followed by the completion
+    let (connection, mut server) = connection_authenticated().await;
+    let mut connection = connection.with_pending(pending);
+    connection.resume_uploads().await.unwrap();
+    assert!(matches!(
+        server.recv().await.unwrap(),
+        ClientMessage::ArtifactChunk(chunk) if chunk.job == job && chunk.data.is_empty()
+    ));
+    connection
+        .handle_artifact_ack(ArtifactAck {
+            job,
+            artifact: "metadata".into(),
+            offset: data.len() as u64,
+        })
+        .await
+        .unwrap();
+    let ClientMessage::JobComplete(received) = server.recv().await.unwrap() else {
+        panic!("expected job completion");
+    };
+    assert_eq!(received.job, complete.job);
+
+    // declared artifacts are signed by the builder
+    let [artifact] = &received.artifacts[..] else {
+        panic!("expected one artifact");
+    };
+    let signature = artifact.signature.as_deref().unwrap();
+    artifact
+        .verify(job, connection.signer.key().public_key(), signature)
+        .unwrap();
+}
@@ -1,27 +1,54 @@
 //! Types for the API of buildsrs
+#[cfg(feature = "schemars")]
+use schemars::JsonSchema;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeSet;
+use uuid::Uuid;
 
-/// Response for crate API
-#[derive(Clone, Debug)]
+/// Query for crate search API
+#[derive(Clone, Debug, Default)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
 pub struct CratesQuery {
     /// Crate name
     pub name: String,
+    /// Maximum number of crates to return
+    pub limit: Option<usize>,
+    /// Cursor returned by the previous page
+    pub cursor: Option<String>,
 }
 
-/// Response for crate API
+/// Crate matching a search
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct CrateSummary {
+    /// Crate name
+    pub name: String,
+    /// Latest version which is not yanked
+    pub latest_version: Option<String>,
+    /// Whether any artifacts have been built for this crate
+    pub has_artifacts: bool,
+}
+
+/// Response for crate search API
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
 pub struct CratesResponse {
-    /// Crates that matched
-    pub crates: Vec<String>,
+    /// Crates that matched, best matches first
+    pub crates: Vec<CrateSummary>,
+    /// Total number of crates that matched
+    pub total: u64,
+    /// Cursor for the next page, if there is one
+    pub next_cursor: Option<String>,
 }
 
 /// Response for crate API
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
 pub struct CrateResponse {
     /// Crate name
     pub name: String,
@@ -32,6 +59,7 @@ pub struct CrateResponse {
 /// Crate version response
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
 pub struct CrateVersionResponse {
     /// Crate name
     pub name: String,
@@ -41,18 +69,244 @@ pub struct CrateVersionResponse {
     pub yanked: bool,
     /// Digest of this crate
     pub checksum: String,
-    /// Artifacts
-    pub artifacts: BTreeSet<String>,
+    /// Artifacts that were built for this version
+    pub artifacts: Vec<ArtifactResponse>,
 }
 
 /// Crate artifact response
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
 pub struct ArtifactResponse {
     /// Name of the crate
     pub name: String,
     /// Version of the crate
     pub version: String,
+    /// Triple the artifact was built for
+    pub triple: String,
+    /// Kind of artifact
+    pub kind: String,
     /// Size in bytes
-    pub size: usize,
+    pub size: u64,
+    /// SHA-256 hash of the artifact, hex-encoded
+    pub hash: String,
+    /// Builder which built the artifact
+    pub builder: Uuid,
+    /// Time the artifact was built at, as a UNIX timestamp
+    pub built: i64,
+    /// Path to download the artifact from
+    pub url: String,
+}
+
+/// Downloads of a crate version for a triple on a single day
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct DownloadsDay {
+    /// Day, as the UNIX timestamp of its start in UTC
+    pub date: i64,
+    /// Number of downloads
+    pub downloads: u64,
+}
+
+/// Downloads of a crate version for a triple over time
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct DownloadsSeries {
+    /// Version of the crate
+    pub version: String,
+    /// Triple the artifacts were built for
+    pub triple: String,
+    /// Total number of downloads
+    pub total: u64,
+    /// Downloads of every day with downloads, oldest first
+    pub days: Vec<DownloadsDay>,
+}
+
+/// Crate downloads response
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct CrateDownloadsResponse {
+    /// Crate name
+    pub name: String,
+    /// Downloads by version and triple
+    pub series: Vec<DownloadsSeries>,
+}
+
+/// Query for uploading an artifact of a job
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct ArtifactUploadQuery {
+    /// SHA-256 hash of the artifact, hex-encoded
+    pub hash: String,
+    /// Size of the artifact, in bytes
+    pub size: u64,
+    /// Signature of the artifact by the builder
+    pub signature: String,
+}
+
+/// Lines of a job log in one stage
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct JobLogStage {
+    /// Name of the stage
+    pub stage: String,
+    /// Lines of output
+    pub lines: Vec<String>,
+}
+
+/// Stored logs of a job, grouped by stage
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct JobLogsResponse {
+    /// Stages in the order they were run
+    pub stages: Vec<JobLogStage>,
+}
+
+/// Line added to the log of a running job
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct JobLogLine {
+    /// Stage the job was in
+    pub stage: String,
+    /// Line of output
+    pub line: String,
+}
+
+/// End of a job
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct JobLogEnd {
+    /// Whether the job was successful
+    pub success: bool,
+}
+
+/// Builder, as returned by the admin API
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct BuilderResponse {
+    /// UUID of the builder
+    pub uuid: Uuid,
+    /// Public key of the builder, in OpenSSH format
+    pub public_key: String,
+    /// SHA-256 fingerprint of the public key
+    pub fingerprint: String,
+    /// Comment
+    pub comment: String,
+    /// Enabled state
+    pub enabled: bool,
+    /// Triples the builder is allowed to build
+    pub triples: BTreeSet<String>,
+}
+
+/// Builders, as returned by the admin API
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct BuildersResponse {
+    /// Registered builders
+    pub builders: Vec<BuilderResponse>,
+}
+
+/// Request to register a builder
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct BuilderAddRequest {
+    /// Public key of the builder, in OpenSSH format
+    pub public_key: String,
+    /// Comment
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub comment: String,
+}
+
+/// Request to edit a builder, fields which are not set are left as-is
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct BuilderEditRequest {
+    /// Set enabled state
+    pub enabled: Option<bool>,
+    /// Set comment
+    pub comment: Option<String>,
+}
+
+/// Triple, as returned by the admin API
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct TripleResponse {
+    /// Name of the triple
+    pub name: String,
+    /// Enabled state
+    pub enabled: bool,
+}
+
+/// Triples, as returned by the admin API
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct TriplesResponse {
+    /// Known triples
+    pub triples: Vec<TripleResponse>,
+}
+
+/// Request to add a triple
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct TripleAddRequest {
+    /// Name of the triple
+    pub name: String,
+}
+
+/// Request to enable or disable a triple or crate
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct EnabledRequest {
+    /// Enabled state
+    pub enabled: bool,
+}
+
+/// Kind of error returned by the API
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub enum ErrorKind {
+    /// Requested resource does not exist
+    NotFound,
+    /// Request is invalid
+    BadRequest,
+    /// Request is not authorized
+    Unauthorized,
+    /// Request conflicts with an existing resource
+    Conflict,
+    /// Service is temporarily unavailable
+    Unavailable,
+    /// Internal error
+    Internal,
+}
+
+/// Error response
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+pub struct ErrorResponse {
+    /// Kind of error
+    pub error: ErrorKind,
+    /// Human-readable description of the error
+    pub message: String,
 }
@@ -5,7 +5,7 @@ use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 use ssh_key::PublicKey;
 use std::time::Duration;
-use strum::EnumString;
+use strum::{AsRefStr, EnumString};
 #[cfg(feature = "proptest")]
 use test_strategy::Arbitrary;
 use url::Url;
@@ -55,7 +55,7 @@ pub struct CrateVersion {
 }
 
 /// Kind of artifact.
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, EnumString)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, EnumString, AsRefStr)]
 #[cfg_attr(feature = "proptest", derive(Arbitrary))]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[strum(serialize_all = "snake_case")]
@@ -80,6 +80,15 @@ impl ArtifactKind {
             Self::Coverage => "coverage.json",
         }
     }
+
+    /// Get MIME type for this artifact kind.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Metadata | Self::Coverage => "application/json",
+            Self::Tarball => "application/gzip",
+            Self::Debian => "application/vnd.debian.binary-package",
+        }
+    }
 }
 
 /// Artifact identifier.
@@ -107,6 +116,15 @@ pub enum ArtifactData {
         /// Bytes
         bytes: Bytes,
     },
+    /// Part of the raw data
+    Partial {
+        /// Bytes of the part
+        bytes: Bytes,
+        /// Offset of the part in the artifact
+        start: u64,
+        /// Size of the whole artifact
+        size: u64,
+    },
     /// Redirect
     Redirect {
         /// How long this link is valid for
@@ -120,7 +138,7 @@ impl ArtifactData {
     /// Get raw bytes, if exists
     pub fn bytes(&self) -> Option<&Bytes> {
         match self {
-            Self::Data { bytes } => Some(bytes),
+            Self::Data { bytes } | Self::Partial { bytes, .. } => Some(bytes),
             Self::Redirect { .. } => None,
         }
     }
@@ -160,6 +178,30 @@ pub struct CrateInfo {
     pub enabled: bool,
 }
 
+/// Crate matching a search
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CrateSearchEntry {
+    /// Name of this crate
+    pub name: String,
+    /// Latest version which is not yanked, preferring releases over pre-releases
+    pub latest_version: Option<String>,
+    /// Whether any artifacts have been recorded for this crate
+    pub has_artifacts: bool,
+    /// How well this crate matches the search, higher is better
+    pub rank: f64,
+}
+
+/// Position in the results of a crate search, which the next page starts after.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CrateSearchCursor {
+    /// Rank of the last crate of the previous page
+    pub rank: f64,
+    /// Name of the last crate of the previous page
+    pub name: String,
+}
+
 /// Crate version
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -188,6 +230,120 @@ pub struct JobInfo {
     pub version: String,
     /// Triple being built
     pub triple: String,
+    /// Kind of task being built
+    pub kind: String,
+    /// Current stage of the job
+    pub stage: String,
+    /// Time the job ended at, as a UNIX timestamp
+    pub ended: Option<i64>,
+    /// Whether the job was successful, if it has ended
+    pub success: Option<bool>,
+    /// Reason the job failed, if it has failed
+    pub reason: Option<String>,
+}
+
+/// Log line of a job
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct JobLogEntry {
+    /// ID of the line, increasing in the order lines were added
+    pub id: i64,
+    /// Stage the job was in when the line was added
+    pub stage: String,
+    /// Contents of the line
+    pub line: String,
+}
+
+/// Artifact uploaded by a job
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct JobArtifactInfo {
+    /// Name of the artifact
+    pub name: String,
+    /// SHA-256 hash of the artifact, hex-encoded
+    pub hash: String,
+    /// Size of the artifact, in bytes
+    pub size: u64,
+    /// Signature of the artifact by the builder
+    pub signature: String,
+}
+
+/// Version of a crate which an artifact has been built for
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ArtifactVersion {
+    /// Version
+    pub version: String,
+    /// Yanked status
+    pub yanked: bool,
+    /// SHA-256 hash of the artifact, hex-encoded
+    pub hash: String,
+}
+
+/// Artifact built for a crate version
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct VersionArtifactInfo {
+    /// Triple the artifact was built for
+    pub triple: String,
+    /// Kind of artifact
+    pub kind: String,
+    /// SHA-256 hash of the artifact, hex-encoded
+    pub hash: String,
+    /// Size of the artifact, in bytes
+    pub size: u64,
+    /// Builder which built the artifact
+    pub builder: Uuid,
+    /// Time the job which built the artifact ended at, as a UNIX timestamp
+    pub built: i64,
+}
+
+/// Target of a crate version, found in its metadata
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CrateTarget {
+    /// Name of the target
+    pub name: String,
+    /// Kind of the target, such as `bin`, `lib` or `cdylib`
+    pub kind: String,
+}
+
+/// Status of a task of a crate version, as of its most recent job
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TaskStatus {
+    /// Kind of the task
+    pub kind: String,
+    /// Triple the task builds for
+    pub triple: String,
+    /// Whether the most recent job succeeded, or `None` if it has not ended or there is none
+    pub success: Option<bool>,
+}
+
+/// Downloads of an artifact on a single day
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ArtifactDownloads {
+    /// Artifact which was downloaded
+    pub artifact: ArtifactId,
+    /// Day of the downloads, as the UNIX timestamp of its start in UTC
+    pub date: i64,
+    /// Number of downloads
+    pub downloads: u64,
+}
+
+/// Downloads of the artifacts of a crate version for a triple on a single day
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DownloadCount {
+    /// Version of the crate
+    pub version: String,
+    /// Triple the artifacts were built for
+    pub triple: String,
+    /// Day of the downloads, as the UNIX timestamp of its start in UTC
+    pub date: i64,
+    /// Number of downloads
+    pub downloads: u64,
 }
 
 /// Task
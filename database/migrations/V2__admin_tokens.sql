@@ -0,0 +1,7 @@
+-- tokens for the admin API, only their hashes are stored
+CREATE TABLE "admin_tokens" (
+    "id" BIGSERIAL PRIMARY KEY,
+    "name" TEXT NOT NULL UNIQUE,
+    "hash" TEXT NOT NULL UNIQUE,
+    "created" BIGINT NOT NULL DEFAULT (extract(epoch from now())::bigint)
+);
@@ -0,0 +1,11 @@
+-- task kind for debian packages of binary crates
+INSERT INTO task_kinds(name) VALUES ('debian');
+
+-- targets of crate versions, found in their metadata
+CREATE TABLE "crate_version_targets" (
+    "id" BIGSERIAL PRIMARY KEY,
+    "version" BIGINT NOT NULL REFERENCES crate_versions(id) ON DELETE CASCADE,
+    "name" TEXT NOT NULL,
+    "kind" TEXT NOT NULL,
+    UNIQUE ("version", "name", "kind")
+);
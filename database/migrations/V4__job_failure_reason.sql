@@ -0,0 +1,23 @@
+-- reason a job failed, as reported by the builder or the server
+ALTER TABLE "jobs" ADD COLUMN "reason" TEXT;
+
+-- recreate view so that it includes the new column
+DROP VIEW "jobs_view";
+CREATE VIEW "jobs_view" AS
+    SELECT
+        jobs.*,
+        triples.name AS triple_name,
+        builders.uuid AS builder_uuid,
+        crates.name AS crate_name,
+        crate_versions.version AS crate_version_version
+    FROM jobs
+    JOIN builders
+        ON jobs.builder = builders.id
+    JOIN tasks
+        ON jobs.task = tasks.id
+    JOIN triples
+        ON tasks.triple = triples.id
+    JOIN crate_versions
+        ON tasks.version = crate_versions.id
+    JOIN crates
+        ON crate_versions.crate = crates.id;
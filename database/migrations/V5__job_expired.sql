@@ -0,0 +1,2 @@
+-- whether a job ended because its lease expired, in which case its task is handed out again
+ALTER TABLE "jobs" ADD COLUMN "expired" BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,8 @@
+-- every job records at most one artifact per name, keep the latest of duplicates
+DELETE FROM "job_artifacts" a
+    USING "job_artifacts" b
+    WHERE a.job = b.job
+    AND a.name = b.name
+    AND a.id < b.id;
+
+ALTER TABLE "job_artifacts" ADD CONSTRAINT "job_artifacts_job_name_key" UNIQUE ("job", "name");
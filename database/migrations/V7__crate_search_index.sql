@@ -0,0 +1,5 @@
+-- similarity search on crate names
+CREATE INDEX "crates_name_trgm" ON "crates" USING GIN ("name" gin_trgm_ops);
+
+-- prefix search on lower case crate names
+CREATE INDEX "crates_name_lower_prefix" ON "crates" (lower("name") text_pattern_ops);
@@ -11,8 +11,14 @@ mod postgres;
 use crate::entity::Builder;
 use async_trait::async_trait;
 use buildsrs_common::entities::*;
+pub use deadpool::Status as PoolStatus;
 pub use postgres::*;
-use std::sync::Arc;
+use ssh_key::PublicKey;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    time::Duration,
+};
 use uuid::Uuid;
 
 #[cfg(feature = "options")]
@@ -48,6 +54,11 @@ pub trait Metadata: Send + Sync + std::fmt::Debug {
 
     /// Get a write handle to use for writing.
     async fn write(&self) -> Result<Box<dyn WriteHandle>, BoxError>;
+
+    /// Status of the connection pool, if connections are pooled.
+    fn status(&self) -> Option<PoolStatus> {
+        None
+    }
 }
 
 #[async_trait]
@@ -55,13 +66,60 @@ pub trait ReadHandle: Send + Sync {
     async fn builder_lookup(&self, fingerprint: &str) -> Result<Uuid, Error>;
     async fn builder_get(&self, builder: Uuid) -> Result<Builder, Error>;
     async fn builder_list(&self) -> Result<Vec<Uuid>, Error>;
+    async fn builder_triples(&self, builder: Uuid) -> Result<BTreeSet<String>, Error>;
+
+    async fn triple_list(&self) -> Result<BTreeSet<String>, Error>;
+    async fn triple_info(&self, triple: &str) -> Result<TargetInfo, Error>;
 
-    async fn crate_list(&self, name: &str) -> Result<Vec<String>, Error>;
+    /// Look up the name of an admin token, returning `None` if it does not exist.
+    async fn admin_token_check(&self, token: &str) -> Result<Option<String>, Error>;
+
+    /// Search for crates by name, best matches first, starting after the cursor if any.
+    async fn crate_search(
+        &self,
+        name: &str,
+        limit: i64,
+        after: Option<&CrateSearchCursor>,
+    ) -> Result<Vec<CrateSearchEntry>, Error>;
+    async fn crate_search_count(&self, name: &str) -> Result<i64, Error>;
     async fn crate_info(&self, name: &str) -> Result<CrateInfo, Error>;
     async fn crate_versions(&self, name: &str) -> Result<Vec<String>, Error>;
     async fn crate_version_info(&self, name: &str, version: &str) -> Result<VersionInfo, Error>;
+    async fn crate_version_list(&self, name: &str) -> Result<Vec<VersionInfo>, Error>;
+    async fn crate_version_artifacts(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> Result<Vec<VersionArtifactInfo>, Error>;
+    /// Get the versions of a crate which have a recorded artifact of a kind for a triple.
+    async fn crate_artifact_versions(
+        &self,
+        name: &str,
+        triple: &str,
+        kind: &str,
+    ) -> Result<Vec<ArtifactVersion>, Error>;
+    async fn crate_version_targets(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> Result<Vec<CrateTarget>, Error>;
+
+    /// Get the status of the tasks of a crate version, by their most recent job.
+    async fn crate_version_tasks(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> Result<Vec<TaskStatus>, Error>;
+
+    /// Get the daily downloads of a crate, by version and triple.
+    async fn crate_downloads(&self, name: &str) -> Result<Vec<DownloadCount>, Error>;
 
     async fn job_info(&self, job: Uuid) -> Result<JobInfo, Error>;
+    async fn job_artifacts(&self, job: Uuid) -> Result<Vec<JobArtifactInfo>, Error>;
+    async fn job_logs(&self, job: Uuid) -> Result<Vec<JobLogEntry>, Error>;
+
+    /// Count the pending tasks of every triple.
+    async fn task_queue(&self) -> Result<BTreeMap<String, u64>, Error>;
 }
 
 /// Handle used for writing to the metadata service.
@@ -71,6 +129,17 @@ pub trait ReadHandle: Send + Sync {
 /// unless they are committed, using the [`commit()`](WriteHandle::commit) call.
 #[async_trait]
 pub trait WriteHandle: ReadHandle + Send + Sync {
+    async fn builder_add(&self, uuid: Uuid, key: &PublicKey, comment: &str)
+        -> Result<(), BoxError>;
+    async fn builder_set_enabled(&self, builder: Uuid, enabled: bool) -> Result<(), BoxError>;
+    async fn builder_set_comment(&self, builder: Uuid, comment: &str) -> Result<(), BoxError>;
+    async fn builder_triple_add(&self, builder: Uuid, triple: &str) -> Result<(), BoxError>;
+    async fn builder_triple_remove(&self, builder: Uuid, triple: &str) -> Result<(), BoxError>;
+
+    async fn triple_add(&self, triple: &str) -> Result<(), BoxError>;
+    async fn triple_enabled(&self, triple: &str, enabled: bool) -> Result<(), BoxError>;
+
+    async fn crate_set_enabled(&self, name: &str, enabled: bool) -> Result<(), BoxError>;
     async fn crate_add(&self, name: &str) -> Result<(), BoxError>;
     async fn crate_version_add(
         &self,
@@ -80,7 +149,42 @@ pub trait WriteHandle: ReadHandle + Send + Sync {
         yanked: bool,
     ) -> Result<(), BoxError>;
 
+    async fn crate_version_target_add(
+        &self,
+        name: &str,
+        version: &str,
+        target: &CrateTarget,
+    ) -> Result<(), BoxError>;
+
     async fn tasks_create_all(&self, kind: &str, triple: &str) -> Result<(), BoxError>;
-    async fn job_request(&self, builder: Uuid) -> Result<Uuid, BoxError>;
+    /// Create a task for a crate version, unless it already exists.
+    async fn task_create(
+        &self,
+        name: &str,
+        version: &str,
+        kind: &str,
+        triple: &str,
+    ) -> Result<(), BoxError>;
+    async fn job_request(
+        &self,
+        builder: Uuid,
+        triples: &[&str],
+        lease: Duration,
+    ) -> Result<Option<Uuid>, BoxError>;
+    async fn job_heartbeat(&self, job: Uuid, lease: Duration) -> Result<bool, BoxError>;
+    async fn jobs_expire(&self) -> Result<Vec<Uuid>, BoxError>;
+    async fn job_stage(&self, job: Uuid, stage: &str) -> Result<(), BoxError>;
+    async fn job_log(&self, job: Uuid, line: &str) -> Result<i64, BoxError>;
+    async fn job_finish(&self, job: Uuid, success: bool) -> Result<(), BoxError>;
+    /// Mark a job as failed, recording the reason.
+    async fn job_fail(&self, job: Uuid, reason: &str) -> Result<(), BoxError>;
+    async fn job_artifact_add(&self, job: Uuid, artifact: &JobArtifactInfo)
+        -> Result<(), BoxError>;
+
+    /// Add downloads of artifacts.
+    ///
+    /// Downloads of artifacts which were not built by a successful job are ignored.
+    async fn artifact_downloads_add(&self, downloads: &[ArtifactDownloads])
+        -> Result<(), BoxError>;
     async fn commit(self: Box<Self>) -> Result<(), BoxError>;
 }
@@ -27,6 +27,10 @@ pub enum Command {
         #[clap(subcommand)]
         command: TripleCommand,
     },
+    Token {
+        #[clap(subcommand)]
+        command: TokenCommand,
+    },
 }
 
 #[derive(Parser, Debug)]
@@ -80,6 +84,20 @@ pub enum TripleCommand {
     List,
 }
 
+#[derive(Parser, Debug)]
+pub enum TokenCommand {
+    /// Create admin token, printing it.
+    Add {
+        #[clap(env)]
+        name: String,
+    },
+    /// Revoke admin token.
+    Remove {
+        #[clap(env)]
+        name: String,
+    },
+}
+
 impl Command {
     async fn apply(
         &self,
@@ -154,6 +172,16 @@ impl Command {
                     }
                 }
             },
+            Command::Token { command } => match command {
+                TokenCommand::Add { name } => {
+                    let token = hex::encode(rand::random::<[u8; 32]>());
+                    database.admin_token_add(name, &token).await?;
+                    println!("{token}");
+                }
+                TokenCommand::Remove { name } => {
+                    database.admin_token_remove(name).await?;
+                }
+            },
         }
 
         Ok(())
@@ -3,11 +3,18 @@
 use super::*;
 use deadpool::unmanaged::{Object, Pool as Deadpool};
 use futures::Stream;
+use sha2::{Digest, Sha256};
 use ssh_key::{HashAlg, PublicKey};
-use std::{collections::BTreeSet, ops::Deref, pin::Pin, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Deref,
+    pin::Pin,
+    sync::Arc,
+    time::Duration,
+};
 use tokio::task::JoinHandle;
 use tokio_postgres::{connect, AsyncMessage, Client, GenericClient, NoTls, Statement};
-pub use tokio_postgres::{Error, Transaction};
+pub use tokio_postgres::{error::SqlState, Transaction};
 use uuid::Uuid;
 
 #[macro_use]
@@ -21,6 +28,32 @@ use entity::*;
 #[cfg(feature = "temp")]
 pub use temp::*;
 
+/// Error in database operation.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    /// Requested entity does not exist
+    #[error("{0} not found")]
+    NotFound(&'static str),
+    /// Error reported by Postgres
+    #[error(transparent)]
+    Postgres(#[from] tokio_postgres::Error),
+}
+
+impl Error {
+    /// Determines if this error was caused by an entity which does not exist.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, Self::NotFound(_))
+    }
+
+    /// SQLSTATE code of this error, if it was reported by the database.
+    pub fn code(&self) -> Option<&SqlState> {
+        match self {
+            Self::NotFound(_) => None,
+            Self::Postgres(error) => error.code(),
+        }
+    }
+}
+
 statements!(
     /// Register new builder by SSH pubkey and comment.
     fn builder_register(uuid: Uuid, pubkey: i64) {
@@ -97,6 +130,25 @@ statements!(
         WHERE name = $1"
     }
 
+    /// Set crate enabled or disabled
+    fn crate_set_enabled(name: &str, enabled: bool) {
+        "UPDATE crates
+        SET enabled = $2
+        WHERE name = $1"
+    }
+
+    /// Register admin token by name and hash.
+    fn admin_token_register(name: &str, hash: &str) {
+        "INSERT INTO admin_tokens(name, hash)
+        VALUES ($1, $2)"
+    }
+
+    /// Remove an admin token
+    fn admin_token_remove(name: &str) {
+        "DELETE FROM admin_tokens
+        WHERE name = $1"
+    }
+
     /// Add a crate to the database.
     fn crate_add(name: &str) {
         "INSERT INTO crates(name) VALUES ($1)
@@ -127,14 +179,97 @@ statements!(
         WHERE uuid = $1"
     }
 
-    /// Add a log message for the job.
-    fn job_log(job: Uuid, line: &str) {
-        "INSERT INTO job_logs(job, stage, line)
+    /// Mark the job as finished.
+    fn job_finish(job: Uuid, success: bool) {
+        "UPDATE jobs
+        SET ended = extract(epoch from now())::bigint, success = $2
+        WHERE uuid = $1
+        AND ended IS NULL"
+    }
+
+    /// Mark the job as failed, recording the reason.
+    fn job_fail(job: Uuid, reason: &str) {
+        "UPDATE jobs
+        SET ended = extract(epoch from now())::bigint, success = false, reason = $2
+        WHERE uuid = $1
+        AND ended IS NULL"
+    }
+
+    /// Record an artifact uploaded by the job, replacing an earlier one of the same name.
+    fn job_artifact_add(job: Uuid, name: &str, hash: &str, size: i64, signature: &str) {
+        "INSERT INTO job_artifacts(job, name, hash, size, signature)
         VALUES (
             (SELECT id FROM jobs WHERE uuid = $1),
-            (SELECT stage FROM jobs WHERE uuid = $1),
-            $2
-        )"
+            $2,
+            $3,
+            $4,
+            $5
+        )
+        ON CONFLICT (job, name)
+        DO UPDATE SET hash = $3, size = $4, signature = $5"
+    }
+
+    /// Record a target of a crate version, found in its metadata.
+    fn crate_version_target_add(krate: &str, version: &str, name: &str, kind: &str) {
+        "INSERT INTO crate_version_targets(version, name, kind)
+        VALUES (
+            (SELECT id FROM crate_versions_view WHERE name = $1 AND version = $2),
+            $3,
+            $4
+        )
+        ON CONFLICT DO NOTHING"
+    }
+
+    /// Create a task for a crate version, unless it already exists.
+    fn task_create(krate: &str, version: &str, kind: &str, triple: &str) {
+        "INSERT INTO tasks(version, kind, triple)
+        VALUES (
+            (SELECT id FROM crate_versions_view WHERE name = $1 AND version = $2),
+            (SELECT id FROM task_kinds WHERE name = $3),
+            (SELECT id FROM triples WHERE name = $4)
+        )
+        ON CONFLICT DO NOTHING"
+    }
+
+    /// Add downloads of an artifact on a day.
+    ///
+    /// Downloads are attributed to the artifact of the most recent successful job.
+    fn artifact_downloads_add(
+        krate: &str,
+        version: &str,
+        triple: &str,
+        kind: &str,
+        date: i64,
+        downloads: i64
+    ) {
+        "WITH artifact AS (
+            SELECT job_artifacts.id
+            FROM job_artifacts
+            JOIN jobs
+            ON job_artifacts.job = jobs.id
+            JOIN tasks
+            ON jobs.task = tasks.id
+            JOIN crate_versions_view
+            ON tasks.version = crate_versions_view.id
+            JOIN triples
+            ON tasks.triple = triples.id
+            WHERE crate_versions_view.name = $1
+            AND crate_versions_view.version = $2
+            AND triples.name = $3
+            AND job_artifacts.name = $4
+            AND jobs.success
+            ORDER BY jobs.ended DESC
+            LIMIT 1
+        ), total AS (
+            UPDATE job_artifacts
+            SET downloads = downloads + $6
+            WHERE id IN (SELECT id FROM artifact)
+        )
+        INSERT INTO job_artifact_downloads(artifact, date, downloads)
+        SELECT id, $5, $6
+        FROM artifact
+        ON CONFLICT (artifact, date) DO UPDATE
+        SET downloads = job_artifact_downloads.downloads + EXCLUDED.downloads"
     }
 
     let builder_by_fingerprint = "
@@ -173,10 +308,59 @@ statements!(
         WHERE name = $1
     ";
 
-    let crate_list = "
-        SELECT name
+    let crate_search = "
+        SELECT
+            matches.name,
+            matches.rank,
+            (
+                SELECT version
+                FROM crate_versions
+                WHERE crate_versions.crate = matches.id
+                AND NOT yanked
+                AND version ~ '^[0-9]+[.][0-9]+[.][0-9]+'
+                ORDER BY
+                    version !~ '^[^+]*-' DESC,
+                    (regexp_match(version, '^([0-9]+)[.]([0-9]+)[.]([0-9]+)'))[1]::numeric DESC,
+                    (regexp_match(version, '^([0-9]+)[.]([0-9]+)[.]([0-9]+)'))[2]::numeric DESC,
+                    (regexp_match(version, '^([0-9]+)[.]([0-9]+)[.]([0-9]+)'))[3]::numeric DESC,
+                    version COLLATE \"C\" DESC
+                LIMIT 1
+            ) AS latest_version,
+            EXISTS (
+                SELECT 1
+                FROM job_artifacts
+                JOIN jobs
+                ON job_artifacts.job = jobs.id
+                JOIN tasks
+                ON jobs.task = tasks.id
+                JOIN crate_versions
+                ON tasks.version = crate_versions.id
+                WHERE crate_versions.crate = matches.id
+                AND jobs.success
+            ) AS has_artifacts
+        FROM (
+            SELECT
+                crates.id,
+                crates.name,
+                (lower(crates.name) = lower($1))::int * 2
+                    + (lower(crates.name) LIKE $2 || '%')::int
+                    + similarity(crates.name, $1)::float8 AS rank
+            FROM crates
+            WHERE crates.name % $1
+            OR lower(crates.name) LIKE $2 || '%'
+        ) AS matches
+        WHERE $4::float8 IS NULL
+        OR matches.rank < $4
+        OR (matches.rank = $4 AND matches.name > $5::text)
+        ORDER BY matches.rank DESC, matches.name
+        LIMIT $3
+    ";
+
+    let crate_search_count = "
+        SELECT count(*) AS count
         FROM crates
-        WHERE name % $1
+        WHERE crates.name % $1
+        OR lower(crates.name) LIKE $2 || '%'
     ";
 
     let crate_info = "
@@ -198,6 +382,109 @@ statements!(
         AND version = $2
     ";
 
+    let version_list = "
+        SELECT *
+        FROM crate_versions_view
+        WHERE name = $1
+    ";
+
+    let version_artifacts = "
+        SELECT DISTINCT ON (triples.name, job_artifacts.name)
+            triples.name AS triple,
+            job_artifacts.name AS kind,
+            job_artifacts.hash,
+            job_artifacts.size,
+            builders.uuid AS builder,
+            jobs.ended
+        FROM job_artifacts
+        JOIN jobs
+        ON job_artifacts.job = jobs.id
+        JOIN tasks
+        ON jobs.task = tasks.id
+        JOIN crate_versions_view
+        ON tasks.version = crate_versions_view.id
+        JOIN triples
+        ON tasks.triple = triples.id
+        JOIN builders
+        ON jobs.builder = builders.id
+        WHERE crate_versions_view.name = $1
+        AND crate_versions_view.version = $2
+        AND jobs.success
+        ORDER BY triples.name, job_artifacts.name, jobs.ended DESC
+    ";
+
+    let artifact_versions = "
+        SELECT DISTINCT ON (crate_versions_view.version)
+            crate_versions_view.version,
+            crate_versions_view.yanked,
+            job_artifacts.hash
+        FROM job_artifacts
+        JOIN jobs
+        ON job_artifacts.job = jobs.id
+        JOIN tasks
+        ON jobs.task = tasks.id
+        JOIN crate_versions_view
+        ON tasks.version = crate_versions_view.id
+        JOIN triples
+        ON tasks.triple = triples.id
+        WHERE crate_versions_view.name = $1
+        AND triples.name = $2
+        AND job_artifacts.name = $3
+        AND jobs.success
+        ORDER BY crate_versions_view.version, jobs.ended DESC
+    ";
+
+    let version_targets = "
+        SELECT crate_version_targets.name, crate_version_targets.kind
+        FROM crate_version_targets
+        JOIN crate_versions_view
+        ON crate_version_targets.version = crate_versions_view.id
+        WHERE crate_versions_view.name = $1
+        AND crate_versions_view.version = $2
+        ORDER BY crate_version_targets.kind, crate_version_targets.name
+    ";
+
+    let version_tasks = "
+        SELECT DISTINCT ON (tasks.id)
+            task_kinds.name AS kind,
+            triples.name AS triple,
+            jobs.success
+        FROM tasks
+        JOIN task_kinds
+        ON tasks.kind = task_kinds.id
+        JOIN triples
+        ON tasks.triple = triples.id
+        JOIN crate_versions_view
+        ON tasks.version = crate_versions_view.id
+        LEFT JOIN jobs
+        ON jobs.task = tasks.id
+        WHERE crate_versions_view.name = $1
+        AND crate_versions_view.version = $2
+        ORDER BY tasks.id, jobs.id DESC
+    ";
+
+    let crate_downloads = "
+        SELECT
+            crate_versions_view.version,
+            triples.name AS triple,
+            job_artifact_downloads.date,
+            sum(job_artifact_downloads.downloads)::bigint AS downloads
+        FROM job_artifact_downloads
+        JOIN job_artifacts
+        ON job_artifact_downloads.artifact = job_artifacts.id
+        JOIN jobs
+        ON job_artifacts.job = jobs.id
+        JOIN tasks
+        ON jobs.task = tasks.id
+        JOIN crate_versions_view
+        ON tasks.version = crate_versions_view.id
+        JOIN triples
+        ON tasks.triple = triples.id
+        WHERE crate_versions_view.name = $1
+        GROUP BY crate_versions_view.version, triples.name, job_artifact_downloads.date
+        ORDER BY crate_versions_view.version, triples.name, job_artifact_downloads.date
+    ";
+
     let task_list = "
         SELECT *
         FROM tasks_view
@@ -207,25 +494,128 @@ statements!(
         AND coalesce(triple = $4, true)
     ";
 
+    let task_queue = "
+        SELECT triples.name AS triple, count(tasks.id) AS count
+        FROM triples
+        LEFT JOIN tasks
+            ON tasks.triple = triples.id
+            AND EXISTS (
+                SELECT 1 FROM crate_versions
+                JOIN crates
+                    ON crate_versions.crate = crates.id
+                WHERE crate_versions.id = tasks.version
+                AND crates.enabled
+            )
+            AND NOT EXISTS (
+                SELECT 1 FROM jobs
+                WHERE jobs.task = tasks.id
+                AND NOT jobs.expired
+            )
+        WHERE triples.enabled
+        GROUP BY triples.name
+    ";
+
     let job_create = "
-        INSERT INTO jobs(uuid, builder, task, stage)
-        VALUES (
+        INSERT INTO jobs(uuid, builder, task, stage, started, timeout)
+        SELECT
             $3,
-            (SELECT id FROM builders WHERE uuid = $1),
-            (SELECT id FROM tasks WHERE
-                triple = (SELECT id FROM triples WHERE name = $2)
-            ),
-            (SELECT id FROM job_stages WHERE name = 'init')
+            builder_triples.builder,
+            tasks.id,
+            (SELECT id FROM job_stages WHERE name = 'init'),
+            extract(epoch from now())::bigint,
+            extract(epoch from now())::bigint + $4
+        FROM tasks
+        JOIN triples
+            ON tasks.triple = triples.id
+        JOIN builder_triples
+            ON builder_triples.triple = tasks.triple
+        JOIN builders
+            ON builder_triples.builder = builders.id
+        JOIN crate_versions
+            ON tasks.version = crate_versions.id
+        JOIN crates
+            ON crate_versions.crate = crates.id
+        WHERE builders.uuid = $1
+        AND builders.enabled
+        AND triples.name = ANY($2)
+        AND triples.enabled
+        AND crates.enabled
+        AND NOT EXISTS (
+            SELECT 1 FROM jobs
+            WHERE jobs.task = tasks.id
+            AND NOT jobs.expired
         )
+        ORDER BY tasks.id
+        LIMIT 1
+        FOR UPDATE OF tasks SKIP LOCKED
+        RETURNING (uuid)
+    ";
+
+    let job_heartbeat = "
+        UPDATE jobs
+        SET timeout = extract(epoch from now())::bigint + $2
+        WHERE uuid = $1
+        AND ended IS NULL
+        AND timeout >= extract(epoch from now())::bigint
+    ";
+
+    let jobs_expire = "
+        UPDATE jobs
+        SET ended = extract(epoch from now())::bigint, success = false, expired = true,
+            reason = 'lease expired'
+        WHERE ended IS NULL
+        AND timeout < extract(epoch from now())::bigint
         RETURNING (uuid)
     ";
 
     let job_info = "
-        SELECT *
+        SELECT jobs_view.*, job_stages.name AS stage_name, task_kinds.name AS kind_name
         FROM jobs_view
+        JOIN job_stages
+        ON jobs_view.stage = job_stages.id
+        JOIN tasks
+        ON jobs_view.task = tasks.id
+        JOIN task_kinds
+        ON tasks.kind = task_kinds.id
         WHERE uuid = $1
     ";
 
+    let job_log = "
+        INSERT INTO job_logs(job, stage, line)
+        VALUES (
+            (SELECT id FROM jobs WHERE uuid = $1),
+            (SELECT stage FROM jobs WHERE uuid = $1),
+            $2
+        )
+        RETURNING id
+    ";
+
+    let job_logs = "
+        SELECT job_logs.id, job_stages.name AS stage, job_logs.line
+        FROM job_logs
+        JOIN jobs
+        ON job_logs.job = jobs.id
+        JOIN job_stages
+        ON job_logs.stage = job_stages.id
+        WHERE jobs.uuid = $1
+        ORDER BY job_logs.id
+    ";
+
+    let job_artifacts = "
+        SELECT job_artifacts.*
+        FROM job_artifacts
+        JOIN jobs
+        ON job_artifacts.job = jobs.id
+        WHERE jobs.uuid = $1
+        ORDER BY job_artifacts.id
+    ";
+
+    let admin_token_lookup = "
+        SELECT name
+        FROM admin_tokens
+        WHERE hash = $1
+    ";
+
     let pubkey_add = "
         INSERT INTO pubkeys (encoded)
         VALUES ($1)
@@ -254,16 +644,18 @@ impl<T: GenericClient> Database<T> {
     pub async fn builder_lookup(&self, fingerprint: &str) -> Result<Uuid, Error> {
         let row = self
             .connection
-            .query_one(&self.statements.builder_by_fingerprint, &[&fingerprint])
-            .await?;
-        row.try_get("uuid")
+            .query_opt(&self.statements.builder_by_fingerprint, &[&fingerprint])
+            .await?
+            .ok_or(Error::NotFound("builder"))?;
+        Ok(row.try_get("uuid")?)
     }
 
     pub async fn builder_get(&self, builder: Uuid) -> Result<Builder, Error> {
         let row = self
             .connection
-            .query_one(&self.statements.builder_get, &[&builder])
-            .await?;
+            .query_opt(&self.statements.builder_get, &[&builder])
+            .await?
+            .ok_or(Error::NotFound("builder"))?;
         Ok(Builder {
             uuid: builder,
             public_key: {
@@ -280,7 +672,9 @@ impl<T: GenericClient> Database<T> {
             .connection
             .query(&self.statements.builder_list, &[])
             .await?;
-        rows.into_iter().map(|row| row.try_get("uuid")).collect()
+        rows.into_iter()
+            .map(|row| Ok(row.try_get("uuid")?))
+            .collect()
     }
 
     pub async fn builder_triples(&self, builder: Uuid) -> Result<BTreeSet<String>, Error> {
@@ -289,7 +683,7 @@ impl<T: GenericClient> Database<T> {
             .query(&self.statements.builder_triples, &[&builder])
             .await?;
         rows.into_iter()
-            .map(|row| row.try_get("triple_name"))
+            .map(|row| Ok(row.try_get("triple_name")?))
             .collect()
     }
 
@@ -298,14 +692,17 @@ impl<T: GenericClient> Database<T> {
             .connection
             .query(&self.statements.triple_list, &[])
             .await?;
-        rows.into_iter().map(|row| row.try_get("name")).collect()
+        rows.into_iter()
+            .map(|row| Ok(row.try_get("name")?))
+            .collect()
     }
 
     pub async fn triple_info(&self, triple: &str) -> Result<TargetInfo, Error> {
         let row = self
             .connection
-            .query_one(&self.statements.triple_info, &[&triple])
-            .await?;
+            .query_opt(&self.statements.triple_info, &[&triple])
+            .await?
+            .ok_or(Error::NotFound("triple"))?;
         Ok(TargetInfo {
             name: row.try_get("name")?,
             enabled: row.try_get("enabled")?,
@@ -338,46 +735,202 @@ impl<T: GenericClient> Database<T> {
             .collect()
     }
 
-    pub async fn job_request(&self, builder: Uuid, triple: &str) -> Result<Uuid, Error> {
+    /// Count the pending tasks of every triple.
+    ///
+    /// Tasks are pending if all of their jobs have expired. Disabled triples and tasks of
+    /// disabled crates are not counted.
+    pub async fn task_queue(&self) -> Result<BTreeMap<String, u64>, Error> {
+        let rows = self
+            .connection
+            .query(&self.statements.task_queue, &[])
+            .await?;
+        rows.into_iter()
+            .map(|row| {
+                let count: i64 = row.try_get("count")?;
+                Ok((
+                    row.try_get("triple")?,
+                    u64::try_from(count).unwrap_or_default(),
+                ))
+            })
+            .collect()
+    }
+
+    /// Create a job for the builder, for a pending task of one of the given triples.
+    ///
+    /// Disabled builders get no jobs. Only enabled triples which the builder is allowed to build
+    /// and tasks of enabled crates are considered. The job is leased to the builder for the given duration. Returns `None`
+    /// if there is no pending task.
+    pub async fn job_request(
+        &self,
+        builder: Uuid,
+        triples: &[&str],
+        lease: Duration,
+    ) -> Result<Option<Uuid>, Error> {
+        let lease = i64::try_from(lease.as_secs()).unwrap_or(i64::MAX);
         let row = self
             .connection
-            .query_one(
+            .query_opt(
                 &self.statements.job_create,
-                &[&builder, &triple, &Uuid::new_v4()],
+                &[&builder, &triples, &Uuid::new_v4(), &lease],
             )
             .await?;
-        row.try_get("uuid")
+        Ok(row.map(|row| row.try_get("uuid")).transpose()?)
+    }
+
+    /// Extend the lease of a running job.
+    ///
+    /// Returns `false` if the job has ended or its lease has already expired.
+    pub async fn job_heartbeat(&self, job: Uuid, lease: Duration) -> Result<bool, Error> {
+        let lease = i64::try_from(lease.as_secs()).unwrap_or(i64::MAX);
+        let count = self
+            .connection
+            .execute(&self.statements.job_heartbeat, &[&job, &lease])
+            .await?;
+        Ok(count > 0)
+    }
+
+    /// Mark running jobs whose lease has expired as failed, returning them.
+    ///
+    /// This returns the tasks of these jobs to the queue.
+    pub async fn jobs_expire(&self) -> Result<Vec<Uuid>, Error> {
+        let rows = self
+            .connection
+            .query(&self.statements.jobs_expire, &[])
+            .await?;
+        rows.iter().map(|row| Ok(row.try_get("uuid")?)).collect()
     }
 
     pub async fn job_info(&self, job: Uuid) -> Result<JobInfo, Error> {
         let row = self
             .connection
-            .query_one(&self.statements.job_info, &[&job])
-            .await?;
+            .query_opt(&self.statements.job_info, &[&job])
+            .await?
+            .ok_or(Error::NotFound("job"))?;
         Ok(JobInfo {
             uuid: row.try_get("uuid")?,
             version: row.try_get("crate_version_version")?,
             name: row.try_get("crate_name")?,
             builder: row.try_get("builder_uuid")?,
             triple: row.try_get("triple_name")?,
+            kind: row.try_get("kind_name")?,
+            stage: row.try_get("stage_name")?,
+            ended: row.try_get("ended")?,
+            success: row.try_get("success")?,
+            reason: row.try_get("reason")?,
         })
     }
 
-    /// Get info on a crate
-    pub async fn crate_list(&self, name: &str) -> Result<Vec<String>, Error> {
+    /// Add a log line for a job in its current stage, returning the ID of the line.
+    pub async fn job_log(&self, job: Uuid, line: &str) -> Result<i64, Error> {
+        let row = self
+            .connection
+            .query_one(&self.statements.job_log, &[&job, &line])
+            .await?;
+        Ok(row.try_get("id")?)
+    }
+
+    /// Get the log lines of a job, in the order they were added.
+    pub async fn job_logs(&self, job: Uuid) -> Result<Vec<JobLogEntry>, Error> {
+        let rows = self
+            .connection
+            .query(&self.statements.job_logs, &[&job])
+            .await?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(JobLogEntry {
+                    id: row.try_get("id")?,
+                    stage: row.try_get("stage")?,
+                    line: row.try_get("line")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Get the artifacts uploaded by a job
+    pub async fn job_artifacts(&self, job: Uuid) -> Result<Vec<JobArtifactInfo>, Error> {
+        let rows = self
+            .connection
+            .query(&self.statements.job_artifacts, &[&job])
+            .await?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(JobArtifactInfo {
+                    name: row.try_get("name")?,
+                    hash: row.try_get("hash")?,
+                    size: u64::try_from(row.try_get::<_, i64>("size")?).unwrap_or_default(),
+                    signature: row.try_get("signature")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Look up the name of an admin token, returning `None` if it does not exist.
+    pub async fn admin_token_check(&self, token: &str) -> Result<Option<String>, Error> {
+        let row = self
+            .connection
+            .query_opt(
+                &self.statements.admin_token_lookup,
+                &[&admin_token_hash(token)],
+            )
+            .await?;
+        Ok(row.map(|row| row.try_get("name")).transpose()?)
+    }
+
+    /// Search for crates by name, best matches first.
+    ///
+    /// Exact matches come first, then crates whose name starts with the query, then crates
+    /// ordered by the similarity of their name. Results are paged by the rank and name of the
+    /// last crate of the previous page, so crates added in between do not shift pages.
+    pub async fn crate_search(
+        &self,
+        name: &str,
+        limit: i64,
+        after: Option<&CrateSearchCursor>,
+    ) -> Result<Vec<CrateSearchEntry>, Error> {
         let rows = self
             .connection
-            .query(&self.statements.crate_list, &[&name])
+            .query(
+                &self.statements.crate_search,
+                &[
+                    &name,
+                    &like_prefix(name),
+                    &limit,
+                    &after.map(|cursor| cursor.rank),
+                    &after.map(|cursor| cursor.name.as_str()),
+                ],
+            )
+            .await?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(CrateSearchEntry {
+                    name: row.try_get("name")?,
+                    latest_version: row.try_get("latest_version")?,
+                    has_artifacts: row.try_get("has_artifacts")?,
+                    rank: row.try_get("rank")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Count the crates matching a search.
+    pub async fn crate_search_count(&self, name: &str) -> Result<i64, Error> {
+        let row = self
+            .connection
+            .query_one(
+                &self.statements.crate_search_count,
+                &[&name, &like_prefix(name)],
+            )
             .await?;
-        rows.into_iter().map(|row| row.try_get("name")).collect()
+        Ok(row.try_get("count")?)
     }
 
     /// Get info on a crate
     pub async fn crate_info(&self, name: &str) -> Result<CrateInfo, Error> {
         let info = self
             .connection
-            .query_one(&self.statements.crate_info, &[&name])
-            .await?;
+            .query_opt(&self.statements.crate_info, &[&name])
+            .await?
+            .ok_or(Error::NotFound("crate"))?;
         Ok(CrateInfo {
             name: info.try_get("name")?,
             enabled: info.try_get("enabled")?,
@@ -390,7 +943,9 @@ impl<T: GenericClient> Database<T> {
             .connection
             .query(&self.statements.crate_versions, &[&name])
             .await?;
-        rows.into_iter().map(|row| row.try_get("version")).collect()
+        rows.into_iter()
+            .map(|row| Ok(row.try_get("version")?))
+            .collect()
     }
 
     /// Get info on a crate version
@@ -401,8 +956,9 @@ impl<T: GenericClient> Database<T> {
     ) -> Result<VersionInfo, Error> {
         let info = self
             .connection
-            .query_one(&self.statements.version_info, &[&name, &version])
-            .await?;
+            .query_opt(&self.statements.version_info, &[&name, &version])
+            .await?
+            .ok_or(Error::NotFound("crate version"))?;
         Ok(VersionInfo {
             name: info.try_get("name")?,
             version: info.try_get("version")?,
@@ -410,9 +966,156 @@ impl<T: GenericClient> Database<T> {
             yanked: info.try_get("yanked")?,
         })
     }
+
+    /// Get the artifacts built for a crate version, by successful jobs.
+    ///
+    /// If an artifact was built more than once, only the most recent build is returned.
+    pub async fn crate_version_artifacts(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> Result<Vec<VersionArtifactInfo>, Error> {
+        let rows = self
+            .connection
+            .query(&self.statements.version_artifacts, &[&name, &version])
+            .await?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(VersionArtifactInfo {
+                    triple: row.try_get("triple")?,
+                    kind: row.try_get("kind")?,
+                    hash: row.try_get("hash")?,
+                    size: u64::try_from(row.try_get::<_, i64>("size")?).unwrap_or_default(),
+                    builder: row.try_get("builder")?,
+                    built: row.try_get("ended")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Get the targets of a crate version, found in its metadata.
+    pub async fn crate_version_targets(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> Result<Vec<CrateTarget>, Error> {
+        let rows = self
+            .connection
+            .query(&self.statements.version_targets, &[&name, &version])
+            .await?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(CrateTarget {
+                    name: row.try_get("name")?,
+                    kind: row.try_get("kind")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Get the status of the tasks of a crate version, by their most recent job.
+    pub async fn crate_version_tasks(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> Result<Vec<TaskStatus>, Error> {
+        let rows = self
+            .connection
+            .query(&self.statements.version_tasks, &[&name, &version])
+            .await?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(TaskStatus {
+                    kind: row.try_get("kind")?,
+                    triple: row.try_get("triple")?,
+                    success: row.try_get("success")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Get the daily downloads of a crate, by version and triple.
+    ///
+    /// Downloads of the different kinds of artifacts are added up.
+    pub async fn crate_downloads(&self, name: &str) -> Result<Vec<DownloadCount>, Error> {
+        let rows = self
+            .connection
+            .query(&self.statements.crate_downloads, &[&name])
+            .await?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(DownloadCount {
+                    version: row.try_get("version")?,
+                    triple: row.try_get("triple")?,
+                    date: row.try_get("date")?,
+                    downloads: u64::try_from(row.try_get::<_, i64>("downloads")?)
+                        .unwrap_or_default(),
+                })
+            })
+            .collect()
+    }
+
+    /// Get the versions of a crate which have a recorded artifact of a kind for a triple.
+    pub async fn crate_artifact_versions(
+        &self,
+        name: &str,
+        triple: &str,
+        kind: &str,
+    ) -> Result<Vec<ArtifactVersion>, Error> {
+        let rows = self
+            .connection
+            .query(&self.statements.artifact_versions, &[&name, &triple, &kind])
+            .await?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(ArtifactVersion {
+                    version: row.try_get("version")?,
+                    yanked: row.try_get("yanked")?,
+                    hash: row.try_get("hash")?,
+                })
+            })
+            .collect()
+    }
+
+    pub async fn crate_version_list(&self, name: &str) -> Result<Vec<VersionInfo>, Error> {
+        let rows = self
+            .connection
+            .query(&self.statements.version_list, &[&name])
+            .await?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(VersionInfo {
+                    name: row.try_get("name")?,
+                    version: row.try_get("version")?,
+                    checksum: row.try_get("checksum")?,
+                    yanked: row.try_get("yanked")?,
+                })
+            })
+            .collect()
+    }
 }
 
-pub type ConnectionStream = Pin<Box<dyn Stream<Item = Result<AsyncMessage, Error>> + Send>>;
+/// Lower case prefix of a `LIKE` pattern matching names which start with the given one.
+///
+/// Crate names may contain underscores, which are wildcards in patterns unless escaped.
+fn like_prefix(name: &str) -> String {
+    let mut pattern = String::with_capacity(name.len());
+    for c in name.to_lowercase().chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern
+}
+
+/// Hash of an admin token, which is what gets stored.
+fn admin_token_hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+pub type ConnectionStream =
+    Pin<Box<dyn Stream<Item = Result<AsyncMessage, tokio_postgres::Error>> + Send>>;
 
 impl Database<Client> {
     /// Create new [`Database`] from Postgres [`Client`].
@@ -444,7 +1147,7 @@ impl Database<Client> {
 impl Database<Transaction<'_>> {
     /// Commit this transaction.
     pub async fn commit(self) -> Result<(), Error> {
-        self.connection.commit().await
+        Ok(self.connection.commit().await?)
     }
 
     /// Add a builder
@@ -460,6 +1163,12 @@ impl Database<Transaction<'_>> {
         Ok(())
     }
 
+    /// Add an admin token, storing only its hash.
+    pub async fn admin_token_add(&self, name: &str, token: &str) -> Result<(), Error> {
+        self.admin_token_register(name, &admin_token_hash(token))
+            .await
+    }
+
     /// Add a pubkey.
     async fn pubkey_add(&self, pubkey: &PublicKey) -> Result<i64, Error> {
         let row = self
@@ -481,11 +1190,14 @@ impl Database<Transaction<'_>> {
 #[derive(Debug)]
 pub struct DatabaseConnection {
     database: Database,
-    connection: Option<JoinHandle<Result<(), Error>>>,
+    connection: Option<JoinHandle<Result<(), tokio_postgres::Error>>>,
 }
 
 impl DatabaseConnection {
-    pub fn new(database: Database, connection: Option<JoinHandle<Result<(), Error>>>) -> Self {
+    pub fn new(
+        database: Database,
+        connection: Option<JoinHandle<Result<(), tokio_postgres::Error>>>,
+    ) -> Self {
         Self {
             database,
             connection,
@@ -659,6 +1371,10 @@ impl Metadata for Pool {
             .await
             .map(|x| Box::new(x) as Box<dyn WriteHandle>)
     }
+
+    fn status(&self) -> Option<PoolStatus> {
+        Some(self.pool.status())
+    }
 }
 
 #[async_trait::async_trait]
@@ -678,8 +1394,33 @@ where
         self.database().builder_list().await
     }
 
-    async fn crate_list(&self, name: &str) -> Result<Vec<String>, Error> {
-        self.database().crate_list(name).await
+    async fn builder_triples(&self, builder: Uuid) -> Result<BTreeSet<String>, Error> {
+        self.database().builder_triples(builder).await
+    }
+
+    async fn triple_list(&self) -> Result<BTreeSet<String>, Error> {
+        self.database().triple_list().await
+    }
+
+    async fn triple_info(&self, triple: &str) -> Result<TargetInfo, Error> {
+        self.database().triple_info(triple).await
+    }
+
+    async fn admin_token_check(&self, token: &str) -> Result<Option<String>, Error> {
+        self.database().admin_token_check(token).await
+    }
+
+    async fn crate_search(
+        &self,
+        name: &str,
+        limit: i64,
+        after: Option<&CrateSearchCursor>,
+    ) -> Result<Vec<CrateSearchEntry>, Error> {
+        self.database().crate_search(name, limit, after).await
+    }
+
+    async fn crate_search_count(&self, name: &str) -> Result<i64, Error> {
+        self.database().crate_search_count(name).await
     }
 
     async fn crate_info(&self, name: &str) -> Result<CrateInfo, Error> {
@@ -694,13 +1435,119 @@ where
         self.database().job_info(job).await
     }
 
+    async fn job_artifacts(&self, job: Uuid) -> Result<Vec<JobArtifactInfo>, Error> {
+        self.database().job_artifacts(job).await
+    }
+
+    async fn job_logs(&self, job: Uuid) -> Result<Vec<JobLogEntry>, Error> {
+        self.database().job_logs(job).await
+    }
+
+    async fn task_queue(&self) -> Result<BTreeMap<String, u64>, Error> {
+        self.database().task_queue().await
+    }
+
     async fn crate_version_info(&self, name: &str, version: &str) -> Result<VersionInfo, Error> {
         self.database().crate_version_info(name, version).await
     }
+
+    async fn crate_version_list(&self, name: &str) -> Result<Vec<VersionInfo>, Error> {
+        self.database().crate_version_list(name).await
+    }
+
+    async fn crate_version_artifacts(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> Result<Vec<VersionArtifactInfo>, Error> {
+        self.database().crate_version_artifacts(name, version).await
+    }
+
+    async fn crate_artifact_versions(
+        &self,
+        name: &str,
+        triple: &str,
+        kind: &str,
+    ) -> Result<Vec<ArtifactVersion>, Error> {
+        self.database()
+            .crate_artifact_versions(name, triple, kind)
+            .await
+    }
+
+    async fn crate_version_targets(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> Result<Vec<CrateTarget>, Error> {
+        self.database().crate_version_targets(name, version).await
+    }
+
+    async fn crate_version_tasks(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> Result<Vec<TaskStatus>, Error> {
+        self.database().crate_version_tasks(name, version).await
+    }
+
+    async fn crate_downloads(&self, name: &str) -> Result<Vec<DownloadCount>, Error> {
+        self.database().crate_downloads(name).await
+    }
 }
 
 #[async_trait::async_trait]
 impl WriteHandle for Writer {
+    async fn builder_add(
+        &self,
+        uuid: Uuid,
+        key: &PublicKey,
+        comment: &str,
+    ) -> Result<(), BoxError> {
+        self.database().builder_add(uuid, key, comment).await?;
+        Ok(())
+    }
+
+    async fn builder_set_enabled(&self, builder: Uuid, enabled: bool) -> Result<(), BoxError> {
+        self.database()
+            .builder_set_enabled(builder, enabled)
+            .await?;
+        Ok(())
+    }
+
+    async fn builder_set_comment(&self, builder: Uuid, comment: &str) -> Result<(), BoxError> {
+        self.database()
+            .builder_set_comment(builder, comment)
+            .await?;
+        Ok(())
+    }
+
+    async fn builder_triple_add(&self, builder: Uuid, triple: &str) -> Result<(), BoxError> {
+        self.database().builder_triple_add(builder, triple).await?;
+        Ok(())
+    }
+
+    async fn builder_triple_remove(&self, builder: Uuid, triple: &str) -> Result<(), BoxError> {
+        self.database()
+            .builder_triple_remove(builder, triple)
+            .await?;
+        Ok(())
+    }
+
+    async fn triple_add(&self, triple: &str) -> Result<(), BoxError> {
+        self.database().triple_add(triple).await?;
+        Ok(())
+    }
+
+    async fn triple_enabled(&self, triple: &str, enabled: bool) -> Result<(), BoxError> {
+        self.database().triple_enabled(triple, enabled).await?;
+        Ok(())
+    }
+
+    async fn crate_set_enabled(&self, name: &str, enabled: bool) -> Result<(), BoxError> {
+        self.database().crate_set_enabled(name, enabled).await?;
+        Ok(())
+    }
+
     async fn crate_add(&self, name: &str) -> Result<(), BoxError> {
         self.database().crate_add(name).await?;
         Ok(())
@@ -724,11 +1571,110 @@ impl WriteHandle for Writer {
         Ok(())
     }
 
-    async fn job_request(&self, builder: Uuid) -> Result<Uuid, BoxError> {
-        let uuid = self.database().job_request(builder, "generic").await?;
+    async fn crate_version_target_add(
+        &self,
+        name: &str,
+        version: &str,
+        target: &CrateTarget,
+    ) -> Result<(), BoxError> {
+        self.database()
+            .crate_version_target_add(name, version, &target.name, &target.kind)
+            .await?;
+        Ok(())
+    }
+
+    async fn task_create(
+        &self,
+        name: &str,
+        version: &str,
+        kind: &str,
+        triple: &str,
+    ) -> Result<(), BoxError> {
+        self.database()
+            .task_create(name, version, kind, triple)
+            .await?;
+        Ok(())
+    }
+
+    async fn job_request(
+        &self,
+        builder: Uuid,
+        triples: &[&str],
+        lease: Duration,
+    ) -> Result<Option<Uuid>, BoxError> {
+        let uuid = self.database().job_request(builder, triples, lease).await?;
         Ok(uuid)
     }
 
+    async fn job_heartbeat(&self, job: Uuid, lease: Duration) -> Result<bool, BoxError> {
+        let active = self.database().job_heartbeat(job, lease).await?;
+        Ok(active)
+    }
+
+    async fn jobs_expire(&self) -> Result<Vec<Uuid>, BoxError> {
+        let jobs = self.database().jobs_expire().await?;
+        Ok(jobs)
+    }
+
+    async fn job_stage(&self, job: Uuid, stage: &str) -> Result<(), BoxError> {
+        self.database().job_stage(job, stage).await?;
+        Ok(())
+    }
+
+    async fn job_log(&self, job: Uuid, line: &str) -> Result<i64, BoxError> {
+        let id = self.database().job_log(job, line).await?;
+        Ok(id)
+    }
+
+    async fn job_finish(&self, job: Uuid, success: bool) -> Result<(), BoxError> {
+        self.database().job_finish(job, success).await?;
+        Ok(())
+    }
+
+    async fn job_fail(&self, job: Uuid, reason: &str) -> Result<(), BoxError> {
+        self.database().job_fail(job, reason).await?;
+        Ok(())
+    }
+
+    async fn job_artifact_add(
+        &self,
+        job: Uuid,
+        artifact: &JobArtifactInfo,
+    ) -> Result<(), BoxError> {
+        let size = i64::try_from(artifact.size).unwrap_or(i64::MAX);
+        self.database()
+            .job_artifact_add(
+                job,
+                &artifact.name,
+                &artifact.hash,
+                size,
+                &artifact.signature,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn artifact_downloads_add(
+        &self,
+        downloads: &[ArtifactDownloads],
+    ) -> Result<(), BoxError> {
+        for entry in downloads {
+            let task = &entry.artifact.task;
+            let count = i64::try_from(entry.downloads).unwrap_or(i64::MAX);
+            self.database()
+                .artifact_downloads_add(
+                    &task.krate,
+                    &task.version,
+                    &task.triple,
+                    task.kind.as_ref(),
+                    entry.date,
+                    count,
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
     async fn commit(self: Box<Self>) -> Result<(), BoxError> {
         let writer: Writer = *self;
         writer.commit().await?;
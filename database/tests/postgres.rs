@@ -1,12 +1,18 @@
 use buildsrs_database::{
-    entity::{ArtifactKind, Task},
-    Pool, TempDatabase,
+    entity::{
+        ArtifactDownloads, ArtifactId, ArtifactKind, ArtifactVersion, CrateSearchCursor,
+        CrateSearchEntry, CrateTarget, DownloadCount, JobArtifactInfo, Task, TaskStatus,
+    },
+    Error, Pool, SqlState, TempDatabase, WriteHandle,
 };
 use rand_core::OsRng;
 use ssh_key::{Algorithm, HashAlg, PrivateKey};
-use std::future::Future;
+use std::{future::Future, time::Duration};
 use uuid::Uuid;
 
+/// Lease used for jobs in tests.
+const LEASE: Duration = Duration::from_secs(60);
+
 fn decompress(mut data: &[u8]) -> Vec<u8> {
     let mut output = vec![];
     lzma_rs::xz_decompress(&mut data, &mut output).unwrap();
@@ -57,6 +63,206 @@ async fn can_add_crate() {
     .await;
 }
 
+#[tokio::test]
+async fn cannot_get_crate_missing() {
+    with_database(|pool: Pool| async move {
+        let reader = pool.read().await.unwrap();
+        let error = reader.crate_info("serde").await.unwrap_err();
+        assert!(error.is_not_found());
+        let error = reader
+            .crate_version_info("serde", "0.1.0")
+            .await
+            .unwrap_err();
+        assert!(error.is_not_found());
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_set_crate_enabled() {
+    with_database(|pool: Pool| async move {
+        let writer = pool.write().await.unwrap();
+        writer.crate_add("serde").await.unwrap();
+        writer.crate_set_enabled("serde", false).await.unwrap();
+        writer.commit().await.unwrap();
+
+        let reader = pool.read().await.unwrap();
+        assert!(!reader.crate_info("serde").await.unwrap().enabled);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_admin_token_check() {
+    with_database(|pool: Pool| async move {
+        let writer = pool.write().await.unwrap();
+        writer.admin_token_add("admin", "secret").await.unwrap();
+        writer.commit().await.unwrap();
+
+        let reader = pool.read().await.unwrap();
+        assert_eq!(
+            reader.admin_token_check("secret").await.unwrap(),
+            Some("admin".into())
+        );
+        assert_eq!(reader.admin_token_check("other").await.unwrap(), None);
+        drop(reader);
+
+        let writer = pool.write().await.unwrap();
+        writer.admin_token_remove("admin").await.unwrap();
+        writer.commit().await.unwrap();
+        let reader = pool.read().await.unwrap();
+        assert_eq!(reader.admin_token_check("secret").await.unwrap(), None);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_page_crate_search() {
+    with_database(|pool: Pool| async move {
+        let writer = pool.write().await.unwrap();
+        for name in [
+            "serde_json",
+            "serdes",
+            "serde",
+            "my_serde",
+            "myxserde_derive",
+        ] {
+            writer.crate_add(name).await.unwrap();
+        }
+        writer.commit().await.unwrap();
+
+        let reader = pool.read().await.unwrap();
+        let names = |entries: Vec<CrateSearchEntry>| -> Vec<String> {
+            entries.into_iter().map(|entry| entry.name).collect()
+        };
+        let found = names(reader.crate_search("serde", 10, None).await.unwrap());
+
+        // pages start after the last crate of the previous page, crates added in between do not
+        // cause others to be skipped or repeated
+        let page = reader.crate_search("serde", 2, None).await.unwrap();
+        let last = page.last().unwrap();
+        let cursor = CrateSearchCursor {
+            rank: last.rank,
+            name: last.name.clone(),
+        };
+        drop(reader);
+        let writer = pool.write().await.unwrap();
+        writer.crate_add("serde_a").await.unwrap();
+        writer.commit().await.unwrap();
+        let reader = pool.read().await.unwrap();
+        let mut page = names(
+            reader
+                .crate_search("serde", 10, Some(&cursor))
+                .await
+                .unwrap(),
+        );
+        page.retain(|name| name != "serde_a");
+        assert_eq!(page, found[2..]);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_search_crates() {
+    with_database(|pool: Pool| async move {
+        let writer = pool.write().await.unwrap();
+        for name in [
+            "serde_json",
+            "serdes",
+            "serde",
+            "rand",
+            "my_serde",
+            "myxserde_derive",
+        ] {
+            writer.crate_add(name).await.unwrap();
+        }
+        writer
+            .crate_version_add("serde", "1.0.0", "abcdef", false)
+            .await
+            .unwrap();
+        writer
+            .crate_version_add("serde", "1.0.1", "abcdef", true)
+            .await
+            .unwrap();
+        for version in ["1.9.0", "1.10.0", "2.0.0-beta.1"] {
+            writer
+                .crate_version_add("serde", version, "abcdef", false)
+                .await
+                .unwrap();
+        }
+        for version in ["1.0.0-alpha", "1.0.0-beta"] {
+            writer
+                .crate_version_add("serdes", version, "abcdef", false)
+                .await
+                .unwrap();
+        }
+        writer.commit().await.unwrap();
+
+        let reader = pool.read().await.unwrap();
+        let names = |entries: Vec<CrateSearchEntry>| -> Vec<String> {
+            entries.into_iter().map(|entry| entry.name).collect()
+        };
+
+        // exact match first, then prefix matches, then similar names
+        let entries = reader.crate_search("serde", 10, None).await.unwrap();
+        assert_eq!(entries[0].latest_version.as_deref(), Some("1.10.0"));
+        assert_eq!(entries[1].latest_version.as_deref(), Some("1.0.0-beta"));
+        assert_eq!(entries[2].latest_version, None);
+        assert!(!entries[0].has_artifacts);
+        let found = names(entries);
+        assert_eq!(found[0], "serde");
+        assert_eq!(found[1..3], ["serdes", "serde_json"]);
+        assert!(found.contains(&"my_serde".into()));
+        assert!(!found.contains(&"rand".into()));
+        assert_eq!(
+            reader.crate_search_count("serde").await.unwrap(),
+            i64::try_from(found.len()).unwrap()
+        );
+
+        // underscores in prefixes are not wildcards
+        let found = names(reader.crate_search("MY_", 10, None).await.unwrap());
+        assert_eq!(found, ["my_serde"]);
+        assert_eq!(reader.crate_search_count("MY_").await.unwrap(), 1);
+        drop(reader);
+
+        // crates only have artifacts once a successful job recorded one
+        let triple = "x86_64-unknown-unknown";
+        let builder = Uuid::new_v4();
+        let private_key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        let writer = pool.write().await.unwrap();
+        writer.triple_add(triple).await.unwrap();
+        writer.triple_enabled(triple, true).await.unwrap();
+        writer
+            .builder_add(builder, private_key.public_key(), "comment")
+            .await
+            .unwrap();
+        writer.builder_set_enabled(builder, true).await.unwrap();
+        writer.builder_triple_add(builder, triple).await.unwrap();
+        writer
+            .task_create("serde", "1.0.0", "metadata", triple)
+            .await
+            .unwrap();
+        let job = writer
+            .job_request(builder, &[triple], LEASE)
+            .await
+            .unwrap()
+            .unwrap();
+        writer.job_finish(job, true).await.unwrap();
+        let entries = writer.crate_search("serde", 1, None).await.unwrap();
+        assert!(!entries[0].has_artifacts);
+        let artifact = JobArtifactInfo {
+            name: "metadata".into(),
+            hash: "abcdef".into(),
+            size: 1024,
+            signature: "signature".into(),
+        };
+        writer.job_artifact_add(job, &artifact).await.unwrap();
+        let entries = writer.crate_search("serde", 1, None).await.unwrap();
+        assert!(entries[0].has_artifacts);
+    })
+    .await;
+}
+
 #[tokio::test]
 async fn can_add_crate_version() {
     with_database(|pool: Pool| async move {
@@ -115,6 +321,84 @@ async fn can_add_crate_version_task() {
     .await;
 }
 
+#[tokio::test]
+async fn can_create_crate_version_task() {
+    with_database(|pool: Pool| async move {
+        let writer = pool.write().await.unwrap();
+        writer.triple_add("x86_64-unknown-linux-gnu").await.unwrap();
+        writer.crate_add("serde").await.unwrap();
+        for version in ["0.1.0", "0.2.0"] {
+            writer
+                .crate_version_add("serde", version, "abcdef", false)
+                .await
+                .unwrap();
+        }
+        for _ in 0..2 {
+            writer
+                .task_create("serde", "0.1.0", "debian", "x86_64-unknown-linux-gnu")
+                .await
+                .unwrap();
+        }
+        writer.commit().await.unwrap();
+
+        // only the task of the given version is created, once
+        let reader = pool.read().await.unwrap();
+        let tasks = reader.task_list(None, None, None, None).await.unwrap();
+        assert_eq!(
+            tasks,
+            [Task {
+                krate: "serde".into(),
+                version: "0.1.0".into(),
+                kind: ArtifactKind::Debian,
+                triple: "x86_64-unknown-linux-gnu".into(),
+            }]
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_add_crate_version_target() {
+    with_database(|pool: Pool| async move {
+        let writer = pool.write().await.unwrap();
+        writer.crate_add("serde").await.unwrap();
+        writer
+            .crate_version_add("serde", "0.1.0", "abcdef", false)
+            .await
+            .unwrap();
+        let target = |name: &str, kind: &str| CrateTarget {
+            name: name.into(),
+            kind: kind.into(),
+        };
+        for target in [
+            target("serde", "lib"),
+            target("serde-cli", "bin"),
+            target("serde", "lib"),
+        ] {
+            writer
+                .crate_version_target_add("serde", "0.1.0", &target)
+                .await
+                .unwrap();
+        }
+        writer.commit().await.unwrap();
+
+        let reader = pool.read().await.unwrap();
+        assert_eq!(
+            reader
+                .crate_version_targets("serde", "0.1.0")
+                .await
+                .unwrap(),
+            [target("serde-cli", "bin"), target("serde", "lib")]
+        );
+        assert!(reader
+            .crate_version_targets("serde", "0.2.0")
+            .await
+            .unwrap()
+            .is_empty());
+    })
+    .await;
+}
+
 #[tokio::test]
 async fn can_yank_crate_version() {
     with_database(|pool: Pool| async move {
@@ -146,6 +430,37 @@ async fn can_yank_crate_version() {
     .await;
 }
 
+#[tokio::test]
+async fn can_list_crate_versions() {
+    with_database(|pool: Pool| async move {
+        let name = "serde";
+
+        let writer = pool.write().await.unwrap();
+        writer.crate_add(name).await.unwrap();
+        writer
+            .crate_version_add(name, "0.1.0", "abcdef", false)
+            .await
+            .unwrap();
+        writer
+            .crate_version_add(name, "0.2.0", "fedcba", true)
+            .await
+            .unwrap();
+        writer.commit().await.unwrap();
+
+        let reader = pool.read().await.unwrap();
+        let mut versions = reader.crate_version_list(name).await.unwrap();
+        versions.sort_by(|a, b| a.version.cmp(&b.version));
+
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[0].version, "0.1.0");
+        assert!(!versions[0].yanked);
+        assert_eq!(versions[1].version, "0.2.0");
+        assert_eq!(versions[1].checksum, "fedcba");
+        assert!(versions[1].yanked);
+    })
+    .await;
+}
+
 #[tokio::test]
 async fn can_add_builder() {
     with_database(|pool: Pool| async move {
@@ -172,6 +487,31 @@ async fn can_add_builder() {
     .await;
 }
 
+#[tokio::test]
+async fn cannot_add_builder_twice() {
+    with_database(|pool: Pool| async move {
+        let private_key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        let uuid = Uuid::new_v4();
+
+        let writer = pool.write().await.unwrap();
+        writer
+            .builder_add(uuid, private_key.public_key(), "comment")
+            .await
+            .unwrap();
+
+        // adding another builder with the same uuid violates the unique constraint
+        let private_key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        let error = writer
+            .builder_add(uuid, private_key.public_key(), "comment")
+            .await
+            .unwrap_err();
+        let error = error.downcast_ref::<Error>().unwrap();
+        assert_eq!(error.code(), Some(&SqlState::UNIQUE_VIOLATION));
+        assert!(!error.is_not_found());
+    })
+    .await;
+}
+
 #[tokio::test]
 async fn can_lookup_builder() {
     with_database(|pool: Pool| async move {
@@ -454,6 +794,7 @@ async fn can_job_create() {
         // add triple
         let triple = "x86_64-unknown-unknown";
         writer.triple_add(triple).await.unwrap();
+        writer.triple_enabled(triple, true).await.unwrap();
 
         // add crate and version
         let name = "serde";
@@ -471,12 +812,17 @@ async fn can_job_create() {
             .builder_add(builder, private_key.public_key(), "comment")
             .await
             .unwrap();
+        writer.builder_set_enabled(builder, true).await.unwrap();
         writer.builder_triple_add(builder, triple).await.unwrap();
 
         writer.tasks_create_all("metadata", triple).await.unwrap();
 
         // add job
-        let job = writer.job_request(builder, triple).await.unwrap();
+        let job = writer
+            .job_request(builder, &[triple], LEASE)
+            .await
+            .unwrap()
+            .unwrap();
 
         writer.commit().await.unwrap();
 
@@ -488,6 +834,564 @@ async fn can_job_create() {
         assert_eq!(info.triple, triple);
         assert_eq!(info.name, name);
         assert_eq!(info.version, version);
+        assert_eq!(info.kind, "metadata");
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn job_request_matches_triples() {
+    with_database(|pool: Pool| async move {
+        let writer = pool.write().await.unwrap();
+
+        // add triples, builder is only allowed to build one of them
+        let allowed = "aarch64-unknown-linux-gnu";
+        let other = "x86_64-unknown-linux-gnu";
+        writer.triple_add(allowed).await.unwrap();
+        writer.triple_enabled(allowed, true).await.unwrap();
+        writer.triple_add(other).await.unwrap();
+        writer.triple_enabled(other, true).await.unwrap();
+        writer.crate_add("serde").await.unwrap();
+        writer
+            .crate_version_add("serde", "0.1.0", "abcdef", false)
+            .await
+            .unwrap();
+        writer.tasks_create_all("metadata", allowed).await.unwrap();
+        writer.tasks_create_all("metadata", other).await.unwrap();
+
+        let private_key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        let builder = Uuid::new_v4();
+        writer
+            .builder_add(builder, private_key.public_key(), "comment")
+            .await
+            .unwrap();
+        writer.builder_set_enabled(builder, true).await.unwrap();
+        writer.builder_triple_add(builder, allowed).await.unwrap();
+
+        // triples not advertised or not allowed are not handed out
+        assert_eq!(writer.job_request(builder, &[], LEASE).await.unwrap(), None);
+        assert_eq!(
+            writer.job_request(builder, &[other], LEASE).await.unwrap(),
+            None
+        );
+
+        let job = writer
+            .job_request(builder, &[allowed, other], LEASE)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(writer.job_info(job).await.unwrap().triple, allowed);
+
+        // task is not handed out again while the job is running
+        assert_eq!(
+            writer
+                .job_request(builder, &[allowed], LEASE)
+                .await
+                .unwrap(),
+            None
+        );
+
+        // nor once the job has failed
+        writer.job_finish(job, false).await.unwrap();
+        assert_eq!(
+            writer
+                .job_request(builder, &[allowed], LEASE)
+                .await
+                .unwrap(),
+            None
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn job_lease_expires() {
+    with_database(|pool: Pool| async move {
+        let writer = pool.write().await.unwrap();
+        let triple = "x86_64-unknown-unknown";
+        writer.triple_add(triple).await.unwrap();
+        writer.triple_enabled(triple, true).await.unwrap();
+        writer.crate_add("serde").await.unwrap();
+        writer
+            .crate_version_add("serde", "0.1.0", "abcdef", false)
+            .await
+            .unwrap();
+        let private_key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        let builder = Uuid::new_v4();
+        writer
+            .builder_add(builder, private_key.public_key(), "comment")
+            .await
+            .unwrap();
+        writer.builder_set_enabled(builder, true).await.unwrap();
+        writer.builder_triple_add(builder, triple).await.unwrap();
+        writer.tasks_create_all("metadata", triple).await.unwrap();
+
+        // job with a lease that expires immediately
+        let job = writer
+            .job_request(builder, &[triple], Duration::ZERO)
+            .await
+            .unwrap()
+            .unwrap();
+        writer.commit().await.unwrap();
+
+        // timestamps have a resolution of one second
+        tokio::time::sleep(Duration::from_secs(2)).await;
+
+        let writer = pool.write().await.unwrap();
+        assert!(!writer.job_heartbeat(job, LEASE).await.unwrap());
+        assert_eq!(writer.jobs_expire().await.unwrap(), vec![job]);
+        let info = writer.job_info(job).await.unwrap();
+        assert!(info.ended.is_some());
+        assert_eq!(info.success, Some(false));
+
+        // task is handed out again, and its lease can be extended
+        let job = writer
+            .job_request(builder, &[triple], LEASE)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(writer.job_heartbeat(job, LEASE).await.unwrap());
+        assert!(writer.jobs_expire().await.unwrap().is_empty());
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_job_stage_and_finish() {
+    with_database(|pool: Pool| async move {
+        let writer = pool.write().await.unwrap();
+
+        // add crate and version
+        let triple = "x86_64-unknown-unknown";
+        writer.triple_add(triple).await.unwrap();
+        writer.triple_enabled(triple, true).await.unwrap();
+        writer.crate_add("serde").await.unwrap();
+        writer
+            .crate_version_add("serde", "0.1.0", "abcdef", false)
+            .await
+            .unwrap();
+
+        // add builder and job
+        let private_key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        let builder = Uuid::new_v4();
+        writer
+            .builder_add(builder, private_key.public_key(), "comment")
+            .await
+            .unwrap();
+        writer.builder_set_enabled(builder, true).await.unwrap();
+        writer.builder_triple_add(builder, triple).await.unwrap();
+        writer.tasks_create_all("metadata", triple).await.unwrap();
+        let job = writer
+            .job_request(builder, &[triple], LEASE)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // jobs start out in the init stage
+        let info = writer.job_info(job).await.unwrap();
+        assert_eq!(info.stage, "init");
+        assert_eq!(info.ended, None);
+        assert_eq!(info.success, None);
+
+        let mut ids = vec![];
+        for stage in ["fetch", "build", "upload"] {
+            writer.job_stage(job, stage).await.unwrap();
+            ids.push(writer.job_log(job, stage).await.unwrap());
+            let info = writer.job_info(job).await.unwrap();
+            assert_eq!(info.stage, stage);
+        }
+
+        // log lines are recorded in the stage the job was in
+        let logs = writer.job_logs(job).await.unwrap();
+        assert_eq!(logs.iter().map(|log| log.id).collect::<Vec<_>>(), ids);
+        for log in &logs {
+            assert_eq!(log.stage, log.line);
+        }
+
+        writer.job_finish(job, true).await.unwrap();
+        writer.commit().await.unwrap();
+
+        let reader = pool.read().await.unwrap();
+        let info = reader.job_info(job).await.unwrap();
+        assert!(info.ended.is_some());
+        assert_eq!(info.success, Some(true));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_job_fail() {
+    with_database(|pool: Pool| async move {
+        let writer = pool.write().await.unwrap();
+
+        let triple = "x86_64-unknown-unknown";
+        writer.triple_add(triple).await.unwrap();
+        writer.triple_enabled(triple, true).await.unwrap();
+        writer.crate_add("serde").await.unwrap();
+        for version in ["0.1.0", "0.2.0"] {
+            writer
+                .crate_version_add("serde", version, "abcdef", false)
+                .await
+                .unwrap();
+        }
+        let private_key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        let builder = Uuid::new_v4();
+        writer
+            .builder_add(builder, private_key.public_key(), "comment")
+            .await
+            .unwrap();
+        writer.builder_set_enabled(builder, true).await.unwrap();
+        writer.builder_triple_add(builder, triple).await.unwrap();
+        writer.tasks_create_all("metadata", triple).await.unwrap();
+
+        // reason reported for the failure is recorded
+        let job = writer
+            .job_request(builder, &[triple], LEASE)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(writer.job_info(job).await.unwrap().reason, None);
+        writer.job_fail(job, "build failed").await.unwrap();
+        let info = writer.job_info(job).await.unwrap();
+        assert!(info.ended.is_some());
+        assert_eq!(info.success, Some(false));
+        assert_eq!(info.reason.as_deref(), Some("build failed"));
+
+        // jobs which have ended are not changed
+        writer.job_fail(job, "other").await.unwrap();
+        let info = writer.job_info(job).await.unwrap();
+        assert_eq!(info.reason.as_deref(), Some("build failed"));
+
+        // expired jobs record why they failed
+        let job = writer
+            .job_request(builder, &[triple], Duration::ZERO)
+            .await
+            .unwrap()
+            .unwrap();
+        writer.commit().await.unwrap();
+
+        // timestamps have a resolution of one second
+        tokio::time::sleep(Duration::from_secs(2)).await;
+
+        let writer = pool.write().await.unwrap();
+        assert_eq!(writer.jobs_expire().await.unwrap(), vec![job]);
+        let info = writer.job_info(job).await.unwrap();
+        assert_eq!(info.reason.as_deref(), Some("lease expired"));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_count_task_queue() {
+    with_database(|pool: Pool| async move {
+        let writer = pool.write().await.unwrap();
+
+        let triple = "x86_64-unknown-unknown";
+        let other = "aarch64-unknown-unknown";
+        writer.triple_add(triple).await.unwrap();
+        writer.triple_enabled(triple, true).await.unwrap();
+        writer.triple_add(other).await.unwrap();
+        writer.triple_enabled(other, true).await.unwrap();
+        writer.crate_add("serde").await.unwrap();
+        for version in ["0.1.0", "0.2.0"] {
+            writer
+                .crate_version_add("serde", version, "abcdef", false)
+                .await
+                .unwrap();
+        }
+        let private_key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        let builder = Uuid::new_v4();
+        writer
+            .builder_add(builder, private_key.public_key(), "comment")
+            .await
+            .unwrap();
+        writer.builder_set_enabled(builder, true).await.unwrap();
+        writer.builder_triple_add(builder, triple).await.unwrap();
+        writer.tasks_create_all("metadata", triple).await.unwrap();
+
+        // triples without tasks are included
+        let queue = writer.task_queue().await.unwrap();
+        assert_eq!(queue[triple], 2);
+        assert_eq!(queue[other], 0);
+
+        // running jobs are not pending
+        let job = writer
+            .job_request(builder, &[triple], LEASE)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(writer.task_queue().await.unwrap()[triple], 1);
+
+        // failed jobs are not retried
+        writer.job_finish(job, false).await.unwrap();
+        assert_eq!(writer.task_queue().await.unwrap()[triple], 1);
+
+        // successful jobs are done
+        let job = writer
+            .job_request(builder, &[triple], LEASE)
+            .await
+            .unwrap()
+            .unwrap();
+        writer.job_finish(job, true).await.unwrap();
+        assert_eq!(writer.task_queue().await.unwrap()[triple], 0);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_get_crate_version_tasks() {
+    with_database(|pool: Pool| async move {
+        let writer = pool.write().await.unwrap();
+
+        let triple = "x86_64-unknown-unknown";
+        writer.triple_add(triple).await.unwrap();
+        writer.triple_enabled(triple, true).await.unwrap();
+        writer.crate_add("serde").await.unwrap();
+        writer
+            .crate_version_add("serde", "0.1.0", "abcdef", false)
+            .await
+            .unwrap();
+        let private_key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        let builder = Uuid::new_v4();
+        writer
+            .builder_add(builder, private_key.public_key(), "comment")
+            .await
+            .unwrap();
+        writer.builder_set_enabled(builder, true).await.unwrap();
+        writer.builder_triple_add(builder, triple).await.unwrap();
+
+        // versions without tasks have no status
+        assert!(writer
+            .crate_version_tasks("serde", "0.1.0")
+            .await
+            .unwrap()
+            .is_empty());
+
+        writer.tasks_create_all("metadata", triple).await.unwrap();
+        let status = |success| {
+            vec![TaskStatus {
+                kind: "metadata".into(),
+                triple: triple.into(),
+                success,
+            }]
+        };
+        assert_eq!(
+            writer.crate_version_tasks("serde", "0.1.0").await.unwrap(),
+            status(None)
+        );
+
+        // only the most recent job counts, expired jobs are handed out again
+        writer
+            .job_request(builder, &[triple], Duration::ZERO)
+            .await
+            .unwrap()
+            .unwrap();
+        writer.commit().await.unwrap();
+
+        // timestamps have a resolution of one second
+        tokio::time::sleep(Duration::from_secs(2)).await;
+
+        let writer = pool.write().await.unwrap();
+        writer.jobs_expire().await.unwrap();
+        assert_eq!(
+            writer.crate_version_tasks("serde", "0.1.0").await.unwrap(),
+            status(Some(false))
+        );
+
+        let job = writer
+            .job_request(builder, &[triple], LEASE)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            writer.crate_version_tasks("serde", "0.1.0").await.unwrap(),
+            status(None)
+        );
+        writer.job_finish(job, true).await.unwrap();
+        assert_eq!(
+            writer.crate_version_tasks("serde", "0.1.0").await.unwrap(),
+            status(Some(true))
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_job_artifact_add() {
+    with_database(|pool: Pool| async move {
+        let writer = pool.write().await.unwrap();
+
+        // add crate, builder and job
+        let triple = "x86_64-unknown-unknown";
+        writer.triple_add(triple).await.unwrap();
+        writer.triple_enabled(triple, true).await.unwrap();
+        writer.crate_add("serde").await.unwrap();
+        writer
+            .crate_version_add("serde", "0.1.0", "abcdef", false)
+            .await
+            .unwrap();
+        let private_key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        let builder = Uuid::new_v4();
+        writer
+            .builder_add(builder, private_key.public_key(), "comment")
+            .await
+            .unwrap();
+        writer.builder_set_enabled(builder, true).await.unwrap();
+        writer.builder_triple_add(builder, triple).await.unwrap();
+        writer.tasks_create_all("metadata", triple).await.unwrap();
+        let job = writer
+            .job_request(builder, &[triple], LEASE)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let artifact = JobArtifactInfo {
+            name: "metadata".into(),
+            hash: "abcdef".into(),
+            size: 1024,
+            signature: "signature".into(),
+        };
+        writer.job_artifact_add(job, &artifact).await.unwrap();
+
+        // adding an artifact of the same name again replaces it
+        let artifact = JobArtifactInfo {
+            hash: "fedcba".into(),
+            size: 2048,
+            ..artifact
+        };
+        writer.job_artifact_add(job, &artifact).await.unwrap();
+        writer.commit().await.unwrap();
+
+        let reader = pool.read().await.unwrap();
+        assert_eq!(reader.job_artifacts(job).await.unwrap(), [artifact]);
+        assert!(reader
+            .job_artifacts(Uuid::new_v4())
+            .await
+            .unwrap()
+            .is_empty());
+
+        // artifacts of the version are only listed once the job was successful
+        assert!(reader
+            .crate_version_artifacts("serde", "0.1.0")
+            .await
+            .unwrap()
+            .is_empty());
+        drop(reader);
+        let writer = pool.write().await.unwrap();
+        writer.job_finish(job, true).await.unwrap();
+        writer.commit().await.unwrap();
+
+        let reader = pool.read().await.unwrap();
+        let artifacts = reader
+            .crate_version_artifacts("serde", "0.1.0")
+            .await
+            .unwrap();
+        assert_eq!(artifacts.len(), 1);
+        assert_eq!(artifacts[0].triple, triple);
+        assert_eq!(artifacts[0].kind, "metadata");
+        assert_eq!(artifacts[0].hash, "fedcba");
+        assert_eq!(artifacts[0].size, 2048);
+        assert_eq!(artifacts[0].builder, builder);
+        assert!(artifacts[0].built > 0);
+
+        // versions with an artifact are listed for its triple and kind
+        assert_eq!(
+            reader
+                .crate_artifact_versions("serde", triple, "metadata")
+                .await
+                .unwrap(),
+            [ArtifactVersion {
+                version: "0.1.0".into(),
+                yanked: false,
+                hash: "fedcba".into(),
+            }]
+        );
+        assert!(reader
+            .crate_artifact_versions("serde", triple, "tarball")
+            .await
+            .unwrap()
+            .is_empty());
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn can_add_artifact_downloads() {
+    with_database(|pool: Pool| async move {
+        let writer = pool.write().await.unwrap();
+
+        // add crate, builder and successful job with an artifact
+        let triple = "x86_64-unknown-unknown";
+        writer.triple_add(triple).await.unwrap();
+        writer.triple_enabled(triple, true).await.unwrap();
+        writer.crate_add("serde").await.unwrap();
+        writer
+            .crate_version_add("serde", "0.1.0", "abcdef", false)
+            .await
+            .unwrap();
+        let private_key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        let builder = Uuid::new_v4();
+        writer
+            .builder_add(builder, private_key.public_key(), "comment")
+            .await
+            .unwrap();
+        writer.builder_set_enabled(builder, true).await.unwrap();
+        writer.builder_triple_add(builder, triple).await.unwrap();
+        writer.tasks_create_all("metadata", triple).await.unwrap();
+        let job = writer
+            .job_request(builder, &[triple], LEASE)
+            .await
+            .unwrap()
+            .unwrap();
+        let artifact = JobArtifactInfo {
+            name: "metadata".into(),
+            hash: "abcdef".into(),
+            size: 1024,
+            signature: "signature".into(),
+        };
+        writer.job_artifact_add(job, &artifact).await.unwrap();
+        writer.job_finish(job, true).await.unwrap();
+
+        let downloads = |version: &str, date, downloads| ArtifactDownloads {
+            artifact: ArtifactId {
+                task: Task {
+                    krate: "serde".into(),
+                    version: version.into(),
+                    triple: triple.into(),
+                    kind: ArtifactKind::Metadata,
+                },
+            },
+            date,
+            downloads,
+        };
+        writer
+            .artifact_downloads_add(&[
+                downloads("0.1.0", 86400, 2),
+                downloads("0.1.0", 0, 1),
+                // downloads of artifacts which were never built are ignored
+                downloads("0.2.0", 0, 5),
+            ])
+            .await
+            .unwrap();
+        // downloads of the same day are added up
+        writer
+            .artifact_downloads_add(&[downloads("0.1.0", 86400, 3)])
+            .await
+            .unwrap();
+        writer.commit().await.unwrap();
+
+        let reader = pool.read().await.unwrap();
+        let count = |date, downloads| DownloadCount {
+            version: "0.1.0".into(),
+            triple: triple.into(),
+            date,
+            downloads,
+        };
+        assert_eq!(
+            reader.crate_downloads("serde").await.unwrap(),
+            [count(0, 1), count(86400, 5)]
+        );
+        assert!(reader.crate_downloads("tokio").await.unwrap().is_empty());
     })
     .await;
 }
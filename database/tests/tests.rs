@@ -17,12 +17,12 @@ async fn can_add_crate(name: String) {
     with_database(|metadata| async move {
         // add crate
         let writer = metadata.write().await.unwrap();
-        writer.crate_add(&name).await.unwrap();
+        writer.crate_add(name).await.unwrap();
         writer.commit().await.unwrap();
 
         // verify presence
         let reader = metadata.read().await.unwrap();
-        let info = reader.crate_info(&name).await.unwrap();
+        let info = reader.crate_info(name).await.unwrap();
         assert_eq!(info.name, name);
         assert!(info.enabled);
     })
@@ -35,15 +35,15 @@ async fn can_add_crates(names: Vec<String>) {
     with_database(|metadata| async move {
         // add crate
         let writer = metadata.write().await.unwrap();
-        for name in names.iter() {
+        for name in names {
             writer.crate_add(name).await.unwrap();
         }
         writer.commit().await.unwrap();
 
         // verify presence
         let reader = metadata.read().await.unwrap();
-        for name in names.iter() {
-            let info = reader.crate_info(&name).await.unwrap();
+        for name in names {
+            let info = reader.crate_info(name).await.unwrap();
             assert_eq!(&info.name, name);
             assert!(info.enabled);
         }
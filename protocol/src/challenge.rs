@@ -0,0 +1,181 @@
+//! Challenge-response authentication of builders
+
+use crate::SignatureError;
+use bytes::Bytes;
+use rand_core::{OsRng, RngCore};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use ssh_key::{HashAlg, PrivateKey, PublicKey, SshSig};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Signature namespace for challenge responses
+const NAMESPACE_CHALLENGE: &str = "challenge@builds.rs";
+
+/// Length of the random nonce, in bytes.
+const NONCE_LENGTH: usize = 32;
+
+/// Duration for which a challenge is valid after it was issued.
+pub const CHALLENGE_VALIDITY: Duration = Duration::from_secs(60);
+
+/// Challenge issued by the server to authenticate a builder.
+///
+/// Every connection gets a fresh challenge, which consists of a random nonce, the identity of the
+/// server issuing it and the time it was issued at. The builder proves possession of its private
+/// key by signing all of these, which binds the response to this connection.
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Clone, Debug)]
+pub struct Challenge {
+    /// Random nonce.
+    pub nonce: Bytes,
+    /// Identity of the server issuing this challenge.
+    pub server: String,
+    /// Time this challenge was issued at, as a UNIX timestamp.
+    pub timestamp: u64,
+}
+
+/// Response to a [`Challenge`].
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct ChallengeResponse {
+    /// Signature of the challenge, created from SSH key.
+    pub signature: String,
+}
+
+impl Challenge {
+    /// Create new challenge with a random nonce for the given server identity.
+    pub fn new(server: impl Into<String>) -> Self {
+        let mut nonce = [0; NONCE_LENGTH];
+        OsRng.fill_bytes(&mut nonce);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        Self {
+            nonce: nonce.to_vec().into(),
+            server: server.into(),
+            timestamp,
+        }
+    }
+
+    /// Determine if this challenge has expired at the given time.
+    pub fn expired(&self, now: SystemTime) -> bool {
+        let issued = UNIX_EPOCH + Duration::from_secs(self.timestamp);
+        match now.duration_since(issued) {
+            Ok(age) => age > CHALLENGE_VALIDITY,
+            // challenges from the future are never valid
+            Err(_) => true,
+        }
+    }
+
+    /// Data that is signed to respond to this challenge.
+    ///
+    /// The server identity is length-prefixed, such that no two different challenges have the
+    /// same encoding.
+    fn signed_data(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(16 + self.server.len() + self.nonce.len());
+        data.extend_from_slice(&(self.server.len() as u64).to_be_bytes());
+        data.extend_from_slice(self.server.as_bytes());
+        data.extend_from_slice(&self.timestamp.to_be_bytes());
+        data.extend_from_slice(&self.nonce);
+        data
+    }
+
+    /// Create a response to this challenge by signing it with the supplied private key.
+    pub fn respond(&self, key: &PrivateKey) -> Result<ChallengeResponse, SignatureError> {
+        let sig = key.sign(NAMESPACE_CHALLENGE, HashAlg::Sha512, &self.signed_data())?;
+        let signature = sig.to_pem(Default::default())?;
+        Ok(ChallengeResponse { signature })
+    }
+
+    /// Verify that the response to this challenge was signed by the supplied public key.
+    pub fn verify(
+        &self,
+        key: &PublicKey,
+        response: &ChallengeResponse,
+    ) -> Result<(), SignatureError> {
+        let signature = SshSig::from_pem(&response.signature)?;
+        key.verify(NAMESPACE_CHALLENGE, &self.signed_data(), &signature)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ssh_key::Algorithm;
+
+    fn random_key() -> PrivateKey {
+        PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap()
+    }
+
+    #[test]
+    fn challenge_is_random() {
+        let left = Challenge::new("builds.rs");
+        let right = Challenge::new("builds.rs");
+        assert_ne!(left.nonce, right.nonce);
+        assert_eq!(left.nonce.len(), NONCE_LENGTH);
+    }
+
+    #[test]
+    fn can_respond_to_challenge() {
+        let key = random_key();
+        let challenge = Challenge::new("builds.rs");
+        let response = challenge.respond(&key).unwrap();
+        challenge.verify(key.public_key(), &response).unwrap();
+    }
+
+    #[test]
+    fn rejects_forged_response() {
+        let key = random_key();
+        let forger = random_key();
+        let challenge = Challenge::new("builds.rs");
+        let response = challenge.respond(&forger).unwrap();
+        assert!(challenge.verify(key.public_key(), &response).is_err());
+    }
+
+    #[test]
+    fn rejects_replayed_response() {
+        let key = random_key();
+        let previous = Challenge::new("builds.rs");
+        let response = previous.respond(&key).unwrap();
+
+        // same server, but a fresh nonce
+        let challenge = Challenge::new("builds.rs");
+        assert!(challenge.verify(key.public_key(), &response).is_err());
+    }
+
+    #[test]
+    fn rejects_response_for_other_server() {
+        let key = random_key();
+        let challenge = Challenge::new("builds.rs");
+        let response = challenge.respond(&key).unwrap();
+
+        // same nonce and timestamp, but issued by another server
+        let relayed = Challenge {
+            server: "evil.example.com".into(),
+            ..challenge
+        };
+        assert!(relayed.verify(key.public_key(), &response).is_err());
+    }
+
+    #[test]
+    fn rejects_response_with_other_timestamp() {
+        let key = random_key();
+        let challenge = Challenge::new("builds.rs");
+        let response = challenge.respond(&key).unwrap();
+
+        let modified = Challenge {
+            timestamp: challenge.timestamp + 1,
+            ..challenge
+        };
+        assert!(modified.verify(key.public_key(), &response).is_err());
+    }
+
+    #[test]
+    fn challenge_expires() {
+        let challenge = Challenge::new("builds.rs");
+        let issued = UNIX_EPOCH + Duration::from_secs(challenge.timestamp);
+        assert!(!challenge.expired(issued));
+        assert!(!challenge.expired(issued + CHALLENGE_VALIDITY));
+        assert!(challenge.expired(issued + CHALLENGE_VALIDITY + Duration::from_secs(1)));
+        assert!(challenge.expired(issued - Duration::from_secs(1)));
+    }
+}
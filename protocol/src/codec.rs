@@ -0,0 +1,294 @@
+//! Wire encoding of messages
+//!
+//! Messages are encoded as JSON in text frames by default. If both sides support it, they can
+//! negotiate [`Feature::Cbor`](crate::Feature::Cbor) during the handshake, after which all
+//! messages are encoded as CBOR in binary frames. Binary fields, such as artifact chunks, are
+//! encoded much more compactly in CBOR.
+
+use serde::{
+    de::{DeserializeOwned, Error, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::fmt;
+
+/// Encoding used for messages.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum Codec {
+    /// JSON, sent in text frames
+    #[default]
+    Json,
+    /// CBOR, sent in binary frames
+    Cbor,
+}
+
+/// `WebSocket` frame containing an encoded message.
+///
+/// When nested in another message, text frames are encoded as strings and binary frames as
+/// byte strings, so that the codec a frame was encoded with is preserved.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Frame {
+    /// Text frame
+    Text(String),
+    /// Binary frame
+    Binary(Vec<u8>),
+}
+
+impl Frame {
+    /// Codec this frame was encoded with.
+    pub fn codec(&self) -> Codec {
+        match self {
+            Self::Text(_) => Codec::Json,
+            Self::Binary(_) => Codec::Cbor,
+        }
+    }
+
+    /// Contents of this frame.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Text(text) => text.as_bytes(),
+            Self::Binary(data) => data,
+        }
+    }
+}
+
+impl Serialize for Frame {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Text(text) => serializer.serialize_str(text),
+            Self::Binary(data) => serializer.serialize_bytes(data),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Frame {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FrameVisitor;
+
+        impl<'de> Visitor<'de> for FrameVisitor {
+            type Value = Frame;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a string or byte string")
+            }
+
+            fn visit_str<E: Error>(self, text: &str) -> Result<Frame, E> {
+                Ok(Frame::Text(text.into()))
+            }
+
+            fn visit_string<E: Error>(self, text: String) -> Result<Frame, E> {
+                Ok(Frame::Text(text))
+            }
+
+            fn visit_bytes<E: Error>(self, data: &[u8]) -> Result<Frame, E> {
+                Ok(Frame::Binary(data.into()))
+            }
+
+            fn visit_byte_buf<E: Error>(self, data: Vec<u8>) -> Result<Frame, E> {
+                Ok(Frame::Binary(data))
+            }
+        }
+
+        deserializer.deserialize_any(FrameVisitor)
+    }
+}
+
+/// Error encoding or decoding messages.
+#[derive(thiserror::Error, Debug)]
+pub enum CodecError {
+    /// Error in JSON encoding
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// Error encoding CBOR
+    #[error(transparent)]
+    CborEncode(#[from] ciborium::ser::Error<std::io::Error>),
+    /// Error decoding CBOR
+    #[error(transparent)]
+    CborDecode(#[from] ciborium::de::Error<std::io::Error>),
+    /// Frame type does not match the codec
+    #[error("unexpected frame type for {0:?} codec")]
+    UnexpectedFrame(Codec),
+}
+
+impl Codec {
+    /// Encode a value.
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, CodecError> {
+        match self {
+            Self::Json => Ok(serde_json::to_vec(value)?),
+            Self::Cbor => {
+                let mut data = vec![];
+                ciborium::into_writer(value, &mut data)?;
+                Ok(data)
+            }
+        }
+    }
+
+    /// Decode a value.
+    pub fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, CodecError> {
+        match self {
+            Self::Json => Ok(serde_json::from_slice(data)?),
+            Self::Cbor => Ok(ciborium::from_reader(data)?),
+        }
+    }
+
+    /// Encode a value into a frame.
+    pub fn encode_frame<T: Serialize>(&self, value: &T) -> Result<Frame, CodecError> {
+        match self {
+            Self::Json => Ok(Frame::Text(serde_json::to_string(value)?)),
+            Self::Cbor => Ok(Frame::Binary(self.encode(value)?)),
+        }
+    }
+
+    /// Decode a value from a frame.
+    pub fn decode_frame<T: DeserializeOwned>(&self, frame: &Frame) -> Result<T, CodecError> {
+        match (self, frame) {
+            (Self::Json, Frame::Text(text)) => Ok(serde_json::from_str(text)?),
+            (Self::Cbor, Frame::Binary(data)) => self.decode(data),
+            _ => Err(CodecError::UnexpectedFrame(*self)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use bytes::Bytes;
+    use rand_core::OsRng;
+    use ssh_key::{Algorithm, PrivateKey};
+    use uuid::Uuid;
+
+    const CODECS: [Codec; 2] = [Codec::Json, Codec::Cbor];
+
+    fn server_messages() -> Vec<ServerMessage> {
+        vec![
+            ServerMessage::ChallengeRequest(Challenge::new("builds.rs")),
+            ServerMessage::Welcome(Welcome {
+                version: PROTOCOL_VERSION,
+                features: [Feature::JobEvents, Feature::Cbor].into(),
+            }),
+            ServerMessage::Rejected(Rejection::BuilderDisabled),
+            ServerMessage::CancelJob(JobCancel {
+                job: Uuid::from_u128(1),
+                reason: "lease expired".into(),
+            }),
+            ServerMessage::ArtifactAck(ArtifactAck {
+                job: Uuid::from_u128(2),
+                artifact: "metadata".into(),
+                offset: 1024,
+            }),
+        ]
+    }
+
+    fn client_messages() -> Vec<ClientMessage> {
+        let key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        let challenge = Challenge::new("builds.rs");
+        vec![
+            ClientMessage::ChallengeResponse(challenge.respond(&key).unwrap()),
+            ClientMessage::JobRequest(JobRequest {
+                target: Some("x86_64-unknown-linux-gnu".into()),
+            }),
+            ClientMessage::JobStage(JobStageUpdate {
+                job: Uuid::from_u128(3),
+                stage: JobStage::Build,
+            }),
+            ClientMessage::ArtifactChunk(ArtifactChunk {
+                job: Uuid::from_u128(4),
+                artifact: "metadata".into(),
+                offset: 0,
+                data: Bytes::from_static(&[0, 1, 2, 3, 255]),
+            }),
+            ClientMessage::JobComplete(JobComplete {
+                job: Uuid::from_u128(5),
+                artifacts: vec![JobArtifact {
+                    name: "metadata".into(),
+                    hash: "abcdef".into(),
+                    size: 5,
+                    signature: Some("signature".into()),
+                }],
+            }),
+        ]
+    }
+
+    /// Round-trip a value through a codec, returning its JSON representation.
+    fn roundtrip<T: Serialize + DeserializeOwned>(codec: Codec, value: &T) -> serde_json::Value {
+        let frame = codec.encode_frame(value).unwrap();
+        let decoded: T = codec.decode_frame(&frame).unwrap();
+        serde_json::to_value(decoded).unwrap()
+    }
+
+    #[test]
+    fn codecs_equivalent_server_messages() {
+        for message in server_messages() {
+            let expected = serde_json::to_value(&message).unwrap();
+            for codec in CODECS {
+                assert_eq!(roundtrip(codec, &message), expected, "{codec:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn codecs_equivalent_client_messages() {
+        for message in client_messages() {
+            let expected = serde_json::to_value(&message).unwrap();
+            for codec in CODECS {
+                assert_eq!(roundtrip(codec, &message), expected, "{codec:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn codecs_equivalent_signed_messages() {
+        let key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        for message in client_messages() {
+            let expected = serde_json::to_value(&message).unwrap();
+            for codec in CODECS {
+                let signed = SignedMessage::new(&key, codec, 0, &message).unwrap();
+                let frame = codec.encode_frame(&signed).unwrap();
+                let decoded: SignedMessage<ClientMessage> = codec.decode_frame(&frame).unwrap();
+                let payload = decoded.verify(key.public_key()).unwrap();
+                assert_eq!(
+                    serde_json::to_value(payload.message).unwrap(),
+                    expected,
+                    "{codec:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn codec_frame_types() {
+        let message = ServerMessage::Rejected(Rejection::BuilderDisabled);
+        assert!(matches!(
+            Codec::Json.encode_frame(&message).unwrap(),
+            Frame::Text(_)
+        ));
+        assert!(matches!(
+            Codec::Cbor.encode_frame(&message).unwrap(),
+            Frame::Binary(_)
+        ));
+    }
+
+    #[test]
+    fn codec_rejects_other_frame_type() {
+        let message = ServerMessage::Rejected(Rejection::BuilderDisabled);
+        let frame = Codec::Json.encode_frame(&message).unwrap();
+        assert!(matches!(
+            Codec::Cbor.decode_frame::<ServerMessage>(&frame),
+            Err(CodecError::UnexpectedFrame(Codec::Cbor))
+        ));
+    }
+
+    #[test]
+    fn cbor_encodes_bytes_compactly() {
+        let chunk = ArtifactChunk {
+            job: Uuid::from_u128(6),
+            artifact: "metadata".into(),
+            offset: 0,
+            data: vec![255; 1024].into(),
+        };
+        let json = Codec::Json.encode(&chunk).unwrap();
+        let cbor = Codec::Cbor.encode(&chunk).unwrap();
+        assert!(cbor.len() < 1100);
+        assert!(json.len() > 3 * 1024);
+    }
+}
@@ -3,16 +3,37 @@
 //! This crate supplies the necessary primitives that defined the protocol between the builder
 //! and the backend. The [`ServerMessage`] defines any messages that might be sent by the
 //! server, and the [`ClientMessage`] any message sent by the client. Every message from the
-//! client is wrapped in a [`SignedMessage`] to add a cryptographic signature.
+//! client is wrapped in a [`SignedMessage`] to add a cryptographic signature. Builders
+//! authenticate by answering a [`Challenge`] issued by the server, after negotiating the
+//! protocol version in their [`Hello`]. Messages are encoded with the negotiated [`Codec`]. A
+//! JSON Schema of all messages is generated by [`schema()`](schema::schema).
 
 pub use ssh_key;
 
+pub mod challenge;
+pub mod codec;
 pub mod messages;
+pub mod schema;
 pub mod signature;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod transport;
 pub mod types;
+pub mod version;
 
 pub use crate::{
+    challenge::{Challenge, ChallengeResponse},
+    codec::{Codec, CodecError, Frame},
     messages::*,
     signature::*,
-    types::{Job, JobRequest},
+    transport::{BoxTransport, Transport, TransportError},
+    types::{
+        ArtifactAck, ArtifactChunk, Capabilities, Job, JobArtifact, JobCancel, JobComplete,
+        JobFailed, JobHeartbeat, JobLog, JobRequest, JobStage, JobStageUpdate, ARTIFACT_CHUNK_SIZE,
+        JOB_HEARTBEAT_INTERVAL,
+    },
+    version::{
+        Feature, Hello, LegacyMessage, Rejection, Welcome, PROTOCOL_VERSION,
+        PROTOCOL_VERSION_LEGACY, PROTOCOL_VERSION_MIN,
+    },
 };
@@ -1,28 +1,49 @@
 //! # Message enumeration that can be sent by either side
 
-use crate::types::*;
-use bytes::Bytes;
+use crate::{types::*, Challenge, ChallengeResponse, Hello, Rejection, Welcome};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use ssh_key::Fingerprint;
 
 /// Message sent by the server.
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub enum ServerMessage {
+    /// Negotiated protocol version and features.
+    Welcome(Welcome),
+    /// Connection was rejected.
+    Rejected(Rejection),
     /// Challenge request for authentication.
-    ChallengeRequest(Bytes),
+    ChallengeRequest(Challenge),
     /// New job response.
     JobResponse(Job),
     /// Currently pending jobs.
     JobList(Vec<Job>),
+    /// Job was cancelled and should be aborted.
+    CancelJob(JobCancel),
+    /// Artifact upload progress.
+    ArtifactAck(ArtifactAck),
 }
 
 /// Messages which can be sent by the client.
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub enum ClientMessage {
     /// Initialize connection
-    Hello(Fingerprint),
+    Hello(Hello),
     /// Respond to challenge
-    ChallengeResponse(Bytes),
+    ChallengeResponse(ChallengeResponse),
+    /// Advertise builder capabilities
+    Capabilities(Capabilities),
     /// Request job
     JobRequest(JobRequest),
+    /// Job is still running
+    JobHeartbeat(JobHeartbeat),
+    /// Job has entered a new stage
+    JobStage(JobStageUpdate),
+    /// Log output of job
+    JobLog(JobLog),
+    /// Chunk of artifact upload
+    ArtifactChunk(ArtifactChunk),
+    /// Job has completed successfully
+    JobComplete(JobComplete),
+    /// Job has failed
+    JobFailed(JobFailed),
 }
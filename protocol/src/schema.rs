@@ -0,0 +1,97 @@
+//! Machine-readable specification of the protocol
+//!
+//! The protocol is specified as a JSON Schema, which is generated from the message types
+//! themselves so that it cannot drift from them. It describes the JSON encoding of messages. The
+//! current specification is checked in as `protocol/schema.json`, for implementors of builders in
+//! other languages.
+
+use crate::*;
+use schemars::{
+    gen::SchemaSettings,
+    schema::{Metadata, RootSchema, SchemaObject, SubschemaValidation},
+};
+
+/// Generate the JSON Schema of the protocol.
+///
+/// The schema matches any message sent over the connection, which is either a signed
+/// [`ClientMessage`] sent by the builder or a [`ServerMessage`] sent by the server. It contains
+/// definitions for all types used by these messages.
+pub fn schema() -> RootSchema {
+    let mut generator = SchemaSettings::draft07().into_generator();
+    let messages = vec![
+        generator.subschema_for::<SignedMessage<ClientMessage>>(),
+        generator.subschema_for::<ServerMessage>(),
+    ];
+
+    // the payload is encoded as a string, but implementors need to know what it contains
+    generator.subschema_for::<Payload<ClientMessage>>();
+
+    RootSchema {
+        meta_schema: generator.settings().meta_schema.clone(),
+        schema: SchemaObject {
+            metadata: Some(Box::new(Metadata {
+                title: Some("buildsrs builder protocol".into()),
+                description: Some(format!(
+                    "Messages of version {PROTOCOL_VERSION} of the protocol between builder and server."
+                )),
+                ..Default::default()
+            })),
+            subschemas: Some(Box::new(SubschemaValidation {
+                one_of: Some(messages),
+                ..Default::default()
+            })),
+            ..Default::default()
+        },
+        definitions: generator.take_definitions(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    /// Path of the checked-in schema.
+    const SCHEMA_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/schema.json");
+
+    #[test]
+    fn schema_has_definitions() {
+        let schema = schema();
+        for name in [
+            "SignedMessage_for_ClientMessage",
+            "Payload_for_ClientMessage",
+            "ClientMessage",
+            "ServerMessage",
+            "Job",
+            "JobKind",
+            "BuildEnv",
+            "Variant",
+        ] {
+            assert!(schema.definitions.contains_key(name), "missing {name}");
+        }
+    }
+
+    /// Fields which are optional when decoding must not be required by the schema.
+    #[test]
+    fn schema_hello_features_optional() {
+        let schema = schema();
+        let hello = schema.definitions["Hello"].clone().into_object();
+        let required = &hello.object.unwrap().required;
+        assert!(required.contains("version"));
+        assert!(!required.contains("features"));
+    }
+
+    /// Makes sure the checked-in schema is current, set `UPDATE_SCHEMA` to regenerate it.
+    #[test]
+    fn schema_is_current() {
+        let current = serde_json::to_string_pretty(&schema()).unwrap() + "\n";
+        if env::var_os("UPDATE_SCHEMA").is_some() {
+            fs::write(SCHEMA_PATH, &current).unwrap();
+        }
+        let checked_in = fs::read_to_string(SCHEMA_PATH).unwrap_or_default();
+        assert!(
+            checked_in == current,
+            "protocol/schema.json is stale, regenerate it with `just protocol-schema`"
+        );
+    }
+}
@@ -1,18 +1,55 @@
 //! Wrapper type for signed messages
+//!
+//! Signed messages carry their payload verbatim, exactly as it was signed, so that verification
+//! does not depend on re-encoding the message. Besides the message itself, the payload contains a
+//! sequence number and a timestamp, which the receiver uses to reject replayed or stale messages.
+//! The payload is encoded with the [`Codec`] of the connection, and records which one that was.
 
-use serde::{Deserialize, Serialize};
+use crate::codec::{Codec, CodecError, Frame};
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use ssh_key::{HashAlg, PrivateKey, PublicKey, SshSig};
+use std::{
+    marker::PhantomData,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 /// Signature namespace for verified messages
 const NAMESPACE_BUILDSRS: &str = "builder@builds.rs";
 
+/// Maximum difference between the timestamp of a message and the time it is received at.
+pub const MESSAGE_FRESHNESS: Duration = Duration::from_secs(5 * 60);
+
+/// Convert a time into a UNIX timestamp.
+fn timestamp(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
 /// Message which has been signed with a cryptographic signature.
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub struct SignedMessage<T> {
-    /// Message
-    pub message: T,
+    /// Encoded [`Payload`], exactly as it was signed.
+    ///
+    /// This is a string when encoded as JSON and a byte string when encoded as CBOR.
+    #[schemars(with = "String")]
+    pub payload: Frame,
     /// Signature, created from SSH key.
     pub signature: String,
+    #[serde(skip)]
+    message: PhantomData<fn() -> T>,
+}
+
+/// Contents of a [`SignedMessage`].
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq, Eq)]
+pub struct Payload<T> {
+    /// Sequence number of this message, must be increasing.
+    pub sequence: u64,
+    /// Time this message was created at, as a UNIX timestamp.
+    pub timestamp: u64,
+    /// Message
+    pub message: T,
 }
 
 /// Error creating or verifying [`SignedMessage`].
@@ -23,24 +60,159 @@ pub enum SignatureError {
     SshKey(#[from] ssh_key::Error),
     /// Error encoding message
     #[error(transparent)]
-    Encoding(#[from] serde_json::Error),
+    Encoding(#[from] CodecError),
+    /// Payload of message could not be decoded
+    #[error("malformed message payload")]
+    Malformed(#[source] CodecError),
+    /// Message was replayed or received out of order
+    #[error("replayed message with sequence {sequence}, expected greater than {last}")]
+    Replayed {
+        /// Sequence number of the message
+        sequence: u64,
+        /// Last sequence number that was accepted
+        last: u64,
+    },
+    /// Message timestamp is outside of the freshness window
+    #[error("stale message with timestamp {timestamp}")]
+    Stale {
+        /// Timestamp of the message
+        timestamp: u64,
+    },
 }
 
 impl<T: Serialize> SignedMessage<T> {
-    /// Create new signed message.
-    pub fn new(key: &PrivateKey, message: T) -> Result<Self, SignatureError> {
-        let encoded = serde_json::to_string(&message)?;
-        let sig = key.sign(NAMESPACE_BUILDSRS, HashAlg::Sha512, encoded.as_bytes())?;
+    /// Create new signed message with the given sequence number, encoding it with `codec`.
+    pub fn new(
+        key: &PrivateKey,
+        codec: Codec,
+        sequence: u64,
+        message: &T,
+    ) -> Result<Self, SignatureError> {
+        let payload = Payload {
+            sequence,
+            timestamp: timestamp(SystemTime::now()),
+            message,
+        };
+        let payload = codec.encode_frame(&payload)?;
+        let sig = key.sign(NAMESPACE_BUILDSRS, HashAlg::Sha512, payload.as_bytes())?;
         let signature = sig.to_pem(Default::default())?;
-        Ok(Self { message, signature })
+        Ok(Self {
+            payload,
+            signature,
+            message: PhantomData,
+        })
+    }
+}
+
+impl<T: DeserializeOwned> SignedMessage<T> {
+    /// Decode the payload of this message without verifying it.
+    ///
+    /// This is only useful to determine which key to verify the message with, the result should
+    /// not be trusted.
+    pub fn inspect(&self) -> Result<Payload<T>, SignatureError> {
+        self.payload
+            .codec()
+            .decode_frame(&self.payload)
+            .map_err(SignatureError::Malformed)
     }
 
-    /// Verify that this message was signed by the supplied public key.
-    pub fn verify(&self, key: &PublicKey) -> Result<(), SignatureError> {
-        let encoded = serde_json::to_string(&self.message)?;
+    /// Verify that this message was signed by the supplied public key and decode it.
+    ///
+    /// This only checks the signature, use a [`MessageVerifier`] to also reject replayed and
+    /// stale messages.
+    pub fn verify(&self, key: &PublicKey) -> Result<Payload<T>, SignatureError> {
         let signature = SshSig::from_pem(&self.signature)?;
-        key.verify(NAMESPACE_BUILDSRS, encoded.as_bytes(), &signature)?;
-        Ok(())
+        key.verify(NAMESPACE_BUILDSRS, self.payload.as_bytes(), &signature)?;
+        self.inspect()
+    }
+}
+
+/// Creates [`SignedMessage`]s with increasing sequence numbers.
+#[derive(Clone, Debug)]
+pub struct MessageSigner {
+    key: PrivateKey,
+    codec: Codec,
+    sequence: u64,
+}
+
+impl MessageSigner {
+    /// Create new signer from a private key.
+    pub fn new(key: PrivateKey) -> Self {
+        Self {
+            key,
+            codec: Codec::default(),
+            sequence: 0,
+        }
+    }
+
+    /// Set the codec used to encode messages.
+    pub fn set_codec(&mut self, codec: Codec) {
+        self.codec = codec;
+    }
+
+    /// Private key used for signing.
+    pub fn key(&self) -> &PrivateKey {
+        &self.key
+    }
+
+    /// Sign a message, using the next sequence number.
+    pub fn sign<T: Serialize>(&mut self, message: &T) -> Result<SignedMessage<T>, SignatureError> {
+        let signed = SignedMessage::new(&self.key, self.codec, self.sequence, message)?;
+        self.sequence += 1;
+        Ok(signed)
+    }
+}
+
+/// Verifies [`SignedMessage`]s received from one peer.
+///
+/// Besides checking the signature, this makes sure that the sequence numbers of received messages
+/// are strictly increasing and that their timestamps are within the [`MESSAGE_FRESHNESS`] window.
+#[derive(Clone, Debug)]
+pub struct MessageVerifier {
+    key: PublicKey,
+    last: Option<u64>,
+}
+
+impl MessageVerifier {
+    /// Create new verifier for messages signed by the given public key.
+    pub fn new(key: PublicKey) -> Self {
+        Self { key, last: None }
+    }
+
+    /// Verify a message received now, returning the message if it is valid.
+    pub fn verify<T: DeserializeOwned>(
+        &mut self,
+        message: &SignedMessage<T>,
+    ) -> Result<T, SignatureError> {
+        self.verify_at(message, SystemTime::now())
+    }
+
+    /// Verify a message received at the given time, returning the message if it is valid.
+    pub fn verify_at<T: DeserializeOwned>(
+        &mut self,
+        message: &SignedMessage<T>,
+        now: SystemTime,
+    ) -> Result<T, SignatureError> {
+        let payload = message.verify(&self.key)?;
+
+        if let Some(last) = self.last {
+            if payload.sequence <= last {
+                return Err(SignatureError::Replayed {
+                    sequence: payload.sequence,
+                    last,
+                });
+            }
+        }
+
+        let now = timestamp(now);
+        if now.abs_diff(payload.timestamp) > MESSAGE_FRESHNESS.as_secs() {
+            return Err(SignatureError::Stale {
+                timestamp: payload.timestamp,
+            });
+        }
+
+        self.last = Some(payload.sequence);
+        Ok(payload.message)
     }
 }
 
@@ -50,11 +222,132 @@ mod tests {
     use rand_core::OsRng;
     use ssh_key::Algorithm;
 
+    fn random_key() -> PrivateKey {
+        PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap()
+    }
+
     #[test]
     fn test_signed_message() {
-        let message = "Hello";
-        let key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
-        let signed = SignedMessage::new(&key, message).unwrap();
-        signed.verify(key.public_key()).unwrap();
+        let message = "Hello".to_string();
+        let key = random_key();
+        let signed = SignedMessage::new(&key, Codec::Json, 0, &message).unwrap();
+        let payload = signed.verify(key.public_key()).unwrap();
+        assert_eq!(payload.message, message);
+        assert_eq!(payload.sequence, 0);
+    }
+
+    #[test]
+    fn signed_message_carries_payload() {
+        let key = random_key();
+        let signed = SignedMessage::new(&key, Codec::Json, 0, &"Hello".to_string()).unwrap();
+        let encoded = serde_json::to_string(&signed).unwrap();
+        let decoded: SignedMessage<String> = serde_json::from_str(&encoded).unwrap();
+        assert_eq!(decoded.payload, signed.payload);
+        decoded.verify(key.public_key()).unwrap();
+    }
+
+    #[test]
+    fn signed_message_cbor() {
+        let key = random_key();
+        let signed = SignedMessage::new(&key, Codec::Cbor, 0, &"Hello".to_string()).unwrap();
+        let encoded = Codec::Cbor.encode(&signed).unwrap();
+        let decoded: SignedMessage<String> = Codec::Cbor.decode(&encoded).unwrap();
+        assert!(matches!(decoded.payload, Frame::Binary(_)));
+        assert_eq!(decoded.verify(key.public_key()).unwrap().message, "Hello");
+    }
+
+    #[test]
+    fn rejects_tampered_payload() {
+        let key = random_key();
+        let mut signed = SignedMessage::new(&key, Codec::Json, 0, &"Hello".to_string()).unwrap();
+        let Frame::Text(payload) = &signed.payload else {
+            panic!("expected text payload");
+        };
+        signed.payload = Frame::Text(payload.replace("Hello", "Hallo"));
+        assert!(matches!(
+            signed.verify(key.public_key()),
+            Err(SignatureError::SshKey(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_other_key() {
+        let key = random_key();
+        let signed = SignedMessage::new(&key, Codec::Json, 0, &"Hello".to_string()).unwrap();
+        assert!(signed.verify(random_key().public_key()).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_payload() {
+        let key = random_key();
+        let signed = SignedMessage::<u64>::new(&key, Codec::Json, 0, &15).unwrap();
+        let signed = SignedMessage::<String> {
+            payload: signed.payload,
+            signature: signed.signature,
+            message: PhantomData,
+        };
+        assert!(matches!(
+            signed.verify(key.public_key()),
+            Err(SignatureError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn verifier_accepts_sequence() {
+        let mut sender = MessageSigner::new(random_key());
+        let mut verifier = MessageVerifier::new(sender.key().public_key().clone());
+        for message in ["first", "second", "third"] {
+            let signed = sender.sign(&message.to_string()).unwrap();
+            assert_eq!(verifier.verify(&signed).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn verifier_rejects_replayed() {
+        let mut sender = MessageSigner::new(random_key());
+        let mut verifier = MessageVerifier::new(sender.key().public_key().clone());
+        let signed = sender.sign(&"Hello".to_string()).unwrap();
+        verifier.verify(&signed).unwrap();
+        assert!(matches!(
+            verifier.verify(&signed),
+            Err(SignatureError::Replayed {
+                sequence: 0,
+                last: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn verifier_rejects_out_of_order() {
+        let mut sender = MessageSigner::new(random_key());
+        let mut verifier = MessageVerifier::new(sender.key().public_key().clone());
+        let first = sender.sign(&"first".to_string()).unwrap();
+        let second = sender.sign(&"second".to_string()).unwrap();
+        verifier.verify(&second).unwrap();
+        assert!(matches!(
+            verifier.verify(&first),
+            Err(SignatureError::Replayed {
+                sequence: 0,
+                last: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn verifier_rejects_stale() {
+        let mut sender = MessageSigner::new(random_key());
+        let mut verifier = MessageVerifier::new(sender.key().public_key().clone());
+        let signed = sender.sign(&"Hello".to_string()).unwrap();
+        // timestamps have a resolution of one second, so allow for rounding
+        let delay = MESSAGE_FRESHNESS + Duration::from_secs(2);
+        for now in [SystemTime::now() + delay, SystemTime::now() - delay] {
+            assert!(matches!(
+                verifier.verify_at(&signed, now),
+                Err(SignatureError::Stale { .. })
+            ));
+        }
+
+        // stale messages do not advance the sequence
+        verifier.verify(&signed).unwrap();
     }
 }
@@ -0,0 +1,361 @@
+//! In-memory test harness
+//!
+//! This module provides an in-memory [`Transport`](crate::Transport) created by [`duplex()`], and
+//! scripted fake peers to test either side of the protocol against: a [`FakeBuilder`] to test
+//! the server, and a [`FakeServer`] to test the builder. The fake peers speak the protocol one
+//! step at a time, so that tests can deviate from it at any point.
+
+use crate::*;
+use futures::{channel::mpsc, Sink, SinkExt, Stream, StreamExt};
+use rand_core::OsRng;
+use ssh_key::{Algorithm, HashAlg, PrivateKey, PublicKey};
+use std::{
+    collections::BTreeSet,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Number of frames that can be in flight in each direction of a [`MemoryTransport`].
+const CAPACITY: usize = 16;
+
+/// Generate a random private key.
+pub fn random_key() -> PrivateKey {
+    PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap()
+}
+
+/// Create a connected pair of in-memory transports.
+///
+/// Frames sent on one transport are received on the other. Closing or dropping one transport
+/// ends the stream of the other one, like closing a `WebSocket` would.
+pub fn duplex() -> (MemoryTransport, MemoryTransport) {
+    let (left_sender, left_receiver) = mpsc::channel(CAPACITY);
+    let (right_sender, right_receiver) = mpsc::channel(CAPACITY);
+    let left = MemoryTransport {
+        sender: left_sender,
+        receiver: right_receiver,
+    };
+    let right = MemoryTransport {
+        sender: right_sender,
+        receiver: left_receiver,
+    };
+    (left, right)
+}
+
+/// One end of an in-memory transport, created by [`duplex()`].
+#[derive(Debug)]
+pub struct MemoryTransport {
+    sender: mpsc::Sender<Frame>,
+    receiver: mpsc::Receiver<Frame>,
+}
+
+impl MemoryTransport {
+    /// Box this transport.
+    pub fn boxed(self) -> BoxTransport {
+        Box::new(self)
+    }
+}
+
+impl Stream for MemoryTransport {
+    type Item = Result<Frame, TransportError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_next_unpin(cx).map(|frame| frame.map(Ok))
+    }
+}
+
+impl Sink<Frame> for MemoryTransport {
+    type Error = TransportError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.sender.poll_ready(cx).map_err(TransportError::new)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, frame: Frame) -> Result<(), Self::Error> {
+        self.sender.start_send(frame).map_err(TransportError::new)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.sender
+            .poll_flush_unpin(cx)
+            .map_err(TransportError::new)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.sender
+            .poll_close_unpin(cx)
+            .map_err(TransportError::new)
+    }
+}
+
+/// Error in a fake peer.
+#[derive(thiserror::Error, Debug)]
+pub enum HarnessError {
+    /// Error in transport
+    #[error(transparent)]
+    Transport(#[from] TransportError),
+    /// Error encoding or decoding message
+    #[error(transparent)]
+    Codec(#[from] CodecError),
+    /// Error signing or verifying message
+    #[error(transparent)]
+    Signature(#[from] SignatureError),
+    /// Peer has closed the connection
+    #[error("connection closed by peer")]
+    Closed,
+    /// Builder was rejected
+    #[error("rejected: {0}")]
+    Rejected(Rejection),
+    /// Peer has sent a message other than the expected one
+    #[error("unexpected message: {0}")]
+    Unexpected(String),
+}
+
+/// Receive the next frame from a transport.
+async fn recv_frame(transport: &mut MemoryTransport) -> Result<Frame, HarnessError> {
+    Ok(transport.next().await.ok_or(HarnessError::Closed)??)
+}
+
+/// Fake builder, to test the server side of the protocol.
+#[derive(Debug)]
+pub struct FakeBuilder {
+    transport: MemoryTransport,
+    signer: MessageSigner,
+    codec: Codec,
+}
+
+impl FakeBuilder {
+    /// Create new fake builder which authenticates with the given key.
+    pub fn new(transport: MemoryTransport, key: PrivateKey) -> Self {
+        Self {
+            transport,
+            signer: MessageSigner::new(key),
+            codec: Codec::default(),
+        }
+    }
+
+    /// Private key of this builder.
+    pub fn key(&self) -> &PrivateKey {
+        self.signer.key()
+    }
+
+    /// Send a raw frame.
+    pub async fn send_frame(&mut self, frame: Frame) -> Result<(), HarnessError> {
+        self.transport.send(frame).await?;
+        Ok(())
+    }
+
+    /// Sign and send a message.
+    pub async fn send(&mut self, message: ClientMessage) -> Result<(), HarnessError> {
+        let signed = self.signer.sign(&message)?;
+        let frame = self.codec.encode_frame(&signed)?;
+        self.send_frame(frame).await
+    }
+
+    /// Receive a message.
+    pub async fn recv(&mut self) -> Result<ServerMessage, HarnessError> {
+        let frame = recv_frame(&mut self.transport).await?;
+        Ok(self.codec.decode_frame(&frame)?)
+    }
+
+    /// Send a hello with the given features, returning the negotiated version and features.
+    pub async fn hello(&mut self, features: BTreeSet<Feature>) -> Result<Welcome, HarnessError> {
+        let fingerprint = self.key().public_key().fingerprint(HashAlg::Sha512);
+        self.send(ClientMessage::Hello(Hello::new(fingerprint, features)))
+            .await?;
+        match self.recv().await? {
+            ServerMessage::Welcome(welcome) => {
+                if welcome.features.contains(&Feature::Cbor) {
+                    self.codec = Codec::Cbor;
+                    self.signer.set_codec(Codec::Cbor);
+                }
+                Ok(welcome)
+            }
+            ServerMessage::Rejected(rejection) => Err(HarnessError::Rejected(rejection)),
+            other => Err(HarnessError::Unexpected(format!("{other:?}"))),
+        }
+    }
+
+    /// Wait for the challenge and respond to it, returning the challenge.
+    pub async fn respond(&mut self) -> Result<Challenge, HarnessError> {
+        let challenge = match self.recv().await? {
+            ServerMessage::ChallengeRequest(challenge) => challenge,
+            other => return Err(HarnessError::Unexpected(format!("{other:?}"))),
+        };
+        let response = challenge.respond(self.key())?;
+        self.send(ClientMessage::ChallengeResponse(response))
+            .await?;
+        Ok(challenge)
+    }
+
+    /// Perform the handshake, returning the negotiated version and features.
+    pub async fn authenticate(
+        &mut self,
+        features: BTreeSet<Feature>,
+    ) -> Result<Welcome, HarnessError> {
+        let welcome = self.hello(features).await?;
+        self.respond().await?;
+        Ok(welcome)
+    }
+
+    /// Close the connection.
+    pub async fn close(&mut self) -> Result<(), HarnessError> {
+        self.transport.close().await?;
+        Ok(())
+    }
+}
+
+/// Fake server, to test the builder side of the protocol.
+#[derive(Debug)]
+pub struct FakeServer {
+    transport: MemoryTransport,
+    key: PublicKey,
+    verifier: MessageVerifier,
+    server: String,
+    codec: Codec,
+}
+
+impl FakeServer {
+    /// Create new fake server with the given identity, which expects messages signed by `key`.
+    pub fn new(transport: MemoryTransport, key: PublicKey, server: impl Into<String>) -> Self {
+        Self {
+            transport,
+            verifier: MessageVerifier::new(key.clone()),
+            key,
+            server: server.into(),
+            codec: Codec::default(),
+        }
+    }
+
+    /// Send a raw frame.
+    pub async fn send_frame(&mut self, frame: Frame) -> Result<(), HarnessError> {
+        self.transport.send(frame).await?;
+        Ok(())
+    }
+
+    /// Send a message.
+    pub async fn send(&mut self, message: ServerMessage) -> Result<(), HarnessError> {
+        let frame = self.codec.encode_frame(&message)?;
+        self.send_frame(frame).await
+    }
+
+    /// Receive and verify a message.
+    pub async fn recv(&mut self) -> Result<ClientMessage, HarnessError> {
+        let frame = recv_frame(&mut self.transport).await?;
+        let signed: SignedMessage<ClientMessage> = self.codec.decode_frame(&frame)?;
+        Ok(self.verifier.verify(&signed)?)
+    }
+
+    /// Wait for the hello and reply with the version and features negotiated against `features`.
+    pub async fn welcome(&mut self, features: &BTreeSet<Feature>) -> Result<Hello, HarnessError> {
+        let hello = match self.recv().await? {
+            ClientMessage::Hello(hello) => hello,
+            other => return Err(HarnessError::Unexpected(format!("{other:?}"))),
+        };
+        match hello.negotiate(features) {
+            Ok(welcome) => {
+                let cbor = welcome.features.contains(&Feature::Cbor);
+                self.send(ServerMessage::Welcome(welcome)).await?;
+                if cbor {
+                    self.codec = Codec::Cbor;
+                }
+            }
+            Err(rejection) => self.send(ServerMessage::Rejected(rejection)).await?,
+        }
+        Ok(hello)
+    }
+
+    /// Challenge the builder and verify its response.
+    pub async fn challenge(&mut self) -> Result<(), HarnessError> {
+        let challenge = Challenge::new(self.server.clone());
+        self.send(ServerMessage::ChallengeRequest(challenge.clone()))
+            .await?;
+        match self.recv().await? {
+            ClientMessage::ChallengeResponse(response) => {
+                Ok(challenge.verify(&self.key, &response)?)
+            }
+            other => Err(HarnessError::Unexpected(format!("{other:?}"))),
+        }
+    }
+
+    /// Perform the handshake, returning the hello of the builder.
+    pub async fn accept(&mut self, features: &BTreeSet<Feature>) -> Result<Hello, HarnessError> {
+        let hello = self.welcome(features).await?;
+        self.challenge().await?;
+        Ok(hello)
+    }
+
+    /// Close the connection.
+    pub async fn close(&mut self) -> Result<(), HarnessError> {
+        self.transport.close().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn features() -> BTreeSet<Feature> {
+        [Feature::JobEvents, Feature::Cbor].into()
+    }
+
+    #[tokio::test]
+    async fn duplex_closes() {
+        let (mut left, mut right) = duplex();
+        left.send(Frame::Text("hello".into())).await.unwrap();
+        left.close().await.unwrap();
+        assert_eq!(
+            right.next().await.unwrap().unwrap(),
+            Frame::Text("hello".into())
+        );
+        assert!(right.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn fake_peers_handshake() {
+        let (left, right) = duplex();
+        let mut builder = FakeBuilder::new(left, random_key());
+        let mut server = FakeServer::new(right, builder.key().public_key().clone(), "builds.rs");
+
+        let features = features();
+        let (welcome, hello) = tokio::join!(
+            builder.authenticate(features.clone()),
+            server.accept(&features)
+        );
+        assert_eq!(welcome.unwrap().features, features);
+        assert_eq!(hello.unwrap().features, features);
+
+        // messages after the handshake are encoded as CBOR
+        builder
+            .send(ClientMessage::JobRequest(JobRequest::default()))
+            .await
+            .unwrap();
+        assert!(matches!(
+            server.recv().await.unwrap(),
+            ClientMessage::JobRequest(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn fake_server_rejects_wrong_key() {
+        let (left, right) = duplex();
+        let mut builder = FakeBuilder::new(left, random_key());
+        let mut server = FakeServer::new(right, random_key().public_key().clone(), "builds.rs");
+        builder
+            .send(ClientMessage::JobRequest(JobRequest::default()))
+            .await
+            .unwrap();
+        assert!(matches!(
+            server.recv().await,
+            Err(HarnessError::Signature(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn fake_builder_closed() {
+        let (left, right) = duplex();
+        let mut builder = FakeBuilder::new(left, random_key());
+        drop(right);
+        assert!(matches!(builder.recv().await, Err(HarnessError::Closed)));
+    }
+}
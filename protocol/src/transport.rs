@@ -0,0 +1,40 @@
+//! Transport of frames between builder and server
+//!
+//! The protocol is spoken over a [`Transport`], which is a bidirectional stream of [`Frame`]s.
+//! In production, this is a `WebSocket` connection, but it can be any stream and sink of frames,
+//! such as an in-memory channel for testing.
+
+use crate::codec::Frame;
+use futures::{Sink, Stream};
+use std::error::Error;
+
+/// Error in the underlying transport.
+#[derive(thiserror::Error, Debug)]
+#[error(transparent)]
+pub struct TransportError(Box<dyn Error + Send + Sync>);
+
+impl TransportError {
+    /// Create new transport error from the underlying error.
+    pub fn new<E: Into<Box<dyn Error + Send + Sync>>>(error: E) -> Self {
+        Self(error.into())
+    }
+}
+
+/// Bidirectional stream of frames.
+///
+/// The stream ends when the connection is closed by the peer.
+pub trait Transport:
+    Stream<Item = Result<Frame, TransportError>> + Sink<Frame, Error = TransportError> + Send + Unpin
+{
+}
+
+impl<T> Transport for T where
+    T: Stream<Item = Result<Frame, TransportError>>
+        + Sink<Frame, Error = TransportError>
+        + Send
+        + Unpin
+{
+}
+
+/// Boxed [`Transport`].
+pub type BoxTransport = Box<dyn Transport>;
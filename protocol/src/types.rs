@@ -1,19 +1,57 @@
 //! Common types
 
+use crate::SignatureError;
+use bytes::Bytes;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, BTreeSet};
+use ssh_key::{HashAlg, PrivateKey, PublicKey, SshSig};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
 use url::Url;
 use uuid::Uuid;
 
+/// Interval at which builders send a [`JobHeartbeat`] for every running job.
+///
+/// The server expires the lease of jobs that it has not heard from for several intervals.
+pub const JOB_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Maximum size of the data in a single [`ArtifactChunk`], in bytes.
+pub const ARTIFACT_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Signature namespace for artifacts
+const NAMESPACE_ARTIFACT: &str = "artifact@builds.rs";
+
 /// Request a job from server.
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default)]
 pub struct JobRequest {
-    /// Target for this job.
-    pub target: String,
+    /// Target triple for this job.
+    ///
+    /// If unset, any of the triples advertised in the builder [`Capabilities`] is chosen.
+    #[serde(default)]
+    pub target: Option<String>,
+}
+
+/// Capabilities of a builder, advertised to the server after authentication.
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Clone, Debug, Default)]
+pub struct Capabilities {
+    /// Target triples this builder can build for.
+    pub triples: BTreeSet<String>,
+    /// Rust toolchains installed on this builder.
+    pub toolchains: BTreeSet<String>,
+    /// Number of CPUs.
+    pub cpus: u32,
+    /// Total memory, in bytes.
+    pub memory: u64,
+    /// Free disk space, in bytes.
+    pub disk: u64,
+    /// Kind of strategy used to build, for example `docker`.
+    pub strategy: String,
 }
 
 /// Job information.
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub struct Job {
     /// UUID of job.
     pub uuid: Uuid,
@@ -25,11 +63,24 @@ pub struct Job {
     pub source: Url,
     /// Kind of job
     pub kind: JobKind,
+    /// Short-lived token authorizing uploads of artifacts of this job over HTTP.
+    pub token: String,
 }
 
 /// Named variants
 #[derive(
-    Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug, Default,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Clone,
+    Copy,
+    Debug,
+    Default,
 )]
 #[serde(rename_all = "snake_case")]
 pub enum NamedVariant {
@@ -39,7 +90,9 @@ pub enum NamedVariant {
 }
 
 /// Variant of build.
-#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Debug)]
+#[derive(
+    Serialize, Deserialize, JsonSchema, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Debug,
+)]
 #[serde(untagged)]
 pub enum Variant {
     /// Named variants
@@ -57,7 +110,7 @@ impl Default for Variant {
 /// Build environment
 ///
 /// This struct contains all inputs needed for the build.
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub struct BuildEnv {
     /// Name of this variant
     pub variant: Variant,
@@ -76,7 +129,7 @@ pub struct BuildEnv {
 /// Kind of job to build.
 ///
 /// Each variant of this enumeration corresponds to one Cargo command.
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub enum JobKind {
     /// Build metadata
     Metadata,
@@ -88,6 +141,165 @@ pub enum JobKind {
     Coverage(BuildEnv),
 }
 
+/// Stage of a running job.
+///
+/// These correspond to the stages that are recorded for every job in the database.
+#[derive(
+    Serialize, Deserialize, JsonSchema, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStage {
+    /// Job is being initialized
+    Init,
+    /// Crate source is being fetched
+    Fetch,
+    /// Crate is being built
+    Build,
+    /// Artifacts are being uploaded
+    Upload,
+}
+
+impl JobStage {
+    /// Name of this stage, as it is stored in the database.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Init => "init",
+            Self::Fetch => "fetch",
+            Self::Build => "build",
+            Self::Upload => "upload",
+        }
+    }
+}
+
+/// Stage transition of a job.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug)]
+pub struct JobStageUpdate {
+    /// UUID of job.
+    pub job: Uuid,
+    /// Stage that the job has entered.
+    pub stage: JobStage,
+}
+
+/// Log output of a job.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct JobLog {
+    /// UUID of job.
+    pub job: Uuid,
+    /// Lines of log output, in order.
+    pub lines: Vec<String>,
+}
+
+/// Artifact produced by a job.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct JobArtifact {
+    /// Name of artifact
+    pub name: String,
+    /// SHA-256 hash of the artifact, hex-encoded.
+    pub hash: String,
+    /// Size of the artifact, in bytes.
+    pub size: u64,
+    /// Signature of the artifact created with [`JobArtifact::sign`], PEM-encoded.
+    ///
+    /// Artifacts declared in a [`JobComplete`] must be signed, the backend records the signature
+    /// alongside the artifact.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+impl JobArtifact {
+    /// Data that is signed for this artifact of a job.
+    fn signed_data(&self, job: Uuid) -> Vec<u8> {
+        let mut data = Vec::with_capacity(40 + self.name.len() + self.hash.len());
+        data.extend_from_slice(job.as_bytes());
+        data.extend_from_slice(&(self.name.len() as u64).to_be_bytes());
+        data.extend_from_slice(self.name.as_bytes());
+        data.extend_from_slice(&(self.hash.len() as u64).to_be_bytes());
+        data.extend_from_slice(self.hash.as_bytes());
+        data.extend_from_slice(&self.size.to_be_bytes());
+        data
+    }
+
+    /// Sign this artifact of a job with the private key of the builder, returning the PEM-encoded
+    /// signature.
+    pub fn sign(&self, job: Uuid, key: &PrivateKey) -> Result<String, SignatureError> {
+        let sig = key.sign(NAMESPACE_ARTIFACT, HashAlg::Sha512, &self.signed_data(job))?;
+        Ok(sig.to_pem(Default::default())?)
+    }
+
+    /// Verify that this artifact of a job was signed by the supplied public key.
+    pub fn verify(
+        &self,
+        job: Uuid,
+        key: &PublicKey,
+        signature: &str,
+    ) -> Result<(), SignatureError> {
+        let signature = SshSig::from_pem(signature)?;
+        key.verify(NAMESPACE_ARTIFACT, &self.signed_data(job), &signature)?;
+        Ok(())
+    }
+}
+
+/// Chunk of an artifact upload.
+///
+/// Artifacts are uploaded in chunks, each of which is acknowledged by the server with an
+/// [`ArtifactAck`]. Chunks carry their offset, which allows resuming interrupted uploads.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct ArtifactChunk {
+    /// UUID of job.
+    pub job: Uuid,
+    /// Name of artifact
+    pub artifact: String,
+    /// Offset of this chunk within the artifact, in bytes.
+    pub offset: u64,
+    /// Data of this chunk.
+    pub data: Bytes,
+}
+
+/// Acknowledgement of an artifact upload.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct ArtifactAck {
+    /// UUID of job.
+    pub job: Uuid,
+    /// Name of artifact
+    pub artifact: String,
+    /// Offset up to which the artifact has been received, uploads continue from here.
+    pub offset: u64,
+}
+
+/// Successful completion of a job.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct JobComplete {
+    /// UUID of job.
+    pub job: Uuid,
+    /// Artifacts that were produced.
+    pub artifacts: Vec<JobArtifact>,
+}
+
+/// Heartbeat of a running job, which extends its lease.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug)]
+pub struct JobHeartbeat {
+    /// UUID of job.
+    pub job: Uuid,
+}
+
+/// Cancellation of a job by the server.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct JobCancel {
+    /// UUID of job.
+    pub job: Uuid,
+    /// Reason the job was cancelled.
+    pub reason: String,
+}
+
+/// Failure of a job.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct JobFailed {
+    /// UUID of job.
+    pub job: Uuid,
+    /// Reason the job failed.
+    pub reason: String,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -113,4 +325,55 @@ mod tests {
     fn variant_serialize_custom() {
         assert_tokens(&Variant::Custom("custom".into()), &[Token::Str("custom")]);
     }
+
+    #[test]
+    fn job_artifact_signature() {
+        let key = PrivateKey::random(&mut rand_core::OsRng, ssh_key::Algorithm::Ed25519).unwrap();
+        let job = Uuid::from_u128(1);
+        let artifact = JobArtifact {
+            name: "metadata".into(),
+            hash: "abcdef".into(),
+            size: 1024,
+            signature: None,
+        };
+        let signature = artifact.sign(job, &key).unwrap();
+        artifact.verify(job, key.public_key(), &signature).unwrap();
+
+        // signature is bound to the job and artifact
+        assert!(artifact
+            .verify(Uuid::from_u128(2), key.public_key(), &signature)
+            .is_err());
+        let tampered = JobArtifact {
+            size: 1025,
+            ..artifact
+        };
+        assert!(tampered.verify(job, key.public_key(), &signature).is_err());
+    }
+
+    #[test]
+    fn job_request_legacy_target() {
+        let request: JobRequest = serde_json::from_str(r#"{"target":"generic"}"#).unwrap();
+        assert_eq!(request.target.as_deref(), Some("generic"));
+        let request: JobRequest = serde_json::from_str("{}").unwrap();
+        assert_eq!(request.target, None);
+    }
+
+    #[test]
+    fn job_stage_serialize() {
+        for (stage, name) in [
+            (JobStage::Init, "init"),
+            (JobStage::Fetch, "fetch"),
+            (JobStage::Build, "build"),
+            (JobStage::Upload, "upload"),
+        ] {
+            assert_eq!(stage.name(), name);
+            assert_tokens(
+                &stage,
+                &[Token::UnitVariant {
+                    name: "JobStage",
+                    variant: name,
+                }],
+            );
+        }
+    }
 }
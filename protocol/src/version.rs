@@ -0,0 +1,336 @@
+//! Protocol version and feature negotiation
+//!
+//! When connecting, the builder announces which protocol version it speaks and which optional
+//! features it supports in its [`Hello`]. The server replies with a [`Welcome`] stating the
+//! negotiated version and features, or with a [`Rejection`] if it cannot serve the builder.
+//!
+//! Builders speaking version 1 of the protocol send only their fingerprint in the [`Hello`]
+//! message, wrapped in a [`LegacyMessage`]. This version is no longer supported, such builders
+//! are rejected.
+
+use crate::ClientMessage;
+use schemars::JsonSchema;
+use serde::{de::IgnoredAny, Deserialize, Deserializer, Serialize};
+use ssh_key::Fingerprint;
+use std::collections::BTreeSet;
+
+/// Current version of the protocol.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Oldest version of the protocol that is still supported.
+pub const PROTOCOL_VERSION_MIN: u32 = 2;
+
+/// Version of the protocol spoken by builders which do not announce a version.
+pub const PROTOCOL_VERSION_LEGACY: u32 = 1;
+
+/// Optional feature of the protocol.
+#[derive(
+    Serialize, Deserialize, JsonSchema, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// Builder reports job stages, logs and results.
+    JobEvents,
+    /// Builder advertises its capabilities.
+    Capabilities,
+    /// Builder sends heartbeats for running jobs and aborts them when cancelled.
+    JobLease,
+    /// Builder uploads artifacts in chunks.
+    ArtifactUpload,
+    /// Messages after the handshake are encoded as CBOR in binary frames.
+    Cbor,
+}
+
+/// Initial message sent by the builder.
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Clone, Debug)]
+#[serde(from = "HelloEncoding")]
+pub struct Hello {
+    /// Fingerprint of the key the builder authenticates with.
+    #[schemars(with = "String")]
+    pub fingerprint: Fingerprint,
+    /// Protocol version the builder speaks.
+    pub version: u32,
+    /// Optional features the builder supports.
+    #[schemars(default)]
+    pub features: BTreeSet<Feature>,
+}
+
+/// Envelope of messages sent by builders speaking version 1 of the protocol.
+///
+/// The message itself was signed rather than an encoded payload. This is only decoded to recognize
+/// such builders, so that they can be rejected.
+#[derive(Deserialize, Clone, Debug)]
+pub struct LegacyMessage {
+    /// Message
+    pub message: ClientMessage,
+    /// Signature, created from SSH key.
+    pub signature: String,
+}
+
+/// Encodings of [`Hello`] that are accepted.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum HelloEncoding {
+    /// Legacy builders only send their fingerprint, this is only parsed to reject them.
+    Legacy(Fingerprint),
+    /// Current encoding.
+    Current {
+        fingerprint: Fingerprint,
+        version: u32,
+        #[serde(default, deserialize_with = "known_features")]
+        features: BTreeSet<Feature>,
+    },
+}
+
+impl From<HelloEncoding> for Hello {
+    fn from(encoding: HelloEncoding) -> Self {
+        match encoding {
+            HelloEncoding::Legacy(fingerprint) => Hello {
+                fingerprint,
+                version: PROTOCOL_VERSION_LEGACY,
+                features: BTreeSet::new(),
+            },
+            HelloEncoding::Current {
+                fingerprint,
+                version,
+                features,
+            } => Hello {
+                fingerprint,
+                version,
+                features,
+            },
+        }
+    }
+}
+
+/// Deserialize a set of features, ignoring any features that are not known.
+///
+/// Builders which are newer than the server may announce features it does not know about.
+fn known_features<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeSet<Feature>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum MaybeFeature {
+        Known(Feature),
+        Unknown(IgnoredAny),
+    }
+
+    let features: Vec<MaybeFeature> = Deserialize::deserialize(deserializer)?;
+    Ok(features
+        .into_iter()
+        .filter_map(|feature| match feature {
+            MaybeFeature::Known(feature) => Some(feature),
+            MaybeFeature::Unknown(_) => None,
+        })
+        .collect())
+}
+
+/// Negotiated protocol version and features, sent by the server in response to [`Hello`].
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Clone, Debug)]
+pub struct Welcome {
+    /// Protocol version used for this connection.
+    pub version: u32,
+    /// Optional features enabled for this connection.
+    pub features: BTreeSet<Feature>,
+}
+
+/// Reason the server rejected a builder.
+#[derive(
+    thiserror::Error, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Clone, Copy, Debug,
+)]
+pub enum Rejection {
+    /// Protocol version of the builder is not supported.
+    #[error("unsupported protocol version {version}, supported are {min} to {max}")]
+    UnsupportedVersion {
+        /// Version the builder speaks
+        version: u32,
+        /// Oldest version the server supports
+        min: u32,
+        /// Newest version the server supports
+        max: u32,
+    },
+    /// Builder is disabled.
+    #[error("builder is disabled")]
+    BuilderDisabled,
+}
+
+impl Hello {
+    /// Create a hello message for the current protocol version.
+    pub fn new(fingerprint: Fingerprint, features: BTreeSet<Feature>) -> Self {
+        Self {
+            fingerprint,
+            version: PROTOCOL_VERSION,
+            features,
+        }
+    }
+
+    /// Negotiate protocol version and features with the ones supported by the server.
+    ///
+    /// Builders that are newer than the server are served with the current version, builders
+    /// older than the oldest supported version are rejected.
+    pub fn negotiate(&self, features: &BTreeSet<Feature>) -> Result<Welcome, Rejection> {
+        if self.version < PROTOCOL_VERSION_MIN {
+            return Err(Rejection::UnsupportedVersion {
+                version: self.version,
+                min: PROTOCOL_VERSION_MIN,
+                max: PROTOCOL_VERSION,
+            });
+        }
+
+        Ok(Welcome {
+            version: self.version.min(PROTOCOL_VERSION),
+            features: self.features.intersection(features).copied().collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Codec, Frame, SignedMessage};
+    use rand_core::OsRng;
+    use serde_json::json;
+    use ssh_key::{Algorithm, HashAlg, PrivateKey};
+
+    const FINGERPRINT: &str = "SHA256:uzS2BDaMqlBp6Y1+gvRpC7cJcXgsu6jZxd4YDXVEbq8";
+
+    fn fingerprint() -> Fingerprint {
+        FINGERPRINT.parse().unwrap()
+    }
+
+    fn all_features() -> BTreeSet<Feature> {
+        [
+            Feature::JobEvents,
+            Feature::Capabilities,
+            Feature::JobLease,
+            Feature::ArtifactUpload,
+        ]
+        .into()
+    }
+
+    #[test]
+    fn fingerprint_parses() {
+        assert_eq!(fingerprint().algorithm(), HashAlg::Sha256);
+    }
+
+    /// Encodings of hello messages sent by builders of different versions, along with the
+    /// negotiation outcome for each.
+    #[test]
+    fn hello_compatibility_matrix() {
+        let matrix = [
+            // version 1 builders only send their fingerprint, they are no longer supported
+            (
+                json!({"Hello": FINGERPRINT}),
+                Hello {
+                    fingerprint: fingerprint(),
+                    version: 1,
+                    features: BTreeSet::new(),
+                },
+                Err(Rejection::UnsupportedVersion {
+                    version: 1,
+                    min: PROTOCOL_VERSION_MIN,
+                    max: PROTOCOL_VERSION,
+                }),
+            ),
+            // version 2 builders without features
+            (
+                json!({"Hello": {"fingerprint": FINGERPRINT, "version": 2}}),
+                Hello {
+                    fingerprint: fingerprint(),
+                    version: 2,
+                    features: BTreeSet::new(),
+                },
+                Ok(Welcome {
+                    version: 2,
+                    features: BTreeSet::new(),
+                }),
+            ),
+            // version 2 builders with features
+            (
+                json!({"Hello": {"fingerprint": FINGERPRINT, "version": 2, "features": ["job_events", "capabilities", "job_lease", "artifact_upload"]}}),
+                Hello {
+                    fingerprint: fingerprint(),
+                    version: 2,
+                    features: all_features(),
+                },
+                Ok(Welcome {
+                    version: 2,
+                    features: all_features(),
+                }),
+            ),
+            // newer builders with unknown features
+            (
+                json!({"Hello": {"fingerprint": FINGERPRINT, "version": 7, "features": ["job_events", "capabilities", "job_lease", "artifact_upload", "teleport"]}}),
+                Hello {
+                    fingerprint: fingerprint(),
+                    version: 7,
+                    features: all_features(),
+                },
+                Ok(Welcome {
+                    version: PROTOCOL_VERSION,
+                    features: all_features(),
+                }),
+            ),
+            // builders older than what is supported
+            (
+                json!({"Hello": {"fingerprint": FINGERPRINT, "version": 0}}),
+                Hello {
+                    fingerprint: fingerprint(),
+                    version: 0,
+                    features: BTreeSet::new(),
+                },
+                Err(Rejection::UnsupportedVersion {
+                    version: 0,
+                    min: PROTOCOL_VERSION_MIN,
+                    max: PROTOCOL_VERSION,
+                }),
+            ),
+        ];
+
+        for (encoded, expected, negotiated) in matrix {
+            let message: ClientMessage = serde_json::from_value(encoded).unwrap();
+            let ClientMessage::Hello(hello) = message else {
+                panic!("expected hello message");
+            };
+            assert_eq!(hello, expected);
+            assert_eq!(hello.negotiate(&all_features()), negotiated);
+        }
+    }
+
+    #[test]
+    fn legacy_envelope() {
+        let frame = Frame::Text(
+            json!({"message": {"Hello": FINGERPRINT}, "signature": "-----BEGIN SSH SIGNATURE-----"})
+                .to_string(),
+        );
+        let legacy: LegacyMessage = Codec::Json.decode_frame(&frame).unwrap();
+        assert!(matches!(legacy.message, ClientMessage::Hello(hello) if hello.version == 1));
+
+        // the current envelope is not mistaken for the legacy one
+        let key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        let hello = ClientMessage::Hello(Hello::new(fingerprint(), all_features()));
+        let signed = SignedMessage::new(&key, Codec::Json, 0, &hello).unwrap();
+        let frame = Codec::Json.encode_frame(&signed).unwrap();
+        assert!(Codec::Json.decode_frame::<LegacyMessage>(&frame).is_err());
+    }
+
+    #[test]
+    fn hello_roundtrip() {
+        let hello = Hello::new(fingerprint(), all_features());
+        let encoded = serde_json::to_value(ClientMessage::Hello(hello.clone())).unwrap();
+        assert_eq!(
+            encoded,
+            json!({"Hello": {"fingerprint": FINGERPRINT, "version": PROTOCOL_VERSION, "features": ["job_events", "capabilities", "job_lease", "artifact_upload"]}})
+        );
+        let decoded: ClientMessage = serde_json::from_value(encoded).unwrap();
+        assert!(matches!(decoded, ClientMessage::Hello(decoded) if decoded == hello));
+    }
+
+    #[test]
+    fn negotiate_features_intersection() {
+        let hello = Hello::new(fingerprint(), all_features());
+        let welcome = hello.negotiate(&BTreeSet::new()).unwrap();
+        assert!(welcome.features.is_empty());
+    }
+}
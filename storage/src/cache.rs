@@ -6,7 +6,10 @@
 use super::*;
 use moka::{future::Cache as MokaCache, Expiry};
 use std::{
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
     time::{Duration, Instant},
 };
 
@@ -90,6 +93,13 @@ impl Expiry<ArtifactId, Entry> for CacheConfig {
     }
 }
 
+/// Counters of cache lookups.
+#[derive(Debug, Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
 /// Storage caching layer.
 ///
 /// This is a layer you can use to wrap an existing storage provider to add an in-memory cache.
@@ -103,6 +113,8 @@ pub struct Cache {
     storage: AnyStorage,
     /// Cache used for artifact sources
     cache: MokaCache<ArtifactId, Entry>,
+    /// Counters of lookups, shared between clones
+    counters: Arc<Counters>,
 }
 
 impl Cache {
@@ -122,7 +134,11 @@ impl Cache {
             .expire_after(config)
             .build();
 
-        Self { storage, cache }
+        Self {
+            storage,
+            cache,
+            counters: Default::default(),
+        }
     }
 
     /// Get a reference to the underlying storage.
@@ -130,6 +146,14 @@ impl Cache {
         &self.storage
     }
 
+    /// Statistics of lookups made through this cache.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+        }
+    }
+
     /// Clear the cache.
     ///
     /// This will invalidate all cache entries.
@@ -140,6 +164,14 @@ impl Cache {
 
 #[async_trait::async_trait]
 impl Storage for Cache {
+    fn kind(&self) -> &'static str {
+        self.storage.kind()
+    }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        Some(self.stats())
+    }
+
     async fn artifact_put(&self, version: &ArtifactId, data: &[u8]) -> Result<(), StorageError> {
         // we cannot cache mutable operations.
         self.storage().artifact_put(version, data).await
@@ -147,11 +179,13 @@ impl Storage for Cache {
 
     async fn artifact_get(&self, version: &ArtifactId) -> Result<ArtifactData, StorageError> {
         let storage = self.storage.clone();
+        let missed = AtomicBool::new(false);
         // using try_get_with here ensures that if we concurrently request the same artifact version
         // twice, only one lookup will be made.
         let result = self
             .cache
-            .try_get_with(version.clone(), async move {
+            .try_get_with(version.clone(), async {
+                missed.store(true, Ordering::Relaxed);
                 match storage.artifact_get(version).await {
                     Ok(bytes) => Ok(Entry::Data(bytes)),
                     Err(StorageError::NotFound(error)) => {
@@ -164,6 +198,13 @@ impl Storage for Cache {
             })
             .await;
 
+        let counter = if missed.load(Ordering::Relaxed) {
+            &self.counters.misses
+        } else {
+            &self.counters.hits
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
         // depending on what the entry is, we construct the right response.
         match result {
             Ok(Entry::Data(bytes)) => Ok(bytes),
@@ -171,4 +212,23 @@ impl Storage for Cache {
             Err(error) => Err((*error).clone()),
         }
     }
+
+    async fn artifact_get_range(
+        &self,
+        version: &ArtifactId,
+        range: ByteRange,
+    ) -> Result<ArtifactData, StorageError> {
+        // ranges are cut out of cached artifacts, but are not cached themselves
+        let entry = self.cache.get(version).await;
+        let counter = match entry {
+            Some(_) => &self.counters.hits,
+            None => &self.counters.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        match entry {
+            Some(Entry::Data(data)) => range.slice(data),
+            Some(Entry::Missing(error)) => Err(StorageError::NotFound(error)),
+            None => self.storage.artifact_get_range(version, range).await,
+        }
+    }
 }
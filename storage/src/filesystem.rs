@@ -5,10 +5,13 @@
 use super::*;
 use std::{
     fmt::Debug,
-    io::ErrorKind,
+    io::{ErrorKind, SeekFrom},
     path::{Path, PathBuf},
 };
-use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
 
 #[cfg(any(test, feature = "temp"))]
 mod temp;
@@ -89,8 +92,21 @@ impl<P: AsRef<Path>> Filesystem<P> {
     }
 }
 
+/// Translate an error reading an artifact, distinguishing missing artifacts.
+fn read_error(error: FilesystemError) -> StorageError {
+    if error.error.kind() == ErrorKind::NotFound {
+        StorageError::NotFound(Arc::new(error))
+    } else {
+        StorageError::Other(Arc::new(error))
+    }
+}
+
 #[async_trait::async_trait]
 impl<P: AsRef<Path> + Send + Sync + Debug> Storage for Filesystem<P> {
+    fn kind(&self) -> &'static str {
+        "filesystem"
+    }
+
     async fn artifact_put(&self, version: &ArtifactId, data: &[u8]) -> Result<(), StorageError> {
         self.do_artifact_put(version, data)
             .await
@@ -98,13 +114,41 @@ impl<P: AsRef<Path> + Send + Sync + Debug> Storage for Filesystem<P> {
     }
 
     async fn artifact_get(&self, version: &ArtifactId) -> Result<ArtifactData, StorageError> {
-        let result = self.do_artifact_get(version).await;
-        match result {
-            Ok(bytes) => Ok(ArtifactData::Data { bytes }),
-            Err(error) if error.error.kind() == ErrorKind::NotFound => {
-                Err(StorageError::NotFound(Arc::new(error)))
-            }
-            Err(error) => Err(StorageError::Other(Arc::new(error))),
-        }
+        self.do_artifact_get(version)
+            .await
+            .map(|bytes| ArtifactData::Data { bytes })
+            .map_err(read_error)
+    }
+
+    async fn artifact_get_range(
+        &self,
+        version: &ArtifactId,
+        range: ByteRange,
+    ) -> Result<ArtifactData, StorageError> {
+        let path = self.artifact_path(version);
+        let error = |error| {
+            read_error(FilesystemError {
+                path: path.clone(),
+                error,
+            })
+        };
+        let mut file = File::open(&path).await.map_err(error)?;
+        let size = file.metadata().await.map_err(error)?.len();
+        let range = range
+            .resolve(size)
+            .ok_or(StorageError::RangeNotSatisfiable(size))?;
+
+        // only the requested range is read, so that resuming large downloads is cheap
+        file.seek(SeekFrom::Start(*range.start()))
+            .await
+            .map_err(error)?;
+        let length = usize::try_from(range.end() - range.start() + 1).unwrap_or(usize::MAX);
+        let mut bytes = vec![0; length];
+        file.read_exact(&mut bytes).await.map_err(error)?;
+        Ok(ArtifactData::Partial {
+            bytes: bytes.into(),
+            start: *range.start(),
+            size,
+        })
     }
 }
@@ -16,6 +16,9 @@ mod temp;
 #[cfg(any(test, feature = "temp"))]
 pub use temp::*;
 
+mod range;
+pub use range::ByteRange;
+
 #[cfg(feature = "cache")]
 mod cache;
 #[cfg(feature = "filesystem")]
@@ -37,6 +40,10 @@ pub enum StorageError {
     #[error("artifact not found")]
     NotFound(#[source] SharedError),
 
+    /// Requested range lies outside of the artifact, which has the given size
+    #[error("range not satisfiable for artifact of {0} bytes")]
+    RangeNotSatisfiable(u64),
+
     /// Other error
     #[error(transparent)]
     Other(#[from] SharedError),
@@ -102,6 +109,15 @@ pub use options::StorageOptions;
 /// Shared generic storage instance.
 pub type AnyStorage = Arc<dyn Storage>;
 
+/// Statistics of a storage cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of lookups which were answered from the cache.
+    pub hits: u64,
+    /// Number of lookups which had to be forwarded to the underlying storage.
+    pub misses: u64,
+}
+
 /// Storage trait.
 #[async_trait::async_trait]
 pub trait Storage: Send + Sync + Debug {
@@ -110,4 +126,26 @@ pub trait Storage: Send + Sync + Debug {
 
     /// Get an artifact from storage.
     async fn artifact_get(&self, version: &ArtifactId) -> Result<ArtifactData, StorageError>;
+
+    /// Get a range of bytes of an artifact from storage.
+    ///
+    /// By default, this gets the whole artifact and cuts the range out of it. Implementations
+    /// should override this if they can read the range directly.
+    async fn artifact_get_range(
+        &self,
+        version: &ArtifactId,
+        range: ByteRange,
+    ) -> Result<ArtifactData, StorageError> {
+        range.slice(self.artifact_get(version).await?)
+    }
+
+    /// Name of the kind of storage, such as `filesystem` or `s3`.
+    fn kind(&self) -> &'static str {
+        "other"
+    }
+
+    /// Statistics of the cache, if this storage is cached.
+    fn cache_stats(&self) -> Option<CacheStats> {
+        None
+    }
 }
@@ -0,0 +1,69 @@
+//! # Byte Ranges
+//!
+//! Ranges of bytes of an artifact, as requested by clients resuming a download.
+
+use super::*;
+use std::ops::RangeInclusive;
+
+/// Range of bytes of an artifact.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteRange {
+    /// Bytes from `start` up to and including `end`, or up to the end of the artifact.
+    From {
+        /// Offset of the first byte
+        start: u64,
+        /// Offset of the last byte
+        end: Option<u64>,
+    },
+    /// Last bytes of the artifact.
+    Suffix(u64),
+}
+
+impl ByteRange {
+    /// Resolve this range for an artifact of the given size.
+    ///
+    /// Returns `None` if the range is not satisfiable, because it lies outside of the artifact.
+    pub fn resolve(&self, size: u64) -> Option<RangeInclusive<u64>> {
+        let last = size.checked_sub(1)?;
+        match *self {
+            Self::From { start, end } if start <= last => {
+                let end = end.map_or(last, |end| end.min(last));
+                (start <= end).then_some(start..=end)
+            }
+            Self::From { .. } | Self::Suffix(0) => None,
+            Self::Suffix(length) => Some(size.saturating_sub(length)..=last),
+        }
+    }
+
+    /// Cut this range out of artifact data.
+    ///
+    /// Redirects are returned as-is, leaving it to their target to serve the range.
+    pub fn slice(&self, data: ArtifactData) -> Result<ArtifactData, StorageError> {
+        let ArtifactData::Data { bytes } = data else {
+            return Ok(data);
+        };
+        let size = bytes.len() as u64;
+        let range = self
+            .resolve(size)
+            .ok_or(StorageError::RangeNotSatisfiable(size))?;
+        let start = usize::try_from(*range.start()).unwrap_or(usize::MAX);
+        let end = usize::try_from(*range.end()).unwrap_or(usize::MAX);
+        Ok(ArtifactData::Partial {
+            bytes: bytes.slice(start..=end),
+            start: *range.start(),
+            size,
+        })
+    }
+
+    /// Format this range as the value of a `Range` header.
+    pub fn header(&self) -> String {
+        match self {
+            Self::From { start, end: None } => format!("bytes={start}-"),
+            Self::From {
+                start,
+                end: Some(end),
+            } => format!("bytes={start}-{end}"),
+            Self::Suffix(length) => format!("bytes=-{length}"),
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use super::*;
 use aws_sdk_s3::{
     error::SdkError,
-    operation::get_object::GetObjectError,
+    operation::{get_object::GetObjectError, head_object::HeadObjectError},
     primitives::{ByteStream, SdkBody},
     Client,
 };
@@ -43,8 +43,61 @@ impl S3 {
     }
 }
 
+impl S3 {
+    /// Look up the size of an artifact.
+    async fn artifact_size(&self, version: &ArtifactId) -> Result<u64, StorageError> {
+        let response = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(version.file_name())
+            .send()
+            .await;
+        match response {
+            Ok(response) => Ok(
+                u64::try_from(response.content_length().unwrap_or_default()).unwrap_or_default()
+            ),
+            Err(SdkError::ServiceError(error))
+                if matches!(error.err(), HeadObjectError::NotFound(_)) =>
+            {
+                Err(StorageError::NotFound(Arc::new(error.into_err())))
+            }
+            Err(error) => Err(StorageError::Other(Arc::new(error))),
+        }
+    }
+}
+
+/// Translate an error getting an object, distinguishing missing artifacts.
+fn get_error(error: SdkError<GetObjectError>) -> StorageError {
+    if let SdkError::ServiceError(service) = &error {
+        if let GetObjectError::NoSuchKey(error) = service.err() {
+            return StorageError::NotFound(Arc::new(error.clone()));
+        }
+    }
+    StorageError::Other(Arc::new(error))
+}
+
+/// Collect the body of an object.
+async fn collect(body: ByteStream) -> Result<Bytes, StorageError> {
+    body.collect()
+        .await
+        .map(|data| data.into_bytes())
+        .map_err(|error| StorageError::Other(Arc::new(error)))
+}
+
+/// Parse a `Content-Range` header, returning the offset of the range and the size of the object.
+fn parse_content_range(value: &str) -> Option<(u64, u64)> {
+    let (range, size) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, _end) = range.split_once('-')?;
+    Some((start.parse().ok()?, size.parse().ok()?))
+}
+
 #[async_trait::async_trait]
 impl Storage for S3 {
+    fn kind(&self) -> &'static str {
+        "s3"
+    }
+
     async fn artifact_put(&self, version: &ArtifactId, data: &[u8]) -> Result<(), StorageError> {
         self.client
             .put_object()
@@ -66,27 +119,54 @@ impl Storage for S3 {
             .send()
             .await;
 
-        // determine if this is a no such key error and translate into artifact missing
-        if let Err(SdkError::ServiceError(error)) = &response {
-            if let GetObjectError::NoSuchKey(error) = error.err() {
-                return Err(StorageError::NotFound(Arc::new(error.clone())));
+        let bytes = collect(response.map_err(get_error)?.body).await?;
+        Ok(ArtifactData::Data { bytes })
+    }
+
+    async fn artifact_get_range(
+        &self,
+        version: &ArtifactId,
+        range: ByteRange,
+    ) -> Result<ArtifactData, StorageError> {
+        // not all S3-compatible servers support suffix ranges, so they are resolved here
+        let range = match range {
+            ByteRange::Suffix(_) => {
+                let size = self.artifact_size(version).await?;
+                let range = range
+                    .resolve(size)
+                    .ok_or(StorageError::RangeNotSatisfiable(size))?;
+                ByteRange::From {
+                    start: *range.start(),
+                    end: Some(*range.end()),
+                }
             }
-        }
+            range @ ByteRange::From { .. } => range,
+        };
+
+        let response = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(version.file_name())
+            .range(range.header())
+            .send()
+            .await;
 
-        // return other errors as-is
         let response = match response {
             Ok(response) => response,
-            Err(error) => return Err(StorageError::Other(Arc::new(error))),
+            Err(SdkError::ServiceError(error)) if error.raw().status().as_u16() == 416 => {
+                let size = self.artifact_size(version).await?;
+                return Err(StorageError::RangeNotSatisfiable(size));
+            }
+            Err(error) => return Err(get_error(error)),
         };
 
-        // collect response
-        let bytes = response
-            .body
-            .collect()
-            .await
-            .map_err(|error| StorageError::Other(Arc::new(error)))
-            .map(|data| data.into_bytes())?;
-
-        Ok(ArtifactData::Data { bytes })
+        let content_range = response.content_range().and_then(parse_content_range);
+        let bytes = collect(response.body).await?;
+        match content_range {
+            Some((start, size)) => Ok(ArtifactData::Partial { bytes, start, size }),
+            // the whole artifact was returned, so the range is cut out here
+            None => range.slice(ArtifactData::Data { bytes }),
+        }
     }
 }
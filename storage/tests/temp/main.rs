@@ -4,6 +4,7 @@ use test_strategy::proptest;
 
 #[cfg(feature = "filesystem")]
 mod filesystem;
+mod range;
 #[cfg(feature = "s3")]
 mod s3;
 
@@ -66,7 +67,7 @@ async fn temp_instances() -> (Vec<AnyStorage>, Cleanup) {
     create_temp_instances(&mut storage, &mut cleanup, S3::new_temp).await;
 
     let cleanup = Box::pin(async move {
-        for c in cleanup.into_iter() {
+        for c in cleanup {
             c.await;
         }
     });
@@ -101,12 +102,82 @@ async fn can_package_put_many(packages: Vec<(ArtifactId, Vec<u8>)>) {
         println!("Testing {storage:?}");
 
         for (version, bytes) in &packages {
-            storage.artifact_put(&version, &bytes).await.unwrap();
+            storage.artifact_put(version, bytes).await.unwrap();
 
-            let result = storage.artifact_get(&version).await.unwrap();
+            let result = storage.artifact_get(version).await.unwrap();
             assert_eq!(result.bytes().unwrap(), bytes);
         }
     }
 
     cleanup.await;
 }
+
+#[cfg(all(feature = "cache", feature = "filesystem"))]
+#[proptest(async = "tokio", cases = 10)]
+async fn can_count_cache_hits(version: ArtifactId, contents: Vec<u8>) {
+    let storage = Filesystem::new_temp().await;
+    let cache = Cache::new(Arc::new(storage.value), CacheConfig::default());
+    assert_eq!(cache.kind(), "filesystem");
+    assert_eq!(cache.cache_stats(), Some(CacheStats::default()));
+
+    cache.artifact_put(&version, &contents).await.unwrap();
+    cache.artifact_get(&version).await.unwrap();
+    cache.artifact_get(&version).await.unwrap();
+    assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1 });
+
+    storage.cleanup.await;
+}
+
+#[proptest(async = "tokio", cases = 10)]
+async fn can_artifact_get_range(version: ArtifactId, contents: Vec<u8>) {
+    let (instances, cleanup) = temp_instances().await;
+    let size = contents.len() as u64;
+    let ranges = [
+        ByteRange::From {
+            start: 0,
+            end: None,
+        },
+        ByteRange::From {
+            start: size / 2,
+            end: Some(size / 2 + 2),
+        },
+        ByteRange::Suffix(3),
+        ByteRange::From {
+            start: size,
+            end: None,
+        },
+    ];
+
+    for storage in instances {
+        storage.artifact_put(&version, &contents).await.unwrap();
+
+        for range in ranges {
+            let expected = range.slice(ArtifactData::Data {
+                bytes: contents.clone().into(),
+            });
+            let result = storage.artifact_get_range(&version, range).await;
+            match (result, expected) {
+                (
+                    Ok(ArtifactData::Partial { bytes, start, size }),
+                    Ok(ArtifactData::Partial {
+                        bytes: expected_bytes,
+                        start: expected_start,
+                        size: expected_size,
+                    }),
+                ) => {
+                    assert_eq!(bytes, expected_bytes, "{storage:?} {range:?}");
+                    assert_eq!((start, size), (expected_start, expected_size));
+                }
+                (
+                    Err(StorageError::RangeNotSatisfiable(size)),
+                    Err(StorageError::RangeNotSatisfiable(expected)),
+                ) => assert_eq!(size, expected),
+                (result, expected) => {
+                    panic!("{storage:?} {range:?}: got {result:?}, expected {expected:?}")
+                }
+            }
+        }
+    }
+
+    cleanup.await;
+}
@@ -0,0 +1,52 @@
+use super::*;
+use bytes::Bytes;
+
+#[test]
+fn can_resolve() {
+    let range = |start, end| ByteRange::From { start, end };
+    assert_eq!(range(0, None).resolve(10), Some(0..=9));
+    assert_eq!(range(2, Some(5)).resolve(10), Some(2..=5));
+    assert_eq!(range(2, Some(50)).resolve(10), Some(2..=9));
+    assert_eq!(range(9, Some(9)).resolve(10), Some(9..=9));
+    assert_eq!(ByteRange::Suffix(3).resolve(10), Some(7..=9));
+    assert_eq!(ByteRange::Suffix(30).resolve(10), Some(0..=9));
+}
+
+#[test]
+fn cannot_resolve_unsatisfiable() {
+    let range = |start, end| ByteRange::From { start, end };
+    assert_eq!(range(10, None).resolve(10), None);
+    assert_eq!(range(5, Some(2)).resolve(10), None);
+    assert_eq!(range(0, None).resolve(0), None);
+    assert_eq!(ByteRange::Suffix(0).resolve(10), None);
+    assert_eq!(ByteRange::Suffix(5).resolve(0), None);
+}
+
+#[test]
+fn can_slice() {
+    let data = ArtifactData::Data {
+        bytes: Bytes::from_static(b"0123456789"),
+    };
+    let ArtifactData::Partial { bytes, start, size } =
+        ByteRange::Suffix(3).slice(data.clone()).unwrap()
+    else {
+        panic!("expected partial data");
+    };
+    assert_eq!((&bytes[..], start, size), (&b"789"[..], 7, 10));
+
+    let error = ByteRange::From {
+        start: 10,
+        end: None,
+    }
+    .slice(data)
+    .unwrap_err();
+    assert!(matches!(error, StorageError::RangeNotSatisfiable(10)));
+}
+
+#[test]
+fn can_format_header() {
+    let range = |start, end| ByteRange::From { start, end };
+    assert_eq!(range(2, None).header(), "bytes=2-");
+    assert_eq!(range(2, Some(5)).header(), "bytes=2-5");
+    assert_eq!(ByteRange::Suffix(3).header(), "bytes=-3");
+}